@@ -0,0 +1,49 @@
+//! 任意のバイト列を TON の各 decoder に渡す
+//!
+//! どの decoder も panic やスタックの使い切りをせずに Ok か Err を返すことを確かめます
+//! `cargo +nightly fuzz run decode corpus/decode` で実行します
+
+#![no_main]
+
+use idis::ton::{
+    forward_to_reverse, from_slice, from_slice_with_limits, reverse_to_forward, to_vec, ForwardSerializer, Limits, SliceRead, StreamReader,
+    TonView, Value,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // 読めた値は ForwardSerializer でも並べ替えと同じバイト列になる
+    if let Ok(value) = from_slice::<Value>(data) {
+        if let Ok(reverse) = to_vec(&value) {
+            let mut serializer = ForwardSerializer::new(Vec::new());
+            serializer.serialize(&value).expect("serializable value must serialize forward");
+            assert_eq!(serializer.into_inner(), reverse_to_forward(&reverse).expect("serialized value must convert"));
+        }
+    }
+    let _ = from_slice_with_limits::<Value>(data, Limits::untrusted());
+
+    // 並べ替えられた値は元に戻せる
+    if let Ok(forward) = reverse_to_forward(data) {
+        let reverse = forward_to_reverse(&forward).expect("forward output must convert back");
+        assert_eq!(reverse_to_forward(&reverse).expect("reverse output must convert again"), forward);
+    }
+    let _ = forward_to_reverse(data);
+
+    if let Ok(reader) = StreamReader::new(SliceRead::new(data)) {
+        for event in reader {
+            if event.is_err() {
+                break;
+            }
+        }
+    }
+
+    if let Ok(view) = TonView::new(data) {
+        let _ = view.payload();
+        let _ = view.meta();
+        let _ = view.len();
+        let _ = view.get("a");
+        let _ = view.get_index(0);
+        let _ = view.pointer("/a/0");
+        let _ = view.to_value();
+    }
+});
@@ -0,0 +1,108 @@
+//! decode の seed corpus を作る
+//!
+//! ReverseSerializer の各モードで書いたバイト列と、それを壊したものを corpus/decode に書き出します
+//! `cargo run --bin seed_corpus` で作り直します
+
+use std::{fs, path::Path};
+
+use idis::ton::{from_text, ReverseSerializer, Value};
+use serde::Serialize;
+
+/// TON の全ての型を含む値
+const SAMPLES: [&str; 12] = [
+    "null",
+    "true",
+    r#"[0u8, -1i8, 300u16, -40000i32, 18446744073709551615u64, -170141183460469231731687303715884105728i128, 340282366920938463463374607431768211455u128]"#,
+    r#"[1.5f32, -0.0, 1e300, NaN, Infinity, -Infinity, {"$float": 0.5}]"#,
+    r#""テキスト""#,
+    r#"{"$bytes": "0001feff"}"#,
+    r#"{"$uuid": "01234567-89ab-cdef-0123-456789abcdef"}"#,
+    r#"[{"$datetime": "2024-01-02T03:04:05.678Z"}, {"$timestamp": 1700000000i64}, {"$duration": -1500000000i64}, {"$undefined": null}]"#,
+    r#"{"$wrapped_json": "{\"a\": [1, 2]}"}"#,
+    r#"{"$meta": [{"unit": "ms"}, 120u32]}"#,
+    r#"{"id": 1u64, "name": "record", "tags": ["a", "b"], "attrs": {"x": 1i32, "y": [[], {}]}}"#,
+    r#"{"$object": [[1u8, "one"], [-2i16, "two"], [{"$uuid": "01234567-89ab-cdef-0123-456789abcdef"}, null]]}"#,
+];
+
+/// 壊したものを何個作るか
+const MUTATIONS: usize = 4;
+
+/// 再現できるよう seed を固定した xorshift
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// mode: 0 通常, 1 compact, 2 canonical
+fn encode(value: &Value, mode: u8) -> Vec<u8> {
+    let mut serializer = match mode {
+        0 => ReverseSerializer::new(Vec::new()),
+        1 => ReverseSerializer::new(Vec::new()).compact(),
+        _ => ReverseSerializer::new(Vec::new()).canonical(),
+    };
+    value.serialize(&mut serializer).expect("sample must serialize");
+    serializer.into_inner()
+}
+
+/// バイトの書き換え、切り詰め、size フィールドの拡大のどれかで壊す
+fn mutate(rng: &mut Rng, bytes: &[u8]) -> Vec<u8> {
+    let mut bytes = bytes.to_vec();
+    match rng.below(3) {
+        0 => {
+            for _ in 0..1 + rng.below(4) {
+                let i = rng.below(bytes.len());
+                bytes[i] = rng.next() as u8;
+            }
+        }
+        1 => {
+            let cut = rng.below(bytes.len());
+            if rng.below(2) == 0 {
+                bytes.truncate(cut);
+            } else {
+                bytes.drain(..cut);
+            }
+        }
+        _ => {
+            // 末尾の prefix の直前は size フィールド
+            let len = bytes.len();
+            if len >= 2 {
+                bytes[len - 2] = 0xFF;
+            }
+        }
+    }
+    bytes
+}
+
+fn main() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus/decode");
+    fs::create_dir_all(&dir).expect("create corpus dir");
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    for (i, text) in SAMPLES.iter().enumerate() {
+        let value: Value = from_text(text).unwrap_or_else(|e| panic!("sample {i}: {e}"));
+        for mode in 0..3u8 {
+            let bytes = encode(&value, mode);
+            fs::write(dir.join(format!("sample{i:02}_mode{mode}")), &bytes).expect("write seed");
+            for m in 0..MUTATIONS {
+                fs::write(dir.join(format!("sample{i:02}_mode{mode}_mut{m}")), mutate(&mut rng, &bytes)).expect("write seed");
+            }
+        }
+    }
+    // 深さの上限を超える入れ子
+    let mut deep = vec![0u8, 0x2C];
+    for _ in 0..1000 {
+        let body_size = deep.len() as u64;
+        deep.extend(body_size.to_be_bytes());
+        deep.push(0x2F);
+    }
+    fs::write(dir.join("deep_arrays"), &deep).expect("write seed");
+}
@@ -0,0 +1,87 @@
+//! std 無しで TON を書いて読む
+//!
+//! この crate 自体も `#![no_std]` で、alloc だけを使います
+//! テストの時だけ test harness が std を持ち込みますが、依存先の idis は no_std のままビルドされます
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use idis::ton::{from_slice, reverse_to_forward, to_vec, Result, Value};
+use serde::{Deserialize, Serialize};
+
+/// センサーの記録
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reading {
+    pub id: u32,
+    pub name: String,
+    pub samples: Vec<f32>,
+    pub tags: BTreeMap<String, i64>,
+    pub note: Option<String>,
+}
+
+/// Reading を TON にする
+///
+/// reading: &Reading
+///
+/// return: Result<Vec<u8>>
+pub fn encode(reading: &Reading) -> Result<Vec<u8>> {
+    to_vec(reading)
+}
+
+/// TON から Reading を読む
+///
+/// bytes: &[u8]
+///
+/// return: Result<Reading>
+pub fn decode(bytes: &[u8]) -> Result<Reading> {
+    from_slice(bytes)
+}
+
+/// TON を型の無い Value として読み、Forward TON に並べ替える
+///
+/// bytes: &[u8]
+///
+/// return: Result<(Value, Vec<u8>)>
+pub fn inspect(bytes: &[u8]) -> Result<(Value, Vec<u8>)> {
+    Ok((from_slice(bytes)?, reverse_to_forward(bytes)?))
+}
+
+#[cfg(test)]
+mod no_std_tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    fn reading() -> Reading {
+        let mut tags = BTreeMap::new();
+        tags.insert("floor".to_string(), 3);
+        tags.insert("offset".to_string(), -40);
+        Reading {
+            id: 7,
+            name: "thermo".to_string(),
+            samples: alloc::vec![20.5, 21.0, -3.25],
+            tags,
+            note: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = encode(&reading()).unwrap();
+        assert_eq!(decode(&bytes).unwrap(), reading());
+
+        let (value, forward) = inspect(&bytes).unwrap();
+        assert_eq!(value["name"], Value::String("thermo".to_string()));
+        assert_eq!(forward.len(), bytes.len());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = encode(&reading()).unwrap();
+        assert!(decode(&bytes[1..]).is_err());
+        assert!(decode(&[]).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt::{self, Debug};
+use std::{fmt::{self, Debug}, io};
 
 pub enum IDVDError {
     VDNotFound,
@@ -24,7 +24,16 @@ impl fmt::Display for IDVDError {
 
 impl Debug for IDVDError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl From<io::Error> for IDVDError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => IDVDError::OSPermissionDenied,
+            _ => IDVDError::Other(error.to_string()),
+        }
     }
 }
 
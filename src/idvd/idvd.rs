@@ -1,5 +1,9 @@
-use std::path::PathBuf;
-use tokio::fs::File;
+use std::{io::{self, SeekFrom}, path::{Path, PathBuf}};
+
+use rand::{rngs::OsRng, TryRngCore};
+use tokio::{fs::{File, OpenOptions}, io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt}};
+
+use super::error::IDVDError;
 
 
 /// IDIS Virtual Disk(IDVD) format
@@ -195,124 +199,440 @@ impl FSPermissions {
     }
 }
 
-// impl IDVD {
-//     pub async fn load(path: &PathBuf) -> Result<Self, IDVDError> {
-//         let mut file = OpenOptions::new()
-//             .read(true)
-//             .write(true)
-//             .create(false)
-//             .open(path)
-//             .await
-//             .map_err(|_| IDVDError::VDNotFound)?;
-
-//         let mut buf = [0u8; 64];
-//         file.read_exact(&mut buf)
-//             .await
-//             .map_err(|_| IDVDError::InvalidFormat)?;
-
-//         let vd_version = buf[7] as u8;
-//         if vd_version != 1 {
-//             return Err(IDVDError::NotSupportedVersion);
-//         }
-
-//         let vd_gen = u64::from_le_bytes(buf[8..16].try_into().unwrap());
-//         let hash_seed = u64::from_le_bytes(buf[16..24].try_into().unwrap());
-//         let block_size = u64::from_le_bytes(buf[24..32].try_into().unwrap());
-//         let size = u64::from_le_bytes(buf[32..40].try_into().unwrap());
-//         let block_index_pos = u64::from_le_bytes(buf[40..48].try_into().unwrap());
-//         let fs_index_addr = u64::from_le_bytes(buf[48..56].try_into().unwrap());
-//         let id_index_addr = u64::from_le_bytes(buf[56..64].try_into().unwrap());
-
-//         Ok(Self {
-//             path: path.clone(),
-//             size,
-//             block_size,
-//             block_index_pos,
-//             fs_index_addr,
-//             id_index_addr,
-//             vd_gen,
-//             vd_version,
-//             hash_seed,
-//             file,
-//         })
-//     }
-
-//     pub async fn new(path: &PathBuf, size: u64, block_size: u64) -> Result<Self, IDVDError> {
-//         let file = OpenOptions::new()
-//             .read(true)
-//             .write(true)
-//             .create(true)
-//             .open(path)
-//             .await
-//             .map_err(|_| IDVDError::OSPermissionDenied)?;
-
-//         let block_index_pos: u64 = 1;
-//         let fs_index_addr: u64 = 1;
-//         let id_index_addr: u64 = 0;
-//         let mut rng = OsRng;
-//         let hash_seed = rng
-//             .try_next_u64()
-//             .map_err(|_| IDVDError::FiledGetOsRng)?;
-//         let vd_version: u8 = 0;
-//         let vd_gen: u64 = 0;
-
-//         Ok(Self {
-//             path: path.clone(),
-//             size,
-//             block_size,
-//             block_index_pos,
-//             fs_index_addr,
-//             id_index_addr,
-//             vd_gen,
-//             vd_version,
-//             hash_seed,
-//             file,
-//         })
-//     }
-
-//     pub fn size(&self) -> u64 {
-//         self.size
-//     }
-
-//     pub fn block_size(&self) -> u64 {
-//         self.block_size
-//     }
-
-//     pub fn block_index_pos(&self) -> u64 {
-//         self.block_index_pos
-//     }
-
-//     pub async fn seek(&mut self, pos: u64) -> Result<(), IDVDError> {
-//         self.file
-//             .seek(std::io::SeekFrom::Start(pos))
-//             .await
-//             .map(|_| ())
-//             .map_err(|_| IDVDError::OSPermissionDenied)
-//     }
-
-//     pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, IDVDError> {
-//         self.file
-//             .read(buf)
-//             .await
-//             .map_err(|_| IDVDError::OSPermissionDenied)
-//     }
-
-//     pub fn file(&mut self) -> &mut File {
-//         &mut self.file
-//     }
-
-//     pub fn buf_write(&mut self) -> BufWriter<&mut File> {
-//         BufWriter::new(self.file())
-//     }
-
-//     pub async fn write(&mut self, buf: &[u8]) -> Result<usize, IDVDError> {
-//         self.file
-//             .write(buf)
-//             .await
-//             .map_err(|_| IDVDError::OSPermissionDenied)
-//     }
-
-//     pub fn resize(&mut self, add_size: u64) {
-//         self.size += add_size;
-//     }
-// }
\ No newline at end of file
+/// block 0 に置く superblock
+///
+/// little endian で次の順に並び、残りの block は 0 で埋めます
+///
+/// | offset | size | field |
+/// |---|---|---|
+/// | 0 | 8 | magic `IDISIDVD` |
+/// | 8 | 1 | vd_version |
+/// | 9 | 7 | reserved |
+/// | 16 | 8 | size |
+/// | 24 | 8 | block_size |
+/// | 32 | 8 | bitmap_pos |
+/// | 40 | 8 | cluster_index_pos |
+/// | 48 | 8 | fs_index_addr |
+/// | 56 | 8 | id_index_addr |
+/// | 64 | 8 | vd_gen |
+/// | 72 | 8 | hash_seed |
+/// | 80 | 4 | checksum (0..80 の CRC-32) |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Superblock {
+    pub size: u64, // in bytes
+    pub block_size: u64, // in bytes
+    pub bitmap_pos: u64, // in blocks
+    pub cluster_index_pos: u64, // in blocks
+    pub fs_index_addr: u64, // in blocks, 0 は未作成
+    pub id_index_addr: u64, // in blocks, 0 は未作成
+    pub vd_gen: u64, // snapshot number
+    pub hash_seed: u64, // hash seed
+    pub vd_version: u8, // version number
+}
+
+impl Superblock {
+    pub const MAGIC: [u8; 8] = *b"IDISIDVD";
+    /// このバージョンで読み書きできる vd_version
+    pub const VERSION: u8 = 1;
+    /// superblock のバイト数
+    pub const SIZE: usize = 84;
+    /// block_size の最小値
+    pub const MIN_BLOCK_SIZE: u64 = 512;
+
+    /// 新しいドライブの superblock を作る
+    ///
+    /// [ meta | data | bitmap ] の順に配置し、bitmap は 1 block 1 bit で後方に置きます
+    /// cluster index は superblock の直後から始めます
+    ///
+    /// # Arguments
+    /// * `size` - ドライブのバイト数 (block_size の倍数)
+    /// * `block_size` - 1 block のバイト数 (512 以上の 2 の冪)
+    /// * `hash_seed` - hash seed
+    ///
+    /// # Returns
+    /// * `Result<Superblock, IDVDError>` - 配置できない大きさの場合は InvalidFormat
+    pub fn new(size: u64, block_size: u64, hash_seed: u64) -> Result<Self, IDVDError> {
+        if !block_size.is_power_of_two() || block_size < Self::MIN_BLOCK_SIZE {
+            return Err(IDVDError::InvalidFormat);
+        }
+        let blocks = size / block_size;
+        let bitmap_blocks = blocks.div_ceil(8).div_ceil(block_size);
+        let superblock = Self {
+            size,
+            block_size,
+            bitmap_pos: blocks.saturating_sub(bitmap_blocks),
+            cluster_index_pos: 1,
+            fs_index_addr: 0,
+            id_index_addr: 0,
+            vd_gen: 0,
+            hash_seed,
+            vd_version: Self::VERSION,
+        };
+        superblock.validate()?;
+        Ok(superblock)
+    }
+
+    /// バイト列にする
+    ///
+    /// # Returns
+    /// * `[u8; Superblock::SIZE]` - checksum を含むバイト列
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..8].copy_from_slice(&Self::MAGIC);
+        buf[8] = self.vd_version;
+        let fields = [
+            self.size,
+            self.block_size,
+            self.bitmap_pos,
+            self.cluster_index_pos,
+            self.fs_index_addr,
+            self.id_index_addr,
+            self.vd_gen,
+            self.hash_seed,
+        ];
+        for (i, field) in fields.iter().enumerate() {
+            buf[16 + i * 8..24 + i * 8].copy_from_slice(&field.to_le_bytes());
+        }
+        let checksum = crc32(&buf[..80]);
+        buf[80..84].copy_from_slice(&checksum.to_le_bytes());
+        buf
+    }
+
+    /// バイト列から読み取って検証する
+    ///
+    /// magic と checksum が合わない場合や配置が壊れている場合は InvalidFormat、
+    /// vd_version が VERSION でない場合は NotSupportedVersion を返します
+    ///
+    /// # Arguments
+    /// * `buf` - block 0 の先頭
+    ///
+    /// # Returns
+    /// * `Result<Superblock, IDVDError>`
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, IDVDError> {
+        if buf.len() < Self::SIZE || buf[0..8] != Self::MAGIC {
+            return Err(IDVDError::InvalidFormat);
+        }
+        // バージョンが違うと checksum の位置も違うかもしれないので先に見る
+        if buf[8] != Self::VERSION {
+            return Err(IDVDError::NotSupportedVersion);
+        }
+        let checksum = u32::from_le_bytes(buf[80..84].try_into().unwrap());
+        if crc32(&buf[..80]) != checksum {
+            return Err(IDVDError::InvalidFormat);
+        }
+        let field = |i: usize| u64::from_le_bytes(buf[16 + i * 8..24 + i * 8].try_into().unwrap());
+        let superblock = Self {
+            size: field(0),
+            block_size: field(1),
+            bitmap_pos: field(2),
+            cluster_index_pos: field(3),
+            fs_index_addr: field(4),
+            id_index_addr: field(5),
+            vd_gen: field(6),
+            hash_seed: field(7),
+            vd_version: buf[8],
+        };
+        superblock.validate()?;
+        Ok(superblock)
+    }
+
+    /// ドライブ全体の block 数
+    #[inline]
+    pub fn blocks(&self) -> u64 {
+        self.size / self.block_size
+    }
+
+    /// 配置がドライブに収まっているか確認する
+    fn validate(&self) -> Result<(), IDVDError> {
+        // 壊れた block_size で割らないよう、block 数を出す前に確認する
+        if !self.block_size.is_power_of_two() || self.block_size < Self::MIN_BLOCK_SIZE {
+            return Err(IDVDError::InvalidFormat);
+        }
+        let blocks = self.blocks();
+        let valid = self.size.is_multiple_of(self.block_size)
+            // superblock と cluster index の間に bitmap は置けない
+            && 0 < self.cluster_index_pos
+            && self.cluster_index_pos < self.bitmap_pos
+            && self.bitmap_pos < blocks
+            && self.fs_index_addr < self.bitmap_pos
+            && self.id_index_addr < self.bitmap_pos;
+        if !valid {
+            return Err(IDVDError::InvalidFormat);
+        }
+        Ok(())
+    }
+}
+
+/// CRC-32 (IEEE 802.3)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+impl IDVD {
+    /// 新しいドライブを作る
+    ///
+    /// ファイルを size まで伸ばし、block 0 に superblock を書きます
+    /// 既にファイルがある場合は上書きせずにエラーを返します
+    /// 途中で失敗した場合は作ったファイルを消すので、そのまま作り直せます
+    ///
+    /// # Arguments
+    /// * `path` - ドライブのファイル
+    /// * `size` - ドライブのバイト数 (block_size の倍数)
+    /// * `block_size` - 1 block のバイト数 (512 以上の 2 の冪)
+    ///
+    /// # Returns
+    /// * `Result<IDVD, IDVDError>`
+    pub async fn create<P: AsRef<Path>>(path: P, size: u64, block_size: u64) -> Result<Self, IDVDError> {
+        let hash_seed = OsRng.try_next_u64().map_err(|_| IDVDError::FiledGetOsRng)?;
+        let superblock = Superblock::new(size, block_size, hash_seed)?;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path.as_ref())
+            .await?;
+
+        match Self::init(path.as_ref(), superblock, file).await {
+            Ok(idvd) => Ok(idvd),
+            Err(e) => {
+                // superblock の無いファイルを残すと create も open もできなくなる
+                let _ = tokio::fs::remove_file(path.as_ref()).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// 作ったファイルを size まで伸ばして superblock を書く
+    ///
+    /// # Arguments
+    /// * `path` - ドライブのファイル
+    /// * `superblock` - 書き込む superblock
+    /// * `file` - 空のファイル
+    ///
+    /// # Returns
+    /// * `Result<IDVD, IDVDError>`
+    async fn init(path: &Path, superblock: Superblock, file: File) -> Result<Self, IDVDError> {
+        file.set_len(superblock.size).await?;
+        let mut idvd = Self::from_superblock(path, superblock, file);
+        idvd.write_superblock().await?;
+        Ok(idvd)
+    }
+
+    /// 既存のドライブを開く
+    ///
+    /// superblock を検証し、ファイルが size より短い場合も InvalidFormat を返します
+    ///
+    /// # Arguments
+    /// * `path` - ドライブのファイル
+    ///
+    /// # Returns
+    /// * `Result<IDVD, IDVDError>` - ファイルが無い場合は VDNotFound
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, IDVDError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path.as_ref())
+            .await
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => IDVDError::VDNotFound,
+                _ => e.into(),
+            })?;
+
+        let mut buf = [0u8; Superblock::SIZE];
+        file.read_exact(&mut buf).await.map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => IDVDError::InvalidFormat,
+            _ => e.into(),
+        })?;
+        let superblock = Superblock::from_bytes(&buf)?;
+        if file.metadata().await?.len() < superblock.size {
+            return Err(IDVDError::InvalidFormat);
+        }
+
+        Ok(Self::from_superblock(path.as_ref(), superblock, file))
+    }
+
+    /// 現在のフィールドで block 0 を書き直す
+    ///
+    /// vd_gen や index の位置を変えた後に呼びます
+    ///
+    /// # Returns
+    /// * `Result<(), IDVDError>`
+    pub async fn write_superblock(&mut self) -> Result<(), IDVDError> {
+        let mut block = vec![0u8; self.block_size as usize];
+        block[..Superblock::SIZE].copy_from_slice(&self.superblock().to_bytes());
+        self.file.seek(SeekFrom::Start(0)).await?;
+        self.file.write_all(&block).await?;
+        self.file.sync_data().await?;
+        Ok(())
+    }
+
+    /// 現在のフィールドの superblock
+    ///
+    /// # Returns
+    /// * `Superblock`
+    pub fn superblock(&self) -> Superblock {
+        Superblock {
+            size: self.size,
+            block_size: self.block_size,
+            bitmap_pos: self.bitmap_pos,
+            cluster_index_pos: self.cluster_index_pos,
+            fs_index_addr: self.fs_index_addr,
+            id_index_addr: self.id_index_addr,
+            vd_gen: self.vd_gen,
+            hash_seed: self.hash_seed,
+            vd_version: self.vd_version,
+        }
+    }
+
+    fn from_superblock(path: &Path, superblock: Superblock, file: File) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            size: superblock.size,
+            block_size: superblock.block_size,
+            bitmap_pos: superblock.bitmap_pos,
+            cluster_index_pos: superblock.cluster_index_pos,
+            fs_index_addr: superblock.fs_index_addr,
+            id_index_addr: superblock.id_index_addr,
+            vd_gen: superblock.vd_gen,
+            hash_seed: superblock.hash_seed,
+            vd_version: superblock.vd_version,
+            file,
+        }
+    }
+}
+
+#[cfg(test)]
+mod idvd_tests {
+    use super::*;
+
+    /// テストごとに別のファイル
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("idis_{}_{}.idvd", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_superblock_layout() {
+        let superblock = Superblock::new(1 << 20, 4096, 42).unwrap();
+        // 256 block の bitmap は 1 block に収まる
+        assert_eq!(superblock.blocks(), 256);
+        assert_eq!(superblock.bitmap_pos, 255);
+        assert_eq!(superblock.cluster_index_pos, 1);
+
+        let bytes = superblock.to_bytes();
+        assert_eq!(&bytes[0..8], b"IDISIDVD");
+        assert_eq!(bytes[8], Superblock::VERSION);
+        assert_eq!(Superblock::from_bytes(&bytes).unwrap(), superblock);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_superblock_invalid() {
+        let bytes = Superblock::new(1 << 20, 4096, 42).unwrap().to_bytes();
+
+        let mut broken = bytes;
+        broken[0] = b'X';
+        assert!(matches!(Superblock::from_bytes(&broken), Err(IDVDError::InvalidFormat)));
+
+        let mut broken = bytes;
+        broken[72] ^= 1;
+        assert!(matches!(Superblock::from_bytes(&broken), Err(IDVDError::InvalidFormat)));
+
+        let mut broken = bytes;
+        broken[8] = Superblock::VERSION + 1;
+        assert!(matches!(Superblock::from_bytes(&broken), Err(IDVDError::NotSupportedVersion)));
+
+        assert!(matches!(Superblock::from_bytes(&bytes[..40]), Err(IDVDError::InvalidFormat)));
+
+        // 配置できない大きさ
+        assert!(matches!(Superblock::new(1 << 20, 1000, 0), Err(IDVDError::InvalidFormat)));
+        assert!(matches!(Superblock::new(1 << 20, 256, 0), Err(IDVDError::InvalidFormat)));
+        assert!(matches!(Superblock::new(4096 * 2, 4096, 0), Err(IDVDError::InvalidFormat)));
+
+        // checksum を合わせた壊れた block_size
+        for block_size in [0u64, 1, 4095] {
+            let mut broken = bytes;
+            broken[24..32].copy_from_slice(&block_size.to_le_bytes());
+            let checksum = crc32(&broken[..80]);
+            broken[80..84].copy_from_slice(&checksum.to_le_bytes());
+            assert!(matches!(Superblock::from_bytes(&broken), Err(IDVDError::InvalidFormat)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_open() {
+        let path = temp_path("create_open");
+        let created = IDVD::create(&path, 1 << 20, 4096).await.unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 1 << 20);
+        drop(created);
+
+        let mut idvd = IDVD::open(&path).await.unwrap();
+        assert_eq!(idvd.size, 1 << 20);
+        assert_eq!(idvd.block_size, 4096);
+        assert_eq!(idvd.vd_version, Superblock::VERSION);
+
+        idvd.vd_gen = 3;
+        idvd.fs_index_addr = 2;
+        idvd.write_superblock().await.unwrap();
+        let superblock = idvd.superblock();
+        drop(idvd);
+        assert_eq!(IDVD::open(&path).await.unwrap().superblock(), superblock);
+
+        // 既にあるドライブは上書きしない
+        assert!(IDVD::create(&path, 1 << 20, 4096).await.is_err());
+        assert_eq!(IDVD::open(&path).await.unwrap().superblock(), superblock);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_failed() {
+        // 親ディレクトリが無いのは VD が無いのとは別のエラー
+        let path = temp_path("create_failed");
+        let missing = path.with_extension("missing").join("drive.idvd");
+        assert!(matches!(IDVD::create(&missing, 1 << 20, 4096).await, Err(IDVDError::Other(_))));
+
+        // ファイルを伸ばせない場合は作りかけのファイルを残さない
+        let size = 1u64 << 63;
+        assert!(Superblock::new(size, 4096, 0).is_ok());
+        assert!(IDVD::create(&path, size, 4096).await.is_err());
+        assert!(!path.exists());
+        IDVD::create(&path, 1 << 20, 4096).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_invalid() {
+        let path = temp_path("open_invalid");
+        assert!(matches!(IDVD::open(&path).await, Err(IDVDError::VDNotFound)));
+
+        std::fs::write(&path, b"not an idvd").unwrap();
+        assert!(matches!(IDVD::open(&path).await, Err(IDVDError::InvalidFormat)));
+
+        std::fs::write(&path, vec![0u8; 4096]).unwrap();
+        assert!(matches!(IDVD::open(&path).await, Err(IDVDError::InvalidFormat)));
+
+        // block_size が 0 でも checksum が合っていれば validate まで進む
+        let mut forged = Superblock::new(1 << 20, 4096, 0).unwrap().to_bytes();
+        forged[24..32].fill(0);
+        let checksum = crc32(&forged[..80]);
+        forged[80..84].copy_from_slice(&checksum.to_le_bytes());
+        std::fs::write(&path, forged).unwrap();
+        assert!(matches!(IDVD::open(&path).await, Err(IDVDError::InvalidFormat)));
+
+        // size より短く切られたファイル
+        std::fs::remove_file(&path).unwrap();
+        IDVD::create(&path, 1 << 20, 4096).await.unwrap();
+        std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(4096).unwrap();
+        assert!(matches!(IDVD::open(&path).await, Err(IDVDError::InvalidFormat)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
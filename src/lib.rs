@@ -1,5 +1,16 @@
+// no_std でもテストは host の std で動かす
+// std を持ち込まないことは no_std/ の crate で確かめる
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "ton")]
 pub mod ton;
+#[cfg(feature = "std")]
 pub mod idvd;
+#[cfg(feature = "std")]
 pub mod engine;
+#[cfg(feature = "std")]
 pub mod cash;
-pub mod utils;
\ No newline at end of file
+#[cfg(feature = "std")]
+pub mod utils;
@@ -1 +1,282 @@
-pub mod serde;
\ No newline at end of file
+pub mod serde;
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::io;
+
+use ::serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub use self::serde::{
+    de::{Deserializer, Limits, SliceRead},
+    error::{Error, ErrorCode, Result},
+    forward::{forward_to_reverse, reverse_to_forward, ForwardSerializer},
+    json::{from_json, from_plain_json, to_json, to_plain_json},
+    schema::{validate, Field, Schema},
+    ser::{ReverseSerializer, Write},
+    stream::{Event, StreamReader},
+    text::{binary_to_text, from_text, text_to_binary, to_text, to_text_pretty},
+    types::WithMeta,
+    view::TonView,
+    value::{map::Map, value::{KeyValue, Value}},
+};
+#[cfg(feature = "std")]
+pub use self::serde::de::IoRead;
+
+/// T を TON にシリアライズして writer に書き込む
+///
+/// writer: W
+/// value: &T
+///
+/// return: Result<()>
+#[inline]
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: Write,
+    T: ?Sized + Serialize,
+{
+    let mut serializer = ReverseSerializer::new(writer);
+    value.serialize(&mut serializer)
+}
+
+/// T を TON にシリアライズして Vec<u8> で返す
+///
+/// value: &T
+///
+/// return: Result<Vec<u8>>
+#[inline]
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut writer = Vec::with_capacity(128);
+    to_writer(&mut writer, value)?;
+    Ok(writer)
+}
+
+/// T を canonical な TON にシリアライズして Vec<u8> で返す
+///
+/// 同じ値からは常に同じバイト列になるので、ハッシュや署名に使えます
+///
+/// value: &T
+///
+/// return: Result<Vec<u8>>
+#[inline]
+pub fn to_canonical_vec<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut serializer = ReverseSerializer::new(Vec::with_capacity(128)).canonical();
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_inner())
+}
+
+/// T を TON にシリアライズし、alignment の倍数のサイズになるよう PADDING を足して返す
+///
+/// IDVD のブロックに置いた値を、隣のブロックを書き換えずにその場で更新するのに使います
+///
+/// value: &T
+/// alignment: usize // DriverCash::block_size などの境界
+///
+/// return: Result<Vec<u8>>
+#[inline]
+pub fn to_vec_aligned<T>(value: &T, alignment: usize) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut serializer = ReverseSerializer::new(Vec::with_capacity(alignment));
+    value.serialize(&mut serializer)?;
+    serializer.pad_to(alignment)?;
+    Ok(serializer.into_inner())
+}
+
+/// T を Value に変換する
+///
+/// value: &T
+///
+/// return: Result<Value>
+#[inline]
+pub fn to_value<T>(value: &T) -> Result<Value>
+where
+    T: ?Sized + Serialize,
+{
+    self::serde::value::ser::to_value(value)
+}
+
+/// TON のバイト列から T をデシリアライズする
+///
+/// スライス全体が 1 つの値である必要があります
+/// 入れ子の深さは Limits::DEFAULT_MAX_DEPTH までです
+///
+/// v: &[u8]
+///
+/// return: Result<T>
+#[inline]
+pub fn from_slice<'a, T>(v: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_slice_with_limits(v, Limits::new())
+}
+
+/// 資源の上限を決めて TON のバイト列から T をデシリアライズする
+///
+/// ネットワークなど信頼できない入力を読むときに使います
+///
+/// v: &[u8]
+/// limits: Limits // Limits::untrusted() など
+///
+/// return: Result<T>
+#[inline]
+pub fn from_slice_with_limits<'a, T>(v: &'a [u8], limits: Limits) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_tail(SliceRead::new(v))?.limits(limits);
+    let value = T::deserialize(&mut deserializer).map_err(|e| e.at(deserializer.pos()))?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// reader から T をデシリアライズする
+///
+/// TON は末尾から読むので reader は Seek できる必要があります
+///
+/// reader: R
+///
+/// return: Result<T>
+#[cfg(feature = "std")]
+#[inline]
+pub fn from_reader<R, T>(reader: R) -> Result<T>
+where
+    R: io::Read + io::Seek,
+    T: DeserializeOwned,
+{
+    let mut deserializer = Deserializer::from_tail(IoRead::new(reader))?;
+    let value = T::deserialize(&mut deserializer).map_err(|e| e.at(deserializer.pos()))?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// 資源の上限を決めて reader から T をデシリアライズする
+///
+/// ネットワークなど信頼できない入力を読むときに使います
+///
+/// reader: R
+/// limits: Limits // Limits::untrusted() など
+///
+/// return: Result<T>
+#[cfg(feature = "std")]
+#[inline]
+pub fn from_reader_with_limits<R, T>(reader: R, limits: Limits) -> Result<T>
+where
+    R: io::Read + io::Seek,
+    T: DeserializeOwned,
+{
+    let mut deserializer = Deserializer::from_tail(IoRead::new(reader))?.limits(limits);
+    let value = T::deserialize(&mut deserializer).map_err(|e| e.at(deserializer.pos()))?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Value から T を組み立てる
+///
+/// value: Value
+///
+/// return: Result<T>
+#[inline]
+pub fn from_value<T>(value: Value) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    self::serde::value::de::from_value(value)
+}
+
+#[cfg(test)]
+mod ton_tests {
+    use ::serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Record {
+        id: u64,
+        name: String,
+        tags: Vec<String>,
+    }
+
+    fn record() -> Record {
+        Record {
+            id: 42,
+            name: "idis".to_string(),
+            tags: vec!["a".to_string(), "b".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_vec_round_trip() {
+        let bytes = to_vec(&record()).unwrap();
+        assert_eq!(from_slice::<Record>(&bytes).unwrap(), record());
+    }
+
+    #[test]
+    fn test_writer_reader_round_trip() {
+        let mut out = Vec::new();
+        to_writer(&mut out, &record()).unwrap();
+        assert_eq!(from_slice::<Record>(&out).unwrap(), record());
+        #[cfg(feature = "std")]
+        assert_eq!(from_reader::<_, Record>(std::io::Cursor::new(out)).unwrap(), record());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_reader_with_limits() {
+        let bytes = to_vec(&record()).unwrap();
+        let read = |limits| from_reader_with_limits::<_, Record>(std::io::Cursor::new(bytes.clone()), limits);
+        assert_eq!(read(Limits::untrusted()).unwrap(), record());
+        let err = read(Limits { max_string_len: 3, ..Limits::new() }).unwrap_err();
+        assert!(matches!(err.code(), ErrorCode::StringLimit));
+        let err = read(Limits { max_elements: 1, ..Limits::new() }).unwrap_err();
+        assert!(matches!(err.code(), ErrorCode::ElementLimit));
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let value = to_value(&record()).unwrap();
+        assert_eq!(value["name"], Value::String("idis".to_string()));
+        assert_eq!(from_value::<Record>(value.clone()).unwrap(), record());
+        // Value も TON にそのまま書ける
+        assert_eq!(from_slice::<Value>(&to_vec(&value).unwrap()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_canonical_round_trip() {
+        let bytes = to_canonical_vec(&record()).unwrap();
+        assert!(bytes.len() < to_vec(&record()).unwrap().len());
+        assert_eq!(from_slice::<Record>(&bytes).unwrap(), record());
+        // Value を経由しても同じバイト列になる
+        assert_eq!(to_canonical_vec(&to_value(&record()).unwrap()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_aligned_padding_is_skipped() {
+        for alignment in [1, 2, 7, 64, 512, 4096] {
+            let bytes = to_vec_aligned(&record(), alignment).unwrap();
+            assert_eq!(bytes.len() % alignment, 0);
+            assert_eq!(from_slice::<Record>(&bytes).unwrap(), record());
+            #[cfg(feature = "std")]
+            assert_eq!(from_reader::<_, Record>(std::io::Cursor::new(bytes.clone())).unwrap(), record());
+            assert_eq!(TonView::new(&bytes).unwrap().get("name").unwrap().unwrap().as_str(), Some("idis"));
+            assert_eq!(StreamReader::new(SliceRead::new(&bytes)).unwrap().next_event().unwrap(), Some(Event::StartObject(3)));
+            assert_eq!(reverse_to_forward(&bytes).unwrap(), reverse_to_forward(&to_vec(&record()).unwrap()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_from_slice_trailing_bytes() {
+        let mut bytes = vec![0xFF];
+        bytes.extend(to_vec(&record()).unwrap());
+        assert!(from_slice::<Record>(&bytes).is_err());
+        assert!(from_slice::<Record>(&[]).is_err());
+    }
+}
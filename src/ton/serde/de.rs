@@ -1,109 +1,1218 @@
-use std::f32::consts::E;
+#[cfg(feature = "std")]
+use std::io;
 
-use serde::de;
+use alloc::{vec, vec::Vec, string::{String, ToString}};
 
+use serde::de::{self, IntoDeserializer};
+use half::f16;
 
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
 
+use super::{error::{Error, ErrorCode, Result}, types::{self, TagKeyDeserializer, TaggedMapAccess}, value::{num::{Float, Int}, prefix::{prefix, prefix_pua_utf8, size_prefix::{SIZE_PREFIX_1BYTE, SIZE_PREFIX_2BYTE, SIZE_PREFIX_4BYTE, SIZE_PREFIX_8BYTE}}, value::Value}};
 
+/// Reverse TON デシリアライザー
+///
+/// ReverseSerializer が書き出したバイト列を末尾から読みます
+/// 値の末尾にある header から型とサイズを取り出し、body を遡って読みます
 pub struct Deserializer<R>
 {
     reader: R,
-    front_buffer: Vec<u8>,
-    back_buffer: Vec<u8>,
+    buffer: Vec<u8>,
     standard_pos: usize,
     pos: usize,
-    deep: usize,
-    reverse: bool,
+    root: usize,
+    limits: Limits,
+    /// 読み込み中の container の入れ子の深さ
+    depth: usize,
+    /// これまでに確保したバイト数
+    allocated: usize,
+}
+
+/// デコード時の資源の上限
+///
+/// header の size フィールドは 8byte まで書けるので、信頼できない入力では
+/// 確保する前にここで決めた上限と比べます
+/// 上限を超えると `Category::Limit` のエラーになります
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Array, Object, META の入れ子の深さ
+    pub max_depth: usize,
+    /// String, Bytes の body と container の要素に確保するバイト数の合計
+    ///
+    /// 要素は 1 つにつき `size_of::<Value>()` byte として数えます
+    pub max_alloc: usize,
+    /// String, Bytes, DATETIME, WrappedJSON の body のバイト数
+    pub max_string_len: usize,
+    /// Array の要素数と Object の entry 数
+    pub max_elements: usize,
+}
+
+impl Limits {
+    /// 入れ子の深さの既定の上限
+    ///
+    /// 再帰で読むのでスタックを使い切らないよう、深さだけは既定でも制限します
+    pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+    /// 入れ子の深さ以外に上限の無い Limits を作る
+    ///
+    /// return: Limits
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            max_alloc: usize::MAX,
+            max_string_len: usize::MAX,
+            max_elements: usize::MAX,
+        }
+    }
+
+    /// ネットワークなど信頼できない入力向けの Limits を作る
+    ///
+    /// return: Limits
+    #[inline]
+    pub const fn untrusted() -> Self {
+        Self {
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            max_alloc: 64 << 20,
+            max_string_len: 16 << 20,
+            max_elements: 1 << 20,
+        }
+    }
+}
+
+impl Default for Limits {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'de, R> Deserializer<R>
-where R: Read<'de>, 
+where R: Read<'de>,
 {
-    fn new(reader: R, pos: usize) -> Self {
+    /// 新しいDeserializerを作る
+    ///
+    /// reader: R
+    /// pos: usize // 読み始める値の prefix の位置(値の最終バイト)
+    ///
+    /// return: Deserializer
+    #[inline]
+    pub fn new(reader: R, pos: usize) -> Self {
         Deserializer {
             reader,
-            front_buffer: Vec::new(),
-            back_buffer: Vec::new(),
-            standard_pos: pos,
-            pos: pos,
-            deep: 0,
-            reverse: false,
+            buffer: Vec::new(),
+            standard_pos: 0,
+            pos,
+            root: pos,
+            limits: Limits::new(),
+            depth: 0,
+            allocated: 0,
+        }
+    }
+
+    /// 資源の上限を設定する
+    ///
+    /// limits: Limits
+    ///
+    /// return: Deserializer
+    #[inline]
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// reader の末尾にある値から読み始めるDeserializerを作る
+    ///
+    /// reader: R
+    ///
+    /// return: Result<Deserializer>
+    #[inline]
+    pub fn from_tail(mut reader: R) -> Result<Self> {
+        let size = reader.size()?;
+        let pos = size.checked_sub(1).ok_or_else(|| Error::syntax(ErrorCode::NotFoundTarget, 0))?;
+        let mut de = Self::new(reader, pos);
+        // 末尾の padding を飛ばしてから値を読む
+        de.skip_padding()?;
+        de.root = de.pos;
+        Ok(de)
+    }
+
+    /// readerを取り出す
+    ///
+    /// return: R
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// 読み込み中の値の prefix の位置
+    ///
+    /// return: usize
+    #[inline]
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// 次に読む値の prefix の位置を設定する
+    ///
+    /// pos: usize
+    #[inline]
+    pub(crate) fn set_pos(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    /// これまでに確保したバイト数を 0 に戻す
+    ///
+    /// StreamReader がイベントごとに max_alloc を数え直すのに使います
+    #[inline]
+    pub(crate) fn reset_allocated(&mut self) {
+        self.allocated = 0;
+    }
+
+    /// len byte の確保を上限と比べて記録する
+    ///
+    /// len: usize
+    /// pos: usize // エラーに使う prefix の位置
+    ///
+    /// return: Result<()>
+    #[inline]
+    fn reserve(&mut self, len: usize, pos: usize) -> Result<()> {
+        match self.allocated.checked_add(len) {
+            Some(allocated) if allocated <= self.limits.max_alloc => {
+                self.allocated = allocated;
+                Ok(())
+            }
+            _ => Err(Error::syntax(ErrorCode::AllocLimit, pos)),
         }
     }
 
-    fn read_byte(&mut self, pos: usize) -> Result<u8, Error> {
-        if self.standard_pos <= pos && pos < self.standard_pos + self.front_buffer.len() {
+    /// String や Bytes の body の長さを上限と比べる
+    ///
+    /// pos: usize // prefix の位置
+    /// header: &Header
+    ///
+    /// return: Result<()>
+    #[inline]
+    fn check_string(&self, pos: usize, header: &Header) -> Result<()> {
+        if header.body_size > self.limits.max_string_len {
+            return Err(Error::syntax(ErrorCode::StringLimit, pos));
+        }
+        Ok(())
+    }
+
+    /// 1 段深い container として f を呼ぶ
+    ///
+    /// 深さは要素を集める前に上限と比べます
+    ///
+    /// pos: usize // container の prefix の位置
+    /// f: F
+    ///
+    /// return: Result<T>
+    #[inline]
+    pub(crate) fn nested<T, F>(&mut self, pos: usize, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        if self.depth >= self.limits.max_depth {
+            return Err(Error::syntax(ErrorCode::DepthLimit, pos));
+        }
+        self.depth += 1;
+        let value = f(self);
+        self.depth -= 1;
+        value
+    }
+
+    /// 読み始めた値が reader の先頭まで使い切っているか確認する
+    ///
+    /// 逆順に読むので、値より前に余ったバイトがあればエラーになります
+    ///
+    /// return: Result<()>
+    pub fn end(&mut self) -> Result<()> {
+        let header = self.read_header(self.root)?;
+        match header.body_start(self.root) {
+            0 => Ok(()),
+            _ => Err(Error::syntax(ErrorCode::TrailingBytes, self.root)),
+        }
+    }
+
+    /// 1byte 読む
+    ///
+    /// chunk に無い場合は reader から読み直します
+    ///
+    /// pos: usize
+    ///
+    /// return: Result<u8>
+    #[inline]
+    fn read_byte(&mut self, pos: usize) -> Result<u8> {
+        if self.standard_pos <= pos && pos < self.standard_pos + self.buffer.len() {
             let local_pos = pos - self.standard_pos;
-            Ok(self.front_buffer[local_pos])
+            Ok(self.buffer[local_pos])
         } else {
-            self.standard_pos = self.reader.get_chunk(&mut self.front_buffer, pos);
-            let local_pos = pos - self.standard_pos;
-            match self.front_buffer.get(local_pos) {
+            self.standard_pos = self.reader.get_chunk(&mut self.buffer, pos)?;
+            match pos.checked_sub(self.standard_pos).and_then(|local_pos| self.buffer.get(local_pos)) {
                 Some(byte) => Ok(*byte),
-                None => Err(Error::syntax(ErrorCode::NotFoundTarget, 0)),
+                None => Err(Error::syntax(ErrorCode::NotFoundTarget, pos)),
             }
         }
     }
 
-    fn read_header(&mut self) -> Result<(TonTypes, usize/*body_size*/), Error> {
-        const MASK: u8 = 0b111111_00;
-        let prefix = self.read_byte(self.pos)?;
-        let ton_type = match prefix | MASK {
-            prefix::UNDEFINED => return Ok((TonTypes::Undefined, 0)),
-            prefix::NONE => return Ok((TonTypes::None, 0)),
-            prefix::BOOL => return Ok((TonTypes::Bool, 0)),
-            prefix::UINT => {
+    /// start から len byte を読む
+    ///
+    /// start: usize
+    /// len: usize
+    ///
+    /// return: Result<Vec<u8>>
+    fn read_range(&mut self, start: usize, len: usize) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(len);
+        let end = start + len;
+        let mut cur = start;
+        while cur < end {
+            // chunk の先頭を合わせる
+            self.read_byte(cur)?;
+            let local_pos = cur - self.standard_pos;
+            let available = (self.buffer.len() - local_pos).min(end - cur);
+            out.extend_from_slice(&self.buffer[local_pos..local_pos + available]);
+            cur += available;
+        }
+        Ok(out)
+    }
 
-            }
-            prefix::INT => {
+    /// 固定長の body を読む
+    ///
+    /// pos: usize // prefix の位置
+    ///
+    /// return: Result<[u8; N]>
+    #[inline]
+    fn read_fixed<const N: usize>(&mut self, pos: usize) -> Result<[u8; N]> {
+        let start = pos.checked_sub(N).ok_or_else(|| Error::syntax(ErrorCode::SizeOverflow, pos))?;
+        let mut buf = [0u8; N];
+        buf.copy_from_slice(&self.read_range(start, N)?);
+        Ok(buf)
+    }
 
-            }
-            prefix::FLOAT => {
+    /// 反転した header を読む
+    ///
+    /// pos にある prefix から型を判定し、size フィールドがあれば読み取ります
+    ///
+    /// pos: usize // prefix の位置
+    ///
+    /// return: Result<Header>
+    pub(crate) fn read_header(&mut self, pos: usize) -> Result<Header> {
+        let byte = self.read_byte(pos)?;
+        let size_prefix = byte & SIZE_PREFIX_8BYTE;
+        let ton_type = match decode_prefix(byte).ok_or_else(|| prefix_error(byte, pos))? {
+            (_, Some(body_size)) if body_size > pos => return Err(Error::syntax(ErrorCode::SizeOverflow, pos)),
+            (ton_type, Some(body_size)) => return Ok(Header { ton_type, header_size: 1, body_size }),
+            (ton_type, None) => ton_type,
+        };
 
-            }
-            prefix::STRING => {
+        // size フィールドは反転して書かれているので big endian として読む
+        let size_width = 1usize << size_prefix;
+        let start = pos.checked_sub(size_width).ok_or_else(|| Error::syntax(ErrorCode::TruncatedHeader, pos))?;
+        let body_size = self.read_range(start, size_width)?
+            .iter()
+            .fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        let body_size = usize::try_from(body_size).map_err(|_| Error::syntax(ErrorCode::SizeOverflow, pos))?;
+        if body_size > start {
+            return Err(Error::syntax(ErrorCode::SizeOverflow, pos));
+        }
+        Ok(Header { ton_type, header_size: 1 + size_width, body_size })
+    }
+
+    /// 値の body を読む
+    ///
+    /// pos: usize // prefix の位置
+    /// header: &Header
+    ///
+    /// return: Result<Vec<u8>>
+    #[inline]
+    fn read_body(&mut self, pos: usize, header: &Header) -> Result<Vec<u8>> {
+        self.check_string(pos, header)?;
+        self.reserve(header.body_size, pos)?;
+        self.read_range(header.body_start(pos), header.body_size)
+    }
+
+    /// 文字列の body を読む
+    ///
+    /// pos: usize // prefix の位置
+    /// header: &Header
+    ///
+    /// return: Result<String>
+    #[inline]
+    fn read_string(&mut self, pos: usize, header: &Header) -> Result<String> {
+        let body = self.read_body(pos, header)?;
+        String::from_utf8(body).map_err(|_| Error::syntax(ErrorCode::InvalidUtf8, pos))
+    }
+
+    /// UUID の body を読む
+    ///
+    /// pos: usize // prefix の位置
+    ///
+    /// return: Result<Uuid>
+    #[inline]
+    fn read_uuid(&mut self, pos: usize) -> Result<Uuid> {
+        Ok(Uuid::from_bytes(self.read_fixed(pos)?))
+    }
+
+    /// DATETIME の body を読む
+    ///
+    /// pos: usize // prefix の位置
+    /// header: &Header
+    ///
+    /// return: Result<DateTime<Utc>>
+    #[inline]
+    fn read_datetime(&mut self, pos: usize, header: &Header) -> Result<DateTime<Utc>> {
+        let body = self.read_string(pos, header)?;
+        DateTime::parse_from_rfc3339(&body)
+            .map(|v| v.with_timezone(&Utc))
+            .map_err(|e| Error::syntax(ErrorCode::Message(e.to_string()), pos))
+    }
 
+    /// META を読み飛ばして値の prefix に移動する
+    ///
+    /// 値は body の末尾にあるので metadata は読みません
+    #[inline]
+    fn skip_meta(&mut self) -> Result<()> {
+        loop {
+            let header = self.read_header(self.pos)?;
+            if header.ton_type != TonTypes::Meta {
+                return Ok(());
             }
-            prefix::BYTES => {
+            self.pos = header.body_end(self.pos).checked_sub(1)
+                .ok_or_else(|| Error::syntax(ErrorCode::SizeOverflow, self.pos))?;
+        }
+    }
+
+    /// pos にある整数を幅によらず読む
+    ///
+    /// compact モードで狭い幅に書かれた整数を、要求された型の幅に戻すのに使います
+    ///
+    /// return: Result<Option<i128>> // i128 に収まる整数でない場合は None
+    fn read_integer(&mut self) -> Result<Option<i128>> {
+        let pos = self.pos;
+        let v = match self.read_header(pos)?.ton_type {
+            TonTypes::UIntU8 => self.read_fixed::<1>(pos)?[0] as i128,
+            TonTypes::UIntU16 => u16::from_le_bytes(self.read_fixed(pos)?) as i128,
+            TonTypes::UIntU32 => u32::from_le_bytes(self.read_fixed(pos)?) as i128,
+            TonTypes::UIntU64 => u64::from_le_bytes(self.read_fixed(pos)?) as i128,
+            TonTypes::IntI8 => i8::from_le_bytes(self.read_fixed(pos)?) as i128,
+            TonTypes::IntI16 => i16::from_le_bytes(self.read_fixed(pos)?) as i128,
+            TonTypes::IntI32 => i32::from_le_bytes(self.read_fixed(pos)?) as i128,
+            TonTypes::IntI64 => i64::from_le_bytes(self.read_fixed(pos)?) as i128,
+            TonTypes::IntI128 => i128::from_le_bytes(self.read_fixed(pos)?),
+            _ => return Ok(None),
+        };
+        Ok(Some(v))
+    }
+
+    /// pos にある PADDING を読み飛ばす
+    ///
+    /// PADDING が続く場合はすべて飛ばし、その前にある値の prefix に移動します
+    ///
+    /// return: Result<()>
+    fn skip_padding(&mut self) -> Result<()> {
+        // PADDING でなければ header は読まずに値を読むときに任せる
+        while self.read_byte(self.pos)? & 0b111111_00 == prefix::PADDING {
+            let header = self.read_header(self.pos)?;
+            self.pos = header.body_start(self.pos).checked_sub(1)
+                .ok_or_else(|| Error::syntax(ErrorCode::SizeOverflow, self.pos))?;
+        }
+        Ok(())
+    }
 
+    /// エラーの path に使う key の表現を読む
+    ///
+    /// String と整数の key 以外は "?" になります
+    ///
+    /// pos: usize // key の prefix の位置
+    ///
+    /// return: String
+    #[cold]
+    fn key_name(&mut self, pos: usize) -> String {
+        let saved = self.pos;
+        self.pos = pos;
+        let name = match self.read_header(pos) {
+            Ok(header) if header.ton_type == TonTypes::String => self.read_string(pos, &header).ok(),
+            Ok(_) => self.read_integer().ok().flatten().map(|v| v.to_string()),
+            Err(_) => None,
+        };
+        self.pos = saved;
+        name.unwrap_or_else(|| "?".to_string())
+    }
+
+    /// container の子要素の prefix の位置を前から順に集める
+    ///
+    /// PADDING は要素に含めません
+    /// 要素数は Limits::max_elements (Object と META は key と値で 2 倍) までです
+    ///
+    /// body の末尾から header を読みながら遡り、最後に反転します
+    ///
+    /// pos: usize // container の prefix の位置
+    /// header: &Header
+    ///
+    /// return: Result<Vec<usize>>
+    pub(crate) fn read_elements(&mut self, pos: usize, header: &Header) -> Result<Vec<usize>> {
+        let body_start = header.body_start(pos);
+        let mut cur = header.body_end(pos);
+        let max_elements = match header.ton_type {
+            TonTypes::Array => self.limits.max_elements,
+            _ => self.limits.max_elements.saturating_mul(2),
+        };
+        let mut elements = Vec::new();
+        while cur > body_start {
+            let elem_pos = cur - 1;
+            let elem_header = self.read_header(elem_pos)?;
+            let elem_size = elem_header.header_size + elem_header.body_size;
+            if elem_size > cur - body_start {
+                return Err(Error::syntax(ErrorCode::SizeOverflow, elem_pos));
             }
-            prefix::UUID => return Ok((TonTypes::UUID, 16)),
-            prefix::DATETIME => {
+            if elem_header.ton_type != TonTypes::Padding {
+                if elements.len() >= max_elements {
+                    return Err(Error::syntax(ErrorCode::ElementLimit, pos));
+                }
+                // 読んだ要素は Value 1 つ分以上のメモリになるので、位置の usize ではなくその分を数える
+                self.reserve(size_of::<Value>(), pos)?;
+                elements.push(elem_pos);
+            }
+            cur -= elem_size;
+        }
+        elements.reverse();
+        Ok(elements)
+    }
+}
+
+/// prefix の byte から型を判定する
+///
+/// 固定長の型は body のサイズも返します
+/// size フィールドを持つ型は None を返し、フィールドの幅は下位 2bit の size prefix で決まります
+///
+/// byte: u8
+///
+/// return: Option<(TonTypes, Option<usize>)> // 不明な prefix の場合は None
+pub(crate) fn decode_prefix(byte: u8) -> Option<(TonTypes, Option<usize>)> {
+    const MASK: u8 = 0b111111_00;
+    let size_prefix = byte & !MASK;
+    let fixed = |ton_type, body_size| Some((ton_type, Some(body_size)));
+    let ton_type = match byte & MASK {
+        prefix::UNDEFINED => return fixed(TonTypes::Undefined, 0),
+        prefix::NONE => return fixed(TonTypes::None, 0),
+        prefix::BOOL => return fixed(TonTypes::Bool, 0),
+        prefix::UINT => {
+            let ton_type = match size_prefix {
+                SIZE_PREFIX_1BYTE => TonTypes::UIntU8,
+                SIZE_PREFIX_2BYTE => TonTypes::UIntU16,
+                SIZE_PREFIX_4BYTE => TonTypes::UIntU32,
+                _ => TonTypes::UIntU64,
+            };
+            return fixed(ton_type, 1 << size_prefix);
+        }
+        prefix::INT => {
+            let ton_type = match size_prefix {
+                SIZE_PREFIX_1BYTE => TonTypes::IntI8,
+                SIZE_PREFIX_2BYTE => TonTypes::IntI16,
+                SIZE_PREFIX_4BYTE => TonTypes::IntI32,
+                _ => TonTypes::IntI64,
+            };
+            return fixed(ton_type, 1 << size_prefix);
+        }
+        prefix::FLOAT => {
+            let ton_type = match size_prefix {
+                SIZE_PREFIX_2BYTE => TonTypes::Float16,
+                SIZE_PREFIX_4BYTE => TonTypes::Float32,
+                SIZE_PREFIX_8BYTE => TonTypes::Float64,
+                _ => return None,
+            };
+            return fixed(ton_type, 1 << size_prefix);
+        }
+        prefix::INT128 => return fixed(TonTypes::IntI128, 16),
+        prefix::UINT128 => return fixed(TonTypes::UIntU128, 16),
+        prefix::UUID => return fixed(TonTypes::UUID, 16),
+        prefix::TIMESTAMP => return fixed(TonTypes::Timestamp, 8),
+        prefix::DURATION => return fixed(TonTypes::Duration, 8),
+        prefix::STRING => TonTypes::String,
+        prefix::BYTES => TonTypes::Bytes,
+        prefix::DATETIME => TonTypes::DateTime,
+        prefix::ARRAY => TonTypes::Array,
+        prefix::OBJECT => TonTypes::Object,
+        prefix::WRAPPED_JSON => TonTypes::WrappedJSON,
+        prefix::META => TonTypes::Meta,
+        prefix::PADDING => TonTypes::Padding,
+        _ => return None,
+    };
+    Some((ton_type, None))
+}
+
+/// decode_prefix で判定できなかった prefix のエラー
+///
+/// 型は分かるが size prefix がその型に使えない場合は BadSizePrefix になります
+///
+/// byte: u8
+/// pos: usize
+///
+/// return: Error
+#[cold]
+pub(crate) fn prefix_error(byte: u8, pos: usize) -> Error {
+    let base = byte & 0b111111_00;
+    if (0..4).any(|size_prefix| decode_prefix(base | size_prefix).is_some()) {
+        Error::syntax(ErrorCode::BadSizePrefix(byte), pos)
+    } else {
+        Error::syntax(ErrorCode::UnknownPrefix(byte), pos)
+    }
+}
+
+/// 反転した header から読み取った情報
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub ton_type: TonTypes,
+    pub header_size: usize,
+    pub body_size: usize,
+}
 
+impl Header {
+    /// body の先頭位置
+    ///
+    /// pos: usize // prefix の位置
+    ///
+    /// return: usize
+    #[inline]
+    pub fn body_start(&self, pos: usize) -> usize {
+        self.body_end(pos) - self.body_size
+    }
+
+    /// body の終端位置(排他的)
+    ///
+    /// pos: usize // prefix の位置
+    ///
+    /// return: usize
+    #[inline]
+    pub fn body_end(&self, pos: usize) -> usize {
+        pos + 1 - self.header_size
+    }
+}
+
+/// META を読み飛ばしてから deserialize_any に渡す
+macro_rules! forward_to_payload {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            #[inline]
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value>
+            where
+                V: de::Visitor<'de> {
+                self.skip_meta()?;
+                self.deserialize_any(visitor)
             }
-            prefix::TIMESTAMP => return Ok((TonTypes::Timestamp, 8)),
-            prefix::DURATION => return Ok((TonTypes::Duration, 8)),
-            prefix::ARRAY => {
+        )*
+    };
+}
 
+/// 整数を要求された型の幅に戻して visitor に渡す
+///
+/// 型に収まらない場合は deserialize_any に渡して visitor にエラーを出させます
+macro_rules! widen_integer {
+    ($($method:ident => $visit:ident($ty:ty))*) => {
+        $(
+            #[inline]
+            fn $method<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: de::Visitor<'de> {
+                self.skip_meta()?;
+                match self.read_integer()?.map(<$ty>::try_from) {
+                    Some(Ok(v)) => visitor.$visit(v),
+                    _ => self.deserialize_any(visitor),
+                }
             }
-            prefix::OBJECT => {
+        )*
+    };
+}
+
+impl<'de, R: Read<'de>> de::Deserializer<'de> for &mut Deserializer<R> {
+    type Error = Error;
 
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        let pos = self.pos;
+        let header = self.read_header(pos)?;
+        match header.ton_type {
+            TonTypes::Undefined => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::UNDEFINED, Value::None)),
+            TonTypes::None => visitor.visit_none(),
+            TonTypes::Bool => {
+                let byte = self.read_byte(pos)?;
+                visitor.visit_bool(byte & 1 == 1)
+            }
+            TonTypes::UIntU8 => visitor.visit_u8(self.read_fixed::<1>(pos)?[0]),
+            TonTypes::UIntU16 => visitor.visit_u16(u16::from_le_bytes(self.read_fixed(pos)?)),
+            TonTypes::UIntU32 => visitor.visit_u32(u32::from_le_bytes(self.read_fixed(pos)?)),
+            TonTypes::UIntU64 => visitor.visit_u64(u64::from_le_bytes(self.read_fixed(pos)?)),
+            TonTypes::IntI8 => visitor.visit_i8(i8::from_le_bytes(self.read_fixed(pos)?)),
+            TonTypes::IntI16 => visitor.visit_i16(i16::from_le_bytes(self.read_fixed(pos)?)),
+            TonTypes::IntI32 => visitor.visit_i32(i32::from_le_bytes(self.read_fixed(pos)?)),
+            TonTypes::IntI64 => visitor.visit_i64(i64::from_le_bytes(self.read_fixed(pos)?)),
+            TonTypes::IntI128 => visitor.visit_i128(i128::from_le_bytes(self.read_fixed(pos)?)),
+            TonTypes::UIntU128 => visitor.visit_u128(u128::from_le_bytes(self.read_fixed(pos)?)),
+            // f16 は serde に無いので {"$float": f32} の map として渡す
+            TonTypes::Float16 => {
+                let payload = Value::Float(Float::F32(f16::from_le_bytes(self.read_fixed(pos)?).to_f32()));
+                visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::FLOAT, payload))
+            }
+            TonTypes::Float32 => visitor.visit_f32(f32::from_le_bytes(self.read_fixed(pos)?)),
+            TonTypes::Float64 => visitor.visit_f64(f64::from_le_bytes(self.read_fixed(pos)?)),
+            // 入力を借りられる場合はコピーせずに渡す
+            // Value などの visitor は借りた body もコピーして持つので、借りる場合も確保として数える
+            TonTypes::String => {
+                self.check_string(pos, &header)?;
+                self.reserve(header.body_size, pos)?;
+                match self.reader.borrow_range(header.body_start(pos), header.body_size) {
+                    Some(body) => visitor.visit_borrowed_str(
+                        core::str::from_utf8(body).map_err(|_| Error::syntax(ErrorCode::InvalidUtf8, pos))?
+                    ),
+                    None => {
+                        let body = self.read_range(header.body_start(pos), header.body_size)?;
+                        visitor.visit_string(String::from_utf8(body).map_err(|_| Error::syntax(ErrorCode::InvalidUtf8, pos))?)
+                    }
+                }
+            }
+            TonTypes::Bytes => {
+                self.check_string(pos, &header)?;
+                self.reserve(header.body_size, pos)?;
+                match self.reader.borrow_range(header.body_start(pos), header.body_size) {
+                    Some(body) => visitor.visit_borrowed_bytes(body),
+                    None => visitor.visit_byte_buf(self.read_range(header.body_start(pos), header.body_size)?),
+                }
+            }
+            // TON 固有の型は {tag: payload} の map として渡す
+            TonTypes::UUID => {
+                let payload = Value::String(self.read_uuid(pos)?.to_string());
+                visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::UUID, payload))
+            }
+            TonTypes::DateTime => {
+                let payload = Value::String(types::datetime_to_string(&self.read_datetime(pos, &header)?));
+                visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::DATETIME, payload))
             }
-            prefix::WRAPPED_JSON => {
+            TonTypes::Timestamp => {
+                let payload = Value::Int(Int::I64(i64::from_le_bytes(self.read_fixed(pos)?)));
+                visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::TIMESTAMP, payload))
+            }
+            TonTypes::Duration => {
+                let payload = Value::Int(Int::I64(i64::from_le_bytes(self.read_fixed(pos)?)));
+                visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::DURATION, payload))
+            }
+            TonTypes::Array => self.nested(pos, |de| {
+                let elements = de.read_elements(pos, &header)?;
+                visitor.visit_seq(SeqAccess::new(de, elements))
+            }),
+            TonTypes::Object => self.nested(pos, |de| {
+                let elements = de.read_elements(pos, &header)?;
+                if elements.len() % 2 != 0 {
+                    return Err(Error::syntax(ErrorCode::InvalidType, pos));
+                }
+                visitor.visit_map(MapAccess::new(de, elements))
+            }),
+            TonTypes::WrappedJSON => {
+                let body = self.read_string(pos, &header)?;
+                serde_json::from_str::<de::IgnoredAny>(&body)
+                    .map_err(|e| Error::syntax(ErrorCode::Message(e.to_string()), pos))?;
+                visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::WRAPPED_JSON, Value::String(body)))
+            }
+            // {"$meta": [metadata, 値]} の map として渡す
+            TonTypes::Meta => self.nested(pos, |de| {
+                let elements = de.read_elements(pos, &header)?;
+                if elements.len() != 2 {
+                    return Err(Error::syntax(ErrorCode::InvalidType, pos));
+                }
+                visitor.visit_map(MetaAccess { de, elements: Some(elements) })
+            }),
+            // PADDING は値ではないので、ここに来るのは壊れた入力
+            TonTypes::Padding => Err(Error::syntax(ErrorCode::InvalidType, pos)),
+        }
+    }
+
+    #[inline]
+    fn is_human_readable(&self) -> bool {
+        false
+    }
 
+    #[inline]
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        self.skip_meta()?;
+        match self.read_header(self.pos)?.ton_type {
+            TonTypes::Float16 => visitor.visit_f32(f16::from_le_bytes(self.read_fixed(self.pos)?).to_f32()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        self.skip_meta()?;
+        // compact モードで狭い幅に書かれた値も f64 に戻す
+        match self.read_header(self.pos)?.ton_type {
+            TonTypes::Float16 => visitor.visit_f64(f16::from_le_bytes(self.read_fixed(self.pos)?).to_f64()),
+            TonTypes::Float32 => visitor.visit_f64(f32::from_le_bytes(self.read_fixed(self.pos)?) as f64),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        self.skip_meta()?;
+        match self.read_header(self.pos)?.ton_type {
+            TonTypes::Timestamp | TonTypes::Duration => visitor.visit_i64(i64::from_le_bytes(self.read_fixed(self.pos)?)),
+            _ => match self.read_integer()?.map(i64::try_from) {
+                Some(Ok(v)) => visitor.visit_i64(v),
+                _ => self.deserialize_any(visitor),
+            },
+        }
+    }
+
+    #[inline]
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        self.skip_meta()?;
+        let pos = self.pos;
+        let header = self.read_header(pos)?;
+        match header.ton_type {
+            TonTypes::UUID => visitor.visit_string(self.read_uuid(pos)?.to_string()),
+            TonTypes::DateTime => visitor.visit_string(self.read_string(pos, &header)?),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        // 所有権が必要なので借りずに読む
+        self.skip_meta()?;
+        let pos = self.pos;
+        let header = self.read_header(pos)?;
+        match header.ton_type {
+            TonTypes::String => visitor.visit_string(self.read_string(pos, &header)?),
+            _ => self.deserialize_str(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        self.skip_meta()?;
+        match self.read_header(self.pos)?.ton_type {
+            TonTypes::UUID => visitor.visit_bytes(&self.read_fixed::<16>(self.pos)?),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        // 所有権が必要なので借りずに読む
+        self.skip_meta()?;
+        let pos = self.pos;
+        let header = self.read_header(pos)?;
+        match header.ton_type {
+            TonTypes::Bytes => visitor.visit_byte_buf(self.read_body(pos, &header)?),
+            _ => self.deserialize_bytes(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        self.skip_meta()?;
+        match self.read_header(self.pos)?.ton_type {
+            TonTypes::None | TonTypes::Undefined => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    #[inline]
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        self.skip_meta()?;
+        match self.read_header(self.pos)?.ton_type {
+            TonTypes::None | TonTypes::Undefined => visitor.visit_unit(),
+            _ => Err(Error::syntax(ErrorCode::InvalidType, self.pos)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        self.deserialize_unit(visitor)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        self.skip_meta()?;
+        let pos = self.pos;
+        let header = self.read_header(pos)?;
+        match header.ton_type {
+            // unit variant は variant 名の文字列
+            TonTypes::String => visitor.visit_enum(self.read_string(pos, &header)?.into_deserializer()),
+            // それ以外は { variant: value } の object
+            TonTypes::Object => self.nested(pos, |de| {
+                let elements = de.read_elements(pos, &header)?;
+                match elements[..] {
+                    [value_pos, key_pos] => visitor.visit_enum(EnumAccess { de, key_pos, value_pos }),
+                    _ => Err(Error::syntax(ErrorCode::InvalidType, pos)),
+                }
+            }),
+            _ => Err(Error::syntax(ErrorCode::InvalidType, pos)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        // サイズが分かっているので読まずに飛ばせる
+        visitor.visit_unit()
+    }
+
+    widen_integer! {
+        deserialize_i8 => visit_i8(i8)
+        deserialize_i16 => visit_i16(i16)
+        deserialize_i32 => visit_i32(i32)
+        deserialize_i128 => visit_i128(i128)
+        deserialize_u8 => visit_u8(u8)
+        deserialize_u16 => visit_u16(u16)
+        deserialize_u32 => visit_u32(u32)
+        deserialize_u64 => visit_u64(u64)
+    }
+
+    forward_to_payload! {
+        deserialize_bool()
+        deserialize_u128()
+        deserialize_char()
+        deserialize_seq()
+        deserialize_tuple(_len: usize)
+        deserialize_tuple_struct(_name: &'static str, _len: usize)
+        deserialize_map()
+        deserialize_struct(_name: &'static str, _fields: &'static [&'static str])
+        deserialize_identifier()
+    }
+}
+
+/// {"$meta": [metadata, 値]} の 1 要素の map として渡す
+struct MetaAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+    elements: Option<Vec<usize>>,
+}
+
+impl<'de, 'a, R: Read<'de>> de::MapAccess<'de> for MetaAccess<'a, R> {
+    type Error = Error;
+
+    #[inline]
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de> {
+        match self.elements {
+            Some(_) => seed.deserialize(TagKeyDeserializer(prefix_pua_utf8::META)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de> {
+        let elements = self.elements.take().ok_or_else(|| Error::syntax(ErrorCode::NotFoundTarget, self.de.pos))?;
+        seed.deserialize(de::value::SeqAccessDeserializer::new(SeqAccess::new(&mut *self.de, elements)))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.is_some() as usize)
+    }
+}
+
+/// Array の要素を前から順に渡す
+struct SeqAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+    elements: vec::IntoIter<usize>,
+    index: usize,
+}
+
+impl<'a, R> SeqAccess<'a, R> {
+    #[inline]
+    fn new(de: &'a mut Deserializer<R>, elements: Vec<usize>) -> Self {
+        Self { de, elements: elements.into_iter(), index: 0 }
+    }
+}
+
+impl<'de, 'a, R: Read<'de>> de::SeqAccess<'de> for SeqAccess<'a, R> {
+    type Error = Error;
+
+    #[inline]
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de> {
+        match self.elements.next() {
+            Some(pos) => {
+                self.de.pos = pos;
+                let index = self.index;
+                self.index += 1;
+                seed.deserialize(&mut *self.de)
+                    .map(Some)
+                    .map_err(|e| e.at(pos).in_index(index))
             }
-            prefix::META => {
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+/// Object の要素を前から順に渡す
+///
+/// 要素は value, key の順に並んでいます
+struct MapAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+    elements: vec::IntoIter<usize>,
+    value_pos: usize,
+    key_pos: usize,
+}
+
+impl<'a, R> MapAccess<'a, R> {
+    #[inline]
+    fn new(de: &'a mut Deserializer<R>, elements: Vec<usize>) -> Self {
+        Self { de, elements: elements.into_iter(), value_pos: 0, key_pos: 0 }
+    }
+}
 
+impl<'de, 'a, R: Read<'de>> de::MapAccess<'de> for MapAccess<'a, R> {
+    type Error = Error;
+
+    #[inline]
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de> {
+        match (self.elements.next(), self.elements.next()) {
+            (Some(value_pos), Some(key_pos)) => {
+                self.value_pos = value_pos;
+                self.key_pos = key_pos;
+                self.de.pos = key_pos;
+                seed.deserialize(&mut *self.de).map(Some).map_err(|e| e.at(key_pos))
             }
-            _ => return Err(Error::syntax(ErrorCode::InvalidType, self.pos)),
-        };
+            _ => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de> {
+        self.de.pos = self.value_pos;
+        match seed.deserialize(&mut *self.de) {
+            Ok(value) => Ok(value),
+            Err(e) => Err(e.at(self.value_pos).in_key(self.de.key_name(self.key_pos))),
+        }
+    }
 
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len() / 2)
     }
 }
 
-impl<'de, R: Read<'de>> de::Deserializer<'de> for &mut Deserializer<R> {
+/// { variant: value } 形式の enum を渡す
+struct EnumAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+    key_pos: usize,
+    value_pos: usize,
+}
+
+impl<'de, 'a, R: Read<'de>> de::EnumAccess<'de> for EnumAccess<'a, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    #[inline]
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: de::DeserializeSeed<'de> {
+        self.de.pos = self.key_pos;
+        let variant = seed.deserialize(&mut *self.de)?;
+        self.de.pos = self.value_pos;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, R: Read<'de>> de::VariantAccess<'de> for EnumAccess<'a, R> {
     type Error = Error;
 
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    #[inline]
+    fn unit_variant(self) -> Result<()> {
+        de::Deserialize::deserialize(self.de)
+    }
+
+    #[inline]
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: de::DeserializeSeed<'de> {
+        seed.deserialize(self.de)
+    }
+
+    #[inline]
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de> {
+        de::Deserializer::deserialize_seq(self.de, visitor)
+    }
+
+    #[inline]
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de> {
-        
+        de::Deserializer::deserialize_map(self.de, visitor)
     }
 }
+
+/// チャンク単位でデータを読むためのトレイト
 pub trait Read<'de> {
-    fn get_chunk(&mut self, buffer: &mut Vec<u8>, pos: usize) -> usize/* chunk_head_position */;
+    /// pos を含むチャンクを buffer に読み込む
+    ///
+    /// buffer: &mut Vec<u8>
+    /// pos: usize
+    ///
+    /// return: Result<usize> // チャンクの先頭位置
+    fn get_chunk(&mut self, buffer: &mut Vec<u8>, pos: usize) -> Result<usize>/* chunk_head_position */;
+
+    /// データ全体のサイズ
+    ///
+    /// return: Result<usize>
+    fn size(&mut self) -> Result<usize>;
+
+    /// 入力をコピーせずに参照で借りる
+    ///
+    /// 入力全体がメモリ上にある場合だけ Some を返します
+    /// Some を返すと文字列とバイト列は `&'de str` `&'de [u8]` のまま渡されます
+    ///
+    /// start: usize
+    /// len: usize
+    ///
+    /// return: Option<&'de [u8]>
+    #[inline]
+    fn borrow_range(&mut self, _start: usize, _len: usize) -> Option<&'de [u8]> {
+        None
+    }
+}
+
+/// チャンクのサイズ
+const CHUNK_SIZE: usize = 4096;
+
+/// スライスから読む
+pub struct SliceRead<'a> {
+    slice: &'a [u8],
+}
+
+impl<'a> SliceRead<'a> {
+    #[inline]
+    pub fn new(slice: &'a [u8]) -> Self {
+        Self { slice }
+    }
+}
+
+impl<'de> Read<'de> for SliceRead<'de> {
+    #[inline]
+    fn get_chunk(&mut self, buffer: &mut Vec<u8>, pos: usize) -> Result<usize> {
+        let head = pos - pos % CHUNK_SIZE;
+        buffer.clear();
+        if head < self.slice.len() {
+            let end = (head + CHUNK_SIZE).min(self.slice.len());
+            buffer.extend_from_slice(&self.slice[head..end]);
+        }
+        Ok(head)
+    }
+
+    #[inline]
+    fn size(&mut self) -> Result<usize> {
+        Ok(self.slice.len())
+    }
+
+    #[inline]
+    fn borrow_range(&mut self, start: usize, len: usize) -> Option<&'de [u8]> {
+        self.slice.get(start..start.checked_add(len)?)
+    }
+}
+
+/// io::Read + io::Seek から読む
+#[cfg(feature = "std")]
+pub struct IoRead<R>
+where
+    R: io::Read + io::Seek,
+{
+    reader: R,
 }
 
+#[cfg(feature = "std")]
+impl<R> IoRead<R>
+where
+    R: io::Read + io::Seek,
+{
+    #[inline]
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// readerを取り出す
+    ///
+    /// return: R
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, R> Read<'de> for IoRead<R>
+where
+    R: io::Read + io::Seek,
+{
+    fn get_chunk(&mut self, buffer: &mut Vec<u8>, pos: usize) -> Result<usize> {
+        let head = pos - pos % CHUNK_SIZE;
+        buffer.clear();
+        self.reader.seek(io::SeekFrom::Start(head as u64)).map_err(Error::io)?;
+        let mut chunk = io::Read::take(&mut self.reader, CHUNK_SIZE as u64);
+        io::Read::read_to_end(&mut chunk, buffer).map_err(Error::io)?;
+        Ok(head)
+    }
+
+    #[inline]
+    fn size(&mut self) -> Result<usize> {
+        let size = self.reader.seek(io::SeekFrom::End(0)).map_err(Error::io)?;
+        Ok(size as usize)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TonTypes {
     Undefined,
     None,
@@ -116,6 +1225,8 @@ pub enum TonTypes {
     IntI16,
     IntI32,
     IntI64,
+    IntI128,
+    UIntU128,
     Float16,
     Float32,
     Float64,
@@ -129,4 +1240,778 @@ pub enum TonTypes {
     Object,
     WrappedJSON,
     Meta,
-}
\ No newline at end of file
+    Padding,
+}
+
+#[cfg(test)]
+mod de_tests {
+    use std::collections::BTreeMap;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::ton::serde::{ser::ReverseSerializer, value::{num::UInt, value::KeyValue}};
+
+    fn to_bytes<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut serializer = ReverseSerializer::new(&mut out);
+        value.serialize(&mut serializer).unwrap();
+        out
+    }
+
+    fn from_bytes<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> T {
+        let mut deserializer = Deserializer::from_tail(SliceRead::new(bytes)).unwrap();
+        T::deserialize(&mut deserializer).unwrap()
+    }
+
+    fn round_trip<T>(value: T)
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + std::fmt::Debug,
+    {
+        let bytes = to_bytes(&value);
+        assert_eq!(from_bytes::<T>(&bytes), value);
+    }
+
+    #[test]
+    fn test_deserialize_bool() {
+        round_trip(true);
+        round_trip(false);
+    }
+
+    #[test]
+    fn test_deserialize_uint() {
+        round_trip(42u8);
+        round_trip(4242u16);
+        round_trip(42424242u32);
+        round_trip(u64::MAX);
+    }
+
+    #[test]
+    fn test_deserialize_int() {
+        round_trip(-42i8);
+        round_trip(-4242i16);
+        round_trip(-42424242i32);
+        round_trip(i64::MIN);
+    }
+
+    #[test]
+    fn test_deserialize_128bit() {
+        round_trip(i128::MIN);
+        round_trip(u128::MAX);
+
+        // 16byte の body と 1byte の prefix
+        let bytes = to_bytes(&u128::MAX);
+        assert_eq!(bytes.len(), 17);
+        assert_eq!(bytes[16], prefix::UINT128);
+    }
+
+    #[test]
+    fn test_deserialize_float() {
+        round_trip(42.5f32);
+        round_trip(-42.125f64);
+    }
+
+    #[test]
+    fn test_deserialize_f16() {
+        // FLOAT の 2byte は f16 として読む
+        let bytes = [0x00, 0x3E, prefix::FLOAT | SIZE_PREFIX_2BYTE];
+        assert_eq!(from_bytes::<f32>(&bytes), 1.5);
+        assert_eq!(from_bytes::<f64>(&bytes), 1.5);
+    }
+
+    #[test]
+    fn test_deserialize_char() {
+        round_trip('A');
+    }
+
+    #[test]
+    fn test_deserialize_str() {
+        round_trip("Hello, world!".to_string());
+        // 2byte の size フィールド
+        round_trip("a".repeat(300));
+    }
+
+    #[test]
+    fn test_deserialize_borrowed() {
+        #[derive(Deserialize)]
+        struct Borrowed<'a> {
+            name: &'a str,
+            #[serde(borrow)]
+            cow: std::borrow::Cow<'a, str>,
+            data: &'a [u8],
+        }
+
+        let value = Value::Object([
+            ("name", Value::String("idis".to_string())),
+            ("cow", Value::String("cache page".to_string())),
+            ("data", Value::Bytes(vec![1, 2, 3])),
+        ].into_iter().map(|(k, v)| (KeyValue::String(k.to_string()), v)).collect());
+        let bytes = to_bytes(&value);
+        let borrowed: Borrowed = from_bytes(&bytes);
+        assert_eq!(borrowed.name, "idis");
+        assert_eq!(borrowed.data, &[1, 2, 3]);
+
+        // 入力のスライスを指している
+        let range = bytes.as_ptr_range();
+        assert!(range.contains(&borrowed.name.as_ptr()));
+        assert!(range.contains(&borrowed.data.as_ptr()));
+        assert!(matches!(borrowed.cow, std::borrow::Cow::Borrowed("cache page")));
+    }
+
+    #[test]
+    fn test_deserialize_bytes() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Bytes(#[serde(with = "serde_bytes_impl")] Vec<u8>);
+
+        mod serde_bytes_impl {
+            pub fn serialize<S: serde::Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+                s.serialize_bytes(v)
+            }
+
+            pub fn deserialize<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+                struct BytesVisitor;
+                impl serde::de::Visitor<'_> for BytesVisitor {
+                    type Value = Vec<u8>;
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        f.write_str("bytes")
+                    }
+                    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+                        Ok(v)
+                    }
+                }
+                d.deserialize_byte_buf(BytesVisitor)
+            }
+        }
+
+        round_trip(Bytes(b"Hello, world!".to_vec()));
+    }
+
+    #[test]
+    fn test_deserialize_option() {
+        round_trip(Some(42u8));
+        round_trip(None::<u8>);
+    }
+
+    #[test]
+    fn test_deserialize_unit() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Unit;
+
+        round_trip(());
+        round_trip(Unit);
+    }
+
+    #[test]
+    fn test_deserialize_newtype_struct() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Newtype(u8);
+
+        round_trip(Newtype(42));
+    }
+
+    #[test]
+    fn test_deserialize_seq() {
+        round_trip(vec![1u8, 2, 3]);
+        round_trip(Vec::<u8>::new());
+        round_trip(("Hello, world!".to_string(), 42u8));
+        round_trip(vec![vec![1u16], vec![], vec![2, 3]]);
+    }
+
+    #[test]
+    fn test_deserialize_tuple_struct() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Tuple(String, u8);
+
+        round_trip(Tuple("Hello, world!".to_string(), 42));
+    }
+
+    #[test]
+    fn test_deserialize_map() {
+        let mut map = BTreeMap::new();
+        map.insert("Hello, world!".to_string(), 42u8);
+        map.insert("foo".to_string(), 7u8);
+        round_trip(map);
+    }
+
+    #[test]
+    fn test_deserialize_struct() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct TestStruct {
+            field1: String,
+            field2: u8,
+            field3: Option<Vec<i32>>,
+        }
+
+        round_trip(TestStruct {
+            field1: "Hello, world!".to_string(),
+            field2: 42,
+            field3: Some(vec![-1, 0, 1]),
+        });
+    }
+
+    #[test]
+    fn test_deserialize_enum() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum TestEnum {
+            Unit,
+            Newtype(u8),
+            Tuple(String, u8),
+            Struct { field1: String, field2: u8 },
+        }
+
+        round_trip(TestEnum::Unit);
+        round_trip(TestEnum::Newtype(42));
+        round_trip(TestEnum::Tuple("Hello, world!".to_string(), 42));
+        round_trip(TestEnum::Struct { field1: "Hello, world!".to_string(), field2: 42 });
+    }
+
+    #[test]
+    fn test_deserialize_large_document() {
+        // チャンク境界をまたぐ
+        let value: Vec<String> = (0..2000).map(|i| format!("value-{}", i)).collect();
+        round_trip(value);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_deserialize_io_read() {
+        let value = vec!["Hello, world!".to_string(); 1000];
+        let bytes = to_bytes(&value);
+        let mut deserializer = Deserializer::from_tail(IoRead::new(std::io::Cursor::new(bytes))).unwrap();
+        assert_eq!(Vec::<String>::deserialize(&mut deserializer).unwrap(), value);
+    }
+
+    #[test]
+    fn test_read_header() {
+        let bytes = to_bytes(&"a".repeat(300));
+        let mut deserializer = Deserializer::new(SliceRead::new(&bytes), bytes.len() - 1);
+        let header = deserializer.read_header(bytes.len() - 1).unwrap();
+        assert_eq!(header, Header { ton_type: TonTypes::String, header_size: 3, body_size: 300 });
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        // 空
+        assert!(Deserializer::from_tail(SliceRead::new(&[])).is_err());
+        // 未定義の prefix
+        let bytes = [0b111110_00];
+        let mut deserializer = Deserializer::from_tail(SliceRead::new(&bytes)).unwrap();
+        assert!(u8::deserialize(&mut deserializer).is_err());
+        // body が足りない
+        let bytes = [b'a', 5, prefix::STRING | SIZE_PREFIX_1BYTE];
+        let mut deserializer = Deserializer::from_tail(SliceRead::new(&bytes)).unwrap();
+        assert!(String::deserialize(&mut deserializer).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_error_code() {
+        let from_slice = crate::ton::from_slice::<Value>;
+
+        let err = from_slice(&[0b111110_00]).unwrap_err();
+        assert!(err.is_unknown_format());
+        assert_eq!(err.to_string(), "Unknown prefix 0xf8 at position 0");
+        let err = from_slice(&[prefix::FLOAT | SIZE_PREFIX_1BYTE]).unwrap_err();
+        assert_eq!(err.to_string(), "Bad size prefix in 0x10 at position 0");
+        // 8byte の size フィールドが入力の先頭からはみ出す
+        let err = from_slice(&[0, 0, prefix::STRING | SIZE_PREFIX_8BYTE]).unwrap_err();
+        assert!(err.is_eof());
+        assert_eq!(err.to_string(), "Truncated header at position 2");
+        let err = from_slice(&[b'a', 5, prefix::STRING | SIZE_PREFIX_1BYTE]).unwrap_err();
+        assert_eq!(err.to_string(), "Size overflow at position 2");
+        let err = from_slice(&[0xff, 1, prefix::STRING | SIZE_PREFIX_1BYTE]).unwrap_err();
+        assert!(err.is_syntax());
+        assert_eq!(err.to_string(), "Invalid UTF-8 at position 2");
+
+        let mut bytes = vec![0xaa];
+        bytes.extend(crate::ton::to_vec(&true).unwrap());
+        let err = from_slice(&bytes).unwrap_err();
+        assert_eq!(err.to_string(), "Trailing bytes at position 1");
+    }
+
+    #[test]
+    fn test_deserialize_error_path() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Inner {
+            b: Vec<u8>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Doc {
+            a: Inner,
+        }
+
+        let mut inner = BTreeMap::new();
+        inner.insert("b", vec![Value::UInt(UInt::U8(1)), Value::UInt(UInt::U8(2)), Value::UInt(UInt::U8(3)), Value::String("x".to_string())]);
+        let mut doc = BTreeMap::new();
+        doc.insert("a", inner);
+        let bytes = to_bytes(&doc);
+
+        let err = crate::ton::from_slice::<Doc>(&bytes).unwrap_err();
+        assert_eq!(err.path(), ".a.b[3]");
+        // 壊れた要素の prefix の位置を指す
+        let view = crate::ton::TonView::new(&bytes).unwrap().pointer("/a/b/3").unwrap().unwrap();
+        assert_eq!(err.offset(), Some(view.position()));
+        assert!(err.to_string().contains("at `.a.b[3]` at position"));
+    }
+
+    #[test]
+    fn test_deserialize_compact_widen() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Counters {
+            id: u64,
+            delta: i64,
+            small: i32,
+            wide: i128,
+            ratio: f64,
+            precise: f64,
+        }
+
+        let value = Counters { id: 7, delta: -3, small: 70000, wide: 1, ratio: 0.5, precise: 0.1 };
+        let mut compact = Vec::new();
+        value.serialize(&mut ReverseSerializer::new(&mut compact).compact()).unwrap();
+        assert!(compact.len() < to_bytes(&value).len());
+        assert_eq!(from_bytes::<Counters>(&compact), value);
+    }
+
+    #[test]
+    fn test_deserialize_widen_exact_visitor() {
+        // visit_u32 だけを実装した visitor にも u32 の幅で渡す
+        struct U32Only;
+
+        impl<'de> de::Visitor<'de> for U32Only {
+            type Value = u32;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("u32")
+            }
+
+            fn visit_u32<E: de::Error>(self, v: u32) -> std::result::Result<u32, E> {
+                Ok(v)
+            }
+        }
+
+        let bytes = to_bytes(&5u8);
+        let mut deserializer = Deserializer::from_tail(SliceRead::new(&bytes)).unwrap();
+        assert_eq!(de::Deserializer::deserialize_u32(&mut deserializer, U32Only).unwrap(), 5);
+
+        // 型に収まらない値はエラーになる
+        let bytes = to_bytes(&300u16);
+        let mut deserializer = Deserializer::from_tail(SliceRead::new(&bytes)).unwrap();
+        assert!(u8::deserialize(&mut deserializer).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_skip_padding() {
+        // Array の要素の間と値の後ろにある PADDING
+        let padding = [0, 0, 2, prefix::PADDING | SIZE_PREFIX_1BYTE];
+        let mut bytes = to_bytes(&1u8);
+        bytes.extend(padding);
+        bytes.extend(to_bytes(&2u8));
+        bytes.extend([8, prefix::ARRAY | SIZE_PREFIX_1BYTE]);
+        bytes.extend(padding);
+        assert_eq!(from_bytes::<Vec<u8>>(&bytes), vec![1, 2]);
+
+        let mut deserializer = Deserializer::from_tail(SliceRead::new(&bytes)).unwrap();
+        Vec::<u8>::deserialize(&mut deserializer).unwrap();
+        deserializer.end().unwrap();
+
+        // PADDING だけでは値が無い
+        assert!(Deserializer::from_tail(SliceRead::new(&padding)).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_limits() {
+        let from_slice = |bytes: &[u8], limits: Limits| crate::ton::from_slice_with_limits::<Value>(bytes, limits);
+        let nested = to_bytes(&vec![vec![vec![1u8]]]);
+        assert!(from_slice(&nested, Limits { max_depth: 3, ..Limits::new() }).is_ok());
+        let err = from_slice(&nested, Limits { max_depth: 2, ..Limits::new() }).unwrap_err();
+        assert!(err.is_limit());
+        assert!(matches!(err.code(), crate::ton::ErrorCode::DepthLimit));
+        assert_eq!(err.path(), "[0][0]");
+
+        let text = to_bytes(&"a".repeat(100));
+        assert!(from_slice(&text, Limits { max_string_len: 100, ..Limits::new() }).is_ok());
+        assert!(from_slice(&text, Limits { max_string_len: 99, ..Limits::new() }).unwrap_err().is_limit());
+        // 所有権が必要な読み方でも同じ上限
+        let mut deserializer = Deserializer::from_tail(SliceRead::new(&text)).unwrap().limits(Limits { max_string_len: 99, ..Limits::new() });
+        assert!(String::deserialize(&mut deserializer).unwrap_err().is_limit());
+
+        let mut map = BTreeMap::new();
+        map.insert("a", 1u8);
+        map.insert("b", 2u8);
+        assert!(from_slice(&to_bytes(&map), Limits { max_elements: 2, ..Limits::new() }).is_ok());
+        assert!(from_slice(&to_bytes(&map), Limits { max_elements: 1, ..Limits::new() }).unwrap_err().is_limit());
+        assert!(from_slice(&to_bytes(&[0u8; 3]), Limits { max_elements: 2, ..Limits::new() }).unwrap_err().is_limit());
+
+        // 借りた String も Value にコピーするので数える
+        let strings = to_bytes(&vec!["a".repeat(64); 4]);
+        assert!(from_slice(&strings, Limits { max_alloc: 4 * (64 + size_of::<Value>()), ..Limits::new() }).is_ok());
+        let err = from_slice(&strings, Limits { max_alloc: 4 * (64 + size_of::<Value>()) - 1, ..Limits::new() }).unwrap_err();
+        assert!(matches!(err.code(), crate::ton::ErrorCode::AllocLimit));
+        let err = from_slice(&to_bytes(&vec![0u8; 64]), Limits { max_alloc: 1024, ..Limits::new() }).unwrap_err();
+        assert!(matches!(err.code(), crate::ton::ErrorCode::AllocLimit));
+        let err = from_slice(&to_bytes(&Value::Bytes(vec![0u8; 64])), Limits { max_alloc: 63, ..Limits::new() }).unwrap_err();
+        assert!(matches!(err.code(), crate::ton::ErrorCode::AllocLimit));
+        let mut deserializer = Deserializer::from_tail(SliceRead::new(&strings)).unwrap().limits(Limits { max_alloc: 128, ..Limits::new() });
+        assert!(Vec::<String>::deserialize(&mut deserializer).unwrap_err().is_limit());
+    }
+
+    #[test]
+    fn test_deserialize_limits_before_alloc() {
+        // 入力より大きな size を書いた String
+        let mut bytes = vec![b'a'; 16];
+        bytes.extend([0xFF, 0xFF, 0xFF, 0xFF, prefix::STRING | SIZE_PREFIX_4BYTE]);
+        let err = crate::ton::from_slice::<String>(&bytes).unwrap_err();
+        assert!(err.is_syntax());
+        assert!(matches!(err.code(), crate::ton::ErrorCode::SizeOverflow));
+
+        // 上限を超える String は body を読む前に止める
+        let bytes = to_bytes(&"a".repeat(1000));
+        let limits = Limits { max_string_len: 10, ..Limits::untrusted() };
+        let err = crate::ton::from_slice_with_limits::<String>(&bytes, limits).unwrap_err();
+        assert!(err.is_limit());
+        assert_eq!(err.offset(), Some(bytes.len() - 1));
+    }
+
+    /// depth 段入れ子にした空の Array
+    ///
+    /// Value を作ると再帰で drop するので、バイト列を直接組み立てる
+    fn deep_arrays(depth: usize) -> Vec<u8> {
+        let mut bytes = vec![0, prefix::ARRAY | SIZE_PREFIX_1BYTE];
+        for _ in 1..depth {
+            let body_size = bytes.len() as u64;
+            bytes.extend(body_size.to_be_bytes());
+            bytes.push(prefix::ARRAY | SIZE_PREFIX_8BYTE);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_deserialize_default_depth_limit() {
+        let bytes = deep_arrays(Limits::DEFAULT_MAX_DEPTH);
+        assert!(crate::ton::from_slice::<Value>(&bytes).is_ok());
+        assert!(crate::ton::from_slice::<Value>(&deep_arrays(Limits::DEFAULT_MAX_DEPTH + 1)).unwrap_err().is_limit());
+
+        // スタックを使い切る前に止まる
+        let bytes = deep_arrays(200_000);
+        assert!(crate::ton::from_slice::<Value>(&bytes).unwrap_err().is_limit());
+        // 読み飛ばすだけなら再帰しないので深さを数えない
+        assert!(crate::ton::from_slice::<serde::de::IgnoredAny>(&bytes).is_ok());
+        assert!(crate::ton::reverse_to_forward(&bytes).unwrap_err().is_limit());
+    }
+}
+
+#[cfg(test)]
+mod fuzz_tests {
+    use chrono::Duration;
+    use proptest::prelude::*;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::ton::{
+        forward_to_reverse, from_slice_with_limits, reverse_to_forward,
+        serde::{forward::forward_value_size, ser::ReverseSerializer, stream::{Event, StreamReader}, value::{num::UInt, value::KeyValue}, view::TonView},
+    };
+
+    fn leaf() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            Just(Value::Undefined),
+            Just(Value::None),
+            any::<bool>().prop_map(Value::Bool),
+            any::<i8>().prop_map(|v| Value::Int(Int::I8(v))),
+            any::<i64>().prop_map(|v| Value::Int(Int::I64(v))),
+            any::<i128>().prop_map(|v| Value::Int(Int::I128(v))),
+            any::<u16>().prop_map(|v| Value::UInt(UInt::U16(v))),
+            any::<u128>().prop_map(|v| Value::UInt(UInt::U128(v))),
+            any::<f32>().prop_map(|v| Value::Float(Float::F32(v))),
+            any::<f64>().prop_map(|v| Value::Float(Float::F64(v))),
+            ".{0,16}".prop_map(Value::String),
+            prop::collection::vec(any::<u8>(), 0..16).prop_map(Value::Bytes),
+            any::<u128>().prop_map(|v| Value::UUID(Uuid::from_u128(v))),
+            (0..4_000_000_000i64).prop_map(|v| Value::DateTime(DateTime::from_timestamp(v, 0).unwrap())),
+            any::<i64>().prop_map(Value::Timestamp),
+            (-1_000_000_000_000..1_000_000_000_000i64).prop_map(|v| Value::Duration(Duration::milliseconds(v))),
+        ]
+    }
+
+    fn value() -> impl Strategy<Value = Value> {
+        leaf().prop_recursive(6, 64, 8, |inner| prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(Value::Array),
+            prop::collection::vec((".{0,8}", inner.clone()), 0..8)
+                .prop_map(|entries| Value::Object(entries.into_iter().map(|(k, v)| (KeyValue::String(k), v)).collect())),
+            (inner.clone(), inner).prop_map(|(meta, v)| Value::Meta(Box::new(meta), Box::new(v))),
+        ])
+    }
+
+    /// 長い String, Bytes と要素の多い container
+    fn large_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            leaf(),
+            ".{0,1024}".prop_map(Value::String),
+            prop::collection::vec(any::<u8>(), 0..16384).prop_map(Value::Bytes),
+        ];
+        leaf.prop_recursive(3, 1024, 256, |inner| prop_oneof![
+            prop::collection::vec(inner.clone(), 0..256).prop_map(Value::Array),
+            prop::collection::vec(("[a-z]{0,4}", inner), 0..64)
+                .prop_map(|entries| Value::Object(entries.into_iter().map(|(k, v)| (KeyValue::String(k), v)).collect())),
+        ])
+    }
+
+    /// 既定の深さの上限の前後まで入れ子にした値
+    fn deep_value() -> impl Strategy<Value = Value> {
+        (leaf(), prop::collection::vec(0..3u8, 0..Limits::DEFAULT_MAX_DEPTH * 2)).prop_map(|(leaf, kinds)| {
+            kinds.into_iter().fold(leaf, |inner, kind| match kind {
+                0 => Value::Array(vec![inner]),
+                1 => Value::Object([(KeyValue::String("a".into()), inner)].into_iter().collect()),
+                _ => Value::Meta(Box::new(Value::None), Box::new(inner)),
+            })
+        })
+    }
+
+    /// ReverseSerializer の各モードで書いたバイト列
+    fn encode(value: &Value, mode: u8) -> Vec<u8> {
+        let mut serializer = match mode {
+            0 => ReverseSerializer::new(Vec::new()),
+            1 => ReverseSerializer::new(Vec::new()).compact(),
+            _ => ReverseSerializer::new(Vec::new()).canonical(),
+        };
+        value.serialize(&mut serializer).unwrap();
+        serializer.into_inner()
+    }
+
+    /// container と META の入れ子の深さ
+    fn depth(value: &Value) -> usize {
+        match value {
+            Value::Array(v) => 1 + v.iter().map(depth).max().unwrap_or(0),
+            Value::Object(v) => 1 + v.iter().map(|(_, v)| depth(v)).max().unwrap_or(0),
+            Value::Meta(meta, v) => 1 + depth(meta).max(depth(v)),
+            _ => 0,
+        }
+    }
+
+    /// 1 つの container にある要素数の最大
+    fn elements(value: &Value) -> usize {
+        match value {
+            Value::Array(v) => v.iter().map(elements).fold(v.len(), usize::max),
+            Value::Object(v) => v.iter().map(|(_, v)| elements(v)).fold(v.len(), usize::max),
+            Value::Meta(meta, v) => elements(meta).max(elements(v)).max(1),
+            _ => 0,
+        }
+    }
+
+    /// StreamReader のイベントから値を組み立てる
+    fn collect_events(reader: &mut StreamReader<SliceRead<'_>>) -> Result<Value> {
+        let mut next = || reader.next_event().and_then(|e| e.ok_or_else(|| Error::new(ErrorCode::NotFoundTarget)));
+        let mut stack: Vec<(Event, Vec<Value>, Vec<KeyValue>)> = Vec::new();
+        loop {
+            let value = match next()? {
+                Event::Scalar(v) => v,
+                event @ (Event::StartArray(_) | Event::StartObject(_) | Event::StartMeta) => {
+                    stack.push((event, Vec::new(), Vec::new()));
+                    continue;
+                }
+                Event::Key(key) => {
+                    stack.last_mut().unwrap().2.push(key);
+                    continue;
+                }
+                Event::EndArray | Event::EndObject | Event::EndMeta => {
+                    let (start, mut values, keys) = stack.pop().unwrap();
+                    match start {
+                        Event::StartArray(_) => Value::Array(values),
+                        Event::StartObject(_) => Value::Object(keys.into_iter().zip(values).collect()),
+                        _ => {
+                            let v = values.pop().unwrap();
+                            Value::Meta(Box::new(values.pop().unwrap()), Box::new(v))
+                        }
+                    }
+                }
+            };
+            match stack.last_mut() {
+                Some((_, values, _)) => values.push(value),
+                None => return Ok(value),
+            }
+        }
+    }
+
+    /// どの decoder も panic せずに Ok か Err を返す
+    fn decode_all(bytes: &[u8]) {
+        let _ = from_slice_with_limits::<Value>(bytes, Limits::untrusted());
+        if let Ok(forward) = reverse_to_forward(bytes) {
+            // 並べ替えられた値は元に戻せる
+            let reverse = forward_to_reverse(&forward).unwrap();
+            assert_eq!(reverse_to_forward(&reverse).unwrap(), forward);
+        }
+        let _ = forward_to_reverse(bytes);
+        if let Ok(reader) = StreamReader::new(SliceRead::new(bytes)) {
+            for event in reader.take(4096) {
+                if event.is_err() {
+                    break;
+                }
+            }
+        }
+        if let Ok(view) = TonView::new(bytes) {
+            let _ = view.meta();
+            let _ = view.len();
+            let _ = view.get("a");
+            let _ = view.get_index(0);
+            let _ = view.pointer("/a/0");
+            let _ = view.to_value();
+        }
+    }
+
+    /// decoder ごとに読んだ値が from_slice と同じになる
+    fn check_decoders(value: &Value, mode: u8) -> core::result::Result<(), TestCaseError> {
+        let bytes = encode(value, mode);
+        let decoded = from_slice_with_limits::<Value>(&bytes, Limits { max_depth: usize::MAX, ..Limits::new() }).unwrap();
+
+        let view = TonView::new(&bytes).unwrap();
+        // TonView::to_value は既定の Limits で読む
+        let within_limit = depth(&decoded) <= Limits::DEFAULT_MAX_DEPTH;
+        if within_limit {
+            prop_assert_eq!(&view.to_value().unwrap(), &decoded);
+        } else {
+            prop_assert!(view.to_value().unwrap_err().is_limit());
+        }
+        match decoded.clone() {
+            _ if !within_limit => {}
+            Value::Array(values) => {
+                prop_assert_eq!(view.len().unwrap(), Some(values.len()));
+                for (i, v) in values.iter().enumerate() {
+                    prop_assert_eq!(&view.get_index(i).unwrap().unwrap().to_value().unwrap(), v);
+                }
+                prop_assert!(view.get_index(values.len()).unwrap().is_none());
+            }
+            Value::Object(map) => {
+                prop_assert_eq!(view.len().unwrap(), Some(map.len()));
+                for (k, v) in map.iter() {
+                    if let KeyValue::String(k) = k {
+                        prop_assert_eq!(&view.get(k).unwrap().unwrap().to_value().unwrap(), v);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        let mut reader = StreamReader::new(SliceRead::new(&bytes)).unwrap();
+        prop_assert_eq!(&collect_events(&mut reader).unwrap(), &decoded);
+        prop_assert!(reader.next_event().unwrap().is_none());
+
+        match reverse_to_forward(&bytes) {
+            Ok(forward) => {
+                prop_assert_eq!(forward_value_size(&forward).unwrap(), Some(forward.len()));
+                // ReverseSerializer は PADDING を書かないので元のバイト列に戻る
+                prop_assert_eq!(forward_to_reverse(&forward).unwrap(), bytes);
+            }
+            Err(e) => {
+                prop_assert!(e.is_limit());
+                prop_assert!(depth(&decoded) > Limits::DEFAULT_MAX_DEPTH);
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(value in value(), mode in 0..3u8) {
+            let bytes = encode(&value, mode);
+            let decoded = from_slice_with_limits::<Value>(&bytes, Limits::untrusted()).unwrap();
+            if mode == 0 {
+                prop_assert_eq!(decoded, value);
+            } else {
+                // compact と canonical は数値の幅や -0.0 が変わるので、もう一度書いて比べる
+                prop_assert_eq!(encode(&decoded, mode), bytes);
+            }
+        }
+
+        #[test]
+        fn test_depth_limit(value in value()) {
+            let bytes = encode(&value, 0);
+            let max_depth = depth(&value);
+            let limits = Limits { max_depth, ..Limits::new() };
+            prop_assert!(from_slice_with_limits::<Value>(&bytes, limits).is_ok());
+            if max_depth > 0 {
+                let err = from_slice_with_limits::<Value>(&bytes, Limits { max_depth: max_depth - 1, ..Limits::new() }).unwrap_err();
+                prop_assert!(err.is_limit());
+            }
+        }
+
+        #[test]
+        fn test_element_limit(value in value()) {
+            let bytes = encode(&value, 0);
+            let max_elements = elements(&value);
+            let limits = Limits { max_elements, ..Limits::new() };
+            prop_assert!(from_slice_with_limits::<Value>(&bytes, limits).is_ok());
+            if max_elements > 0 {
+                let err = from_slice_with_limits::<Value>(&bytes, Limits { max_elements: max_elements - 1, ..Limits::new() }).unwrap_err();
+                prop_assert!(err.is_limit());
+            }
+        }
+
+        #[test]
+        fn test_decoders_agree(value in value(), mode in 0..3u8) {
+            check_decoders(&value, mode)?;
+        }
+
+        #[test]
+        fn test_deep_value(value in deep_value(), mode in 0..3u8) {
+            check_decoders(&value, mode)?;
+            let bytes = encode(&value, mode);
+            let decoded = from_slice_with_limits::<Value>(&bytes, Limits::new());
+            prop_assert_eq!(decoded.is_ok(), depth(&value) <= Limits::DEFAULT_MAX_DEPTH);
+        }
+
+        #[test]
+        fn test_random_bytes(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+            decode_all(&bytes);
+        }
+
+        #[test]
+        fn test_mutated_output(
+            value in value(),
+            mode in 0..3u8,
+            mutations in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..8),
+            cut in any::<prop::sample::Index>(),
+        ) {
+            let mut bytes = encode(&value, mode);
+            // prefix や size フィールドに当たると、入力より大きな size や不明な型になる
+            for (index, byte) in mutations {
+                let len = bytes.len();
+                bytes[index.index(len)] = byte;
+            }
+            decode_all(&bytes);
+            let len = bytes.len();
+            decode_all(&bytes[cut.index(len)..]);
+            decode_all(&bytes[..cut.index(len)]);
+        }
+    }
+
+    proptest! {
+        // 大きな入力は 1 件が重いので件数を減らす
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn test_large_value(value in large_value(), mode in 0..3u8) {
+            check_decoders(&value, mode)?;
+        }
+
+        #[test]
+        fn test_large_random_bytes(bytes in prop::collection::vec(any::<u8>(), 0..65536)) {
+            decode_all(&bytes);
+        }
+
+        #[test]
+        fn test_mutated_large_output(
+            value in prop_oneof![large_value(), deep_value()],
+            mode in 0..3u8,
+            mutations in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..32),
+        ) {
+            let mut bytes = encode(&value, mode);
+            for (index, byte) in mutations {
+                let len = bytes.len();
+                bytes[index.index(len)] = byte;
+            }
+            decode_all(&bytes);
+        }
+    }
+}
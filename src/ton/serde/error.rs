@@ -1,6 +1,8 @@
-use core::fmt;
-use std::{fmt::{Debug, Display}, io, result};
-use std::str::FromStr;
+use core::{fmt::{self, Debug, Display}, result};
+#[cfg(feature = "std")]
+use std::io;
+
+use alloc::{vec::Vec, string::{String, ToString}, boxed::Box};
 
 use serde::{de, ser};
 
@@ -11,16 +13,57 @@ pub struct Error {
 pub type Result<T> = result::Result<T, Error>;
 
 impl Error {
+    /// エラーが起きた byte の位置
+    ///
+    /// 位置が分からない場合は 0 を返します
+    ///
+    /// return: usize
     pub fn pos(&self) -> usize {
+        self.err.pos.unwrap_or(0)
+    }
+
+    /// エラーが起きた byte の位置
+    ///
+    /// return: Option<usize> // 位置が分からない場合は None
+    pub fn offset(&self) -> Option<usize> {
         self.err.pos
     }
 
+    /// エラーが起きた値の document 内の位置
+    ///
+    /// `.a.b[3]` のように Object の key を `.key`、Array の index を `[3]` で表します
+    /// 識別子にならない key は `["a.b"]` のように書きます
+    /// 最上位の値の場合は空文字列になります
+    ///
+    /// return: String
+    pub fn path(&self) -> String {
+        self.err.path()
+    }
+
+    /// エラーの詳しい原因
+    ///
+    /// return: &ErrorCode
+    pub fn code(&self) -> &ErrorCode {
+        &self.err.code
+    }
+
     pub fn classify(&self) -> Category {
         match &self.err.code {
             ErrorCode::Message(_) => Category::InvalidType,
+            #[cfg(feature = "std")]
             ErrorCode::Io(_) => Category::Io,
             ErrorCode::NotFoundTarget => Category::Eof,
             ErrorCode::InvalidType => Category::InvalidType,
+            ErrorCode::TruncatedHeader => Category::Eof,
+            ErrorCode::BadSizePrefix(_) => Category::Syntax,
+            ErrorCode::InvalidUtf8 => Category::Syntax,
+            ErrorCode::UnknownPrefix(_) => Category::UnknownFormat,
+            ErrorCode::SizeOverflow => Category::Syntax,
+            ErrorCode::TrailingBytes => Category::Syntax,
+            ErrorCode::DepthLimit => Category::Limit,
+            ErrorCode::AllocLimit => Category::Limit,
+            ErrorCode::StringLimit => Category::Limit,
+            ErrorCode::ElementLimit => Category::Limit,
         }
     }
 
@@ -44,18 +87,89 @@ impl Error {
         self.classify() == Category::UnknownFormat
     }
 
+    pub fn is_limit(&self) -> bool {
+        self.classify() == Category::Limit
+    }
+
 }
 
 pub struct ErrorImpl {
     code: ErrorCode,
-    pos: usize,
+    pos: Option<usize>,
+    /// 内側の値から順に並べた document 内の位置
+    path: Vec<PathSegment>,
 }
 
-pub(crate) enum ErrorCode {
+/// エラーの詳しい原因
+///
+/// Error::code で取り出せます
+/// 今後も種類が増えるので、match では `_` の腕が必要です
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// serde の custom エラーなどのメッセージ
     Message(String),
+    /// reader や writer の IO エラー
+    #[cfg(feature = "std")]
     Io(io::Error),
+    /// 読む位置に値が無い
     NotFoundTarget,
+    /// 要求された型として読めない
     InvalidType,
+    /// size フィールドが入力の先頭より前にはみ出している
+    TruncatedHeader,
+    /// 型に対して使えない size prefix
+    BadSizePrefix(u8),
+    /// String の body が UTF-8 でない
+    InvalidUtf8,
+    /// どの型にも当たらない prefix
+    UnknownPrefix(u8),
+    /// body のサイズが入力や親の container に収まらない
+    SizeOverflow,
+    /// 値の前に読まれないバイトが残っている
+    TrailingBytes,
+    /// container の入れ子が Limits::max_depth を超えた
+    DepthLimit,
+    /// 確保するバイト数の合計が Limits::max_alloc を超えた
+    AllocLimit,
+    /// String や Bytes の長さが Limits::max_string_len を超えた
+    StringLimit,
+    /// container の要素数が Limits::max_elements を超えた
+    ElementLimit,
+}
+
+impl ErrorImpl {
+    /// path を `.a.b[3]` の形にする
+    ///
+    /// return: String
+    fn path(&self) -> String {
+        let mut out = String::new();
+        for segment in self.path.iter().rev() {
+            match segment {
+                PathSegment::Key(key) if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                    out.push('.');
+                    out.push_str(key);
+                }
+                PathSegment::Key(key) => {
+                    out.push('[');
+                    out.push_str(&serde_json::to_string(key).unwrap_or_default());
+                    out.push(']');
+                }
+                PathSegment::Index(index) => {
+                    out.push('[');
+                    out.push_str(&index.to_string());
+                    out.push(']');
+                }
+            }
+        }
+        out
+    }
+}
+
+/// document 内の位置の 1 段
+enum PathSegment {
+    Key(String),
+    Index(usize),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -78,6 +192,11 @@ pub enum Category {
     /// 
     /// ファイルの終端に達した場合
     Eof,
+
+    /// 資源の上限
+    /// 
+    /// デコーダーに設定した Limits を超えた場合
+    Limit,
 }
 
 impl Error {
@@ -86,41 +205,88 @@ impl Error {
         Self {
             err: Box::new(ErrorImpl {
                 code,
-                pos,
+                pos: Some(pos),
+                path: Vec::new(),
             }),
         }
     }
 
+    /// 位置を持たないエラーを作る
+    ///
+    /// code: ErrorCode
+    ///
+    /// return: Error
     #[cold]
-    pub(crate) fn io(error: io::Error) -> Self {
-        Error {
+    pub(crate) fn new(code: ErrorCode) -> Self {
+        Self {
             err: Box::new(ErrorImpl {
-                code: ErrorCode::Io(error),
-                pos: 0,
+                code,
+                pos: None,
+                path: Vec::new(),
             }),
         }
     }
 
+    #[cfg(feature = "std")]
     #[cold]
-    pub(crate) fn fix_position<F>(self, f: F) -> Self
-    where
-        F: FnOnce(ErrorCode) -> Error,
-    {
-        if self.err.pos == 0 {
-            f(self.err.code)
-        } else {
-            self
-        }
+    pub(crate) fn io(error: io::Error) -> Self {
+        Self::new(ErrorCode::Io(error))
+    }
+
+    /// 位置が分からないエラーに pos を設定する
+    ///
+    /// 内側で設定された位置の方が正確なので上書きはしません
+    ///
+    /// pos: usize
+    ///
+    /// return: Error
+    #[cold]
+    pub(crate) fn at(mut self, pos: usize) -> Self {
+        self.err.pos.get_or_insert(pos);
+        self
+    }
+
+    /// エラーが Object の key の値の中で起きたことを記録する
+    ///
+    /// key: String
+    ///
+    /// return: Error
+    #[cold]
+    pub(crate) fn in_key(mut self, key: String) -> Self {
+        self.err.path.push(PathSegment::Key(key));
+        self
+    }
+
+    /// エラーが Array の index 番目の要素の中で起きたことを記録する
+    ///
+    /// index: usize
+    ///
+    /// return: Error
+    #[cold]
+    pub(crate) fn in_index(mut self, index: usize) -> Self {
+        self.err.path.push(PathSegment::Index(index));
+        self
     }
 }
 
 impl Display for ErrorCode {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             ErrorCode::Message(msg) => f.write_str(msg),
+            #[cfg(feature = "std")]
             ErrorCode::Io(err) => Display::fmt(err, f),
             ErrorCode::NotFoundTarget => f.write_str("Not found target"),
             ErrorCode::InvalidType => f.write_str("Invalid type"),
+            ErrorCode::TruncatedHeader => f.write_str("Truncated header"),
+            ErrorCode::BadSizePrefix(byte) => write!(f, "Bad size prefix in 0x{:02x}", byte),
+            ErrorCode::InvalidUtf8 => f.write_str("Invalid UTF-8"),
+            ErrorCode::UnknownPrefix(byte) => write!(f, "Unknown prefix 0x{:02x}", byte),
+            ErrorCode::SizeOverflow => f.write_str("Size overflow"),
+            ErrorCode::TrailingBytes => f.write_str("Trailing bytes"),
+            ErrorCode::DepthLimit => f.write_str("Depth limit exceeded"),
+            ErrorCode::AllocLimit => f.write_str("Allocation limit exceeded"),
+            ErrorCode::StringLimit => f.write_str("String length limit exceeded"),
+            ErrorCode::ElementLimit => f.write_str("Element count limit exceeded"),
         }
     }
 }
@@ -129,9 +295,10 @@ impl Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Error {{ code: {:?}, pos: {} }}",
+            "Error {{ code: {:?}, pos: {:?}, path: {:?} }}",
             self.err.code.to_string(),
-            self.err.pos
+            self.err.pos,
+            self.path()
         )
     }
 }
@@ -145,12 +312,15 @@ impl Display for Error {
 }
 
 impl Display for ErrorImpl {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {  
-        if self.pos == 0 {
-            Display::fmt(&self.code, f)
-        } else {
-            write!(f, "{} at position {}", self.code, self.pos)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.code, f)?;
+        if !self.path.is_empty() {
+            write!(f, " at `{}`", self.path())?;
+        }
+        if let Some(pos) = self.pos {
+            write!(f, " at position {}", pos)?;
         }
+        Ok(())
     }
 }
 
@@ -168,37 +338,6 @@ impl ser::Error for Error {
     }
 }
 
-fn make_error(mut msg: String) -> Error {
-    let pos = parse_pos(&mut msg).unwrap_or(0);
-    Error {
-        err: Box::new(ErrorImpl {
-            code: ErrorCode::Message(msg),
-            pos,
-        }),
-    }
+fn make_error(msg: String) -> Error {
+    Error::new(ErrorCode::Message(msg))
 }
-
-fn parse_pos(msg: &mut String) -> Option<usize> {
-    let start_of_suffix = match msg.rfind(" at pos ") {
-        Some(index) => index,
-        None => return None, // " at pos " が見つからなければ解析できない
-    };
-
-    // " at pos " の直後にある数値を探す
-    let start_of_pos = start_of_suffix + " at pos ".len();
-    let mut end_of_pos = start_of_pos;
-
-    while msg[end_of_pos..].starts_with(|c: char| c.is_ascii_digit()) {
-        end_of_pos += 1;
-    }
-
-    // 数値を `usize` に変換
-    let pos = match usize::from_str(&msg[start_of_pos..end_of_pos]) {
-        Ok(pos) => pos,
-        Err(_) => return None, // 数値のパースに失敗したら None を返す
-    };
-
-    // `msg` から " at pos X" を削除
-    msg.truncate(start_of_suffix);
-    Some(pos)
-}
\ No newline at end of file
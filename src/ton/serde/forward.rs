@@ -0,0 +1,1009 @@
+//! Forward TON
+//!
+//! ReverseSerializer は body の後ろに反転した header を書くので、末尾から読む必要があります
+//! Forward TON は header を body の前に置いたレイアウトで、先頭から順に読めます
+//!
+//! - header は prefix, size フィールド (little endian) の順に並びます
+//! - body は Reverse TON と同じで、各要素のサイズも変わりません
+//! - Object の要素は key, value の順に並びます
+//! - Meta の body は metadata, 値 の順に並びます
+//!
+//! 先頭の header だけで値全体のサイズが分かるので、
+//! ネットワークや追記型のログでは受信しきる前に値の区切りを判定できます
+
+use alloc::vec::Vec;
+
+use serde::{ser, Serialize, Serializer};
+
+use super::{de::{decode_prefix, prefix_error, Deserializer, Limits, SliceRead, TonTypes}, error::{Error, ErrorCode, Result}, ser::{generate_header, ReverseSerializer, Write}, value::prefix::{prefix, prefix_pua_utf8, size_prefix::SIZE_PREFIX_8BYTE}};
+
+/// Forward TON シリアライザー
+///
+/// 値ごとに 2 回シリアライズします
+/// 1 回目は何も書かずに container の body のサイズを数え、2 回目はそのサイズで header を先に書きながら writer に流すので、
+/// 値全体をメモリに溜めずに書き込めます
+/// Serialize は 2 回呼ぶので、同じ値からは同じ順で要素を出す必要があります
+/// 値は続けて書き込めるので、追記型のログにそのまま使えます
+pub struct ForwardSerializer<W>
+where
+    W: Write,
+{
+    writer: W,
+    // container の body のサイズ (開いた順)
+    sizes: Vec<usize>,
+    // 末端の値を ReverseSerializer で書くバッファ
+    leaf: Vec<u8>,
+}
+
+impl<W> ForwardSerializer<W>
+where
+    W: Write,
+{
+    /// 新しいForwardSerializerを作る
+    ///
+    /// writer: W
+    ///
+    /// return: ForwardSerializer
+    #[inline]
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            sizes: Vec::new(),
+            leaf: Vec::with_capacity(256/*default capacity*/),
+        }
+    }
+
+    /// 値を 1 つ書き込む
+    ///
+    /// value: &T
+    ///
+    /// return: Result<()>
+    pub fn serialize<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.sizes.clear();
+        // サイズを数えるだけで書き込まない
+        value.serialize(&mut Encoder::<W>::new(None, &mut self.sizes, &mut self.leaf))?;
+        // 数えたサイズで header を先に書く
+        value.serialize(&mut Encoder::new(Some(&mut self.writer), &mut self.sizes, &mut self.leaf))
+    }
+
+    /// writerを取り出す
+    ///
+    /// return: W
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// ForwardSerializer の 1 回分のシリアライズ
+///
+/// writer が None の場合は container の body のサイズを sizes に積むだけで、何も書き込みません
+/// 末端の値は ReverseSerializer で書いてから header を前に付け替えるので、body は Reverse TON と同じになります
+struct Encoder<'w, W>
+where
+    W: Write,
+{
+    writer: Option<&'w mut W>,
+    sizes: &'w mut Vec<usize>,
+    leaf: &'w mut Vec<u8>,
+    // 次に開く container の sizes の位置
+    next: usize,
+    // ここまでに書いたサイズ
+    size: usize,
+    // 次の tuple を META として書く
+    meta: bool,
+}
+
+/// 開いている container
+#[derive(Clone, Copy)]
+struct Open {
+    prefix: u8,
+    // sizes の位置
+    index: usize,
+    // body の先頭の位置
+    start: usize,
+}
+
+impl<'w, W> Encoder<'w, W>
+where
+    W: Write,
+{
+    #[inline]
+    fn new(writer: Option<&'w mut W>, sizes: &'w mut Vec<usize>, leaf: &'w mut Vec<u8>) -> Self {
+        Self {
+            writer,
+            sizes,
+            leaf,
+            next: 0,
+            size: 0,
+            meta: false,
+        }
+    }
+
+    /// 末端の値を 1 つ書く
+    ///
+    /// f: F // ReverseSerializer に値を書く
+    ///
+    /// return: Result<()>
+    fn write_leaf<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut ReverseSerializer<&mut Vec<u8>>) -> Result<()>,
+    {
+        self.meta = false;
+        self.leaf.clear();
+        f(&mut ReverseSerializer::new(&mut *self.leaf))?;
+        self.size += self.leaf.len();
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        let pos = self.leaf.len() - 1;
+        let byte = self.leaf[pos];
+        let header_size = match decode_prefix(byte).ok_or_else(|| prefix_error(byte, pos))? {
+            // TON 固有の型の newtype に container を渡された場合
+            (TonTypes::Array | TonTypes::Object | TonTypes::Meta, _) => return writer.write_all(&reverse_to_forward(self.leaf)?),
+            (_, Some(_)) => 1,
+            (_, None) => 1 + (1usize << (byte & SIZE_PREFIX_8BYTE)),
+        };
+        // 反転した header を戻して body の前に書く
+        let (body, header) = self.leaf.split_at(self.leaf.len() - header_size);
+        let mut forward = [0u8; 9];
+        forward[..header_size].iter_mut().zip(header.iter().rev()).for_each(|(d, s)| *d = *s);
+        writer.write_all(&forward[..header_size])?;
+        writer.write_all(body)
+    }
+
+    /// container を開く
+    ///
+    /// 書き込む場合は、数えておいた body のサイズで header を書きます
+    ///
+    /// prefix: u8
+    ///
+    /// return: Result<Open>
+    fn open(&mut self, prefix: u8) -> Result<Open> {
+        self.meta = false;
+        let index = self.next;
+        self.next += 1;
+        match self.writer.as_mut() {
+            None => self.sizes.push(0),
+            Some(writer) => {
+                let body_size = *self.sizes.get(index).ok_or_else(changed)?;
+                let (header, header_size) = generate_header(prefix, body_size);
+                writer.write_all(&header[..header_size])?;
+                self.size += header_size;
+            }
+        }
+        Ok(Open { prefix, index, start: self.size })
+    }
+
+    /// container を閉じる
+    ///
+    /// サイズを数えている場合は body のサイズを記録して、後から書く header の分を足します
+    ///
+    /// open: Open
+    ///
+    /// return: Result<()>
+    fn close(&mut self, open: Open) -> Result<()> {
+        let body_size = self.size - open.start;
+        match self.writer {
+            None => {
+                self.sizes[open.index] = body_size;
+                self.size += generate_header(open.prefix, body_size).1;
+            }
+            // header を書いた後なので、サイズが変わった値は書き直せない
+            Some(_) if self.sizes[open.index] != body_size => return Err(changed()),
+            Some(_) => {}
+        }
+        Ok(())
+    }
+
+    /// enum の variant を包む Object を開いて variant 名を書く
+    ///
+    /// variant: &'static str
+    ///
+    /// return: Result<Open>
+    fn open_variant(&mut self, variant: &'static str) -> Result<Open> {
+        let outer = self.open(prefix::OBJECT)?;
+        self.write_leaf(|ser| ser.serialize_str(variant))?;
+        Ok(outer)
+    }
+}
+
+/// 2 回のシリアライズで値が変わった場合のエラー
+#[cold]
+fn changed() -> Error {
+    ser::Error::custom("value changed between the size pass and the write pass")
+}
+
+impl<'a, 'w, W> ser::Serializer for &'a mut Encoder<'w, W>
+where
+    W: Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Container<'a, 'w, W>;
+    type SerializeTuple = Container<'a, 'w, W>;
+    type SerializeTupleStruct = Container<'a, 'w, W>;
+    type SerializeTupleVariant = Container<'a, 'w, W>;
+    type SerializeMap = Container<'a, 'w, W>;
+    type SerializeStruct = Container<'a, 'w, W>;
+    type SerializeStructVariant = Container<'a, 'w, W>;
+
+    #[inline]
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_bool(v))
+    }
+
+    #[inline]
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_i8(v))
+    }
+
+    #[inline]
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_i16(v))
+    }
+
+    #[inline]
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_i32(v))
+    }
+
+    #[inline]
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_i64(v))
+    }
+
+    #[inline]
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_i128(v))
+    }
+
+    #[inline]
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_u8(v))
+    }
+
+    #[inline]
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_u16(v))
+    }
+
+    #[inline]
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_u32(v))
+    }
+
+    #[inline]
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_u64(v))
+    }
+
+    #[inline]
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_u128(v))
+    }
+
+    #[inline]
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_f32(v))
+    }
+
+    #[inline]
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_f64(v))
+    }
+
+    #[inline]
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_char(v))
+    }
+
+    #[inline]
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_str(v))
+    }
+
+    #[inline]
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_bytes(v))
+    }
+
+    #[inline]
+    fn serialize_none(self) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_none())
+    }
+
+    #[inline]
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_unit(self) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_unit())
+    }
+
+    #[inline]
+    fn serialize_unit_struct(self, name: &'static str) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_unit_struct(name))
+    }
+
+    #[inline]
+    fn serialize_unit_variant(self, name: &'static str, variant_index: u32, variant: &'static str) -> Result<()> {
+        self.write_leaf(|ser| ser.serialize_unit_variant(name, variant_index, variant))
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        match name {
+            // META は (metadata, 値) の tuple を container として書く
+            prefix_pua_utf8::META => {
+                self.meta = true;
+                let result = value.serialize(&mut *self);
+                self.meta = false;
+                result
+            }
+            // その他の TON 固有の型は末端の値
+            prefix_pua_utf8::UUID
+            | prefix_pua_utf8::DATETIME
+            | prefix_pua_utf8::TIMESTAMP
+            | prefix_pua_utf8::DURATION
+            | prefix_pua_utf8::FLOAT
+            | prefix_pua_utf8::WRAPPED_JSON => self.write_leaf(|ser| ser.serialize_newtype_struct(name, value)),
+            _ => value.serialize(self),
+        }
+    }
+
+    fn serialize_newtype_variant<T>(self, _name: &'static str, _variant_index: u32, variant: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let outer = self.open_variant(variant)?;
+        value.serialize(&mut *self)?;
+        self.close(outer)
+    }
+
+    #[inline]
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Container::new(self, prefix::ARRAY, None)
+    }
+
+    #[inline]
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        let prefix = match core::mem::take(&mut self.meta) {
+            true => prefix::META,
+            false => prefix::ARRAY,
+        };
+        Container::new(self, prefix, None)
+    }
+
+    #[inline]
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> {
+        Container::new(self, prefix::ARRAY, None)
+    }
+
+    #[inline]
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant> {
+        let outer = self.open_variant(variant)?;
+        Container::new(self, prefix::ARRAY, Some(outer))
+    }
+
+    #[inline]
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Container::new(self, prefix::OBJECT, None)
+    }
+
+    #[inline]
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Container::new(self, prefix::OBJECT, None)
+    }
+
+    #[inline]
+    fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant> {
+        let outer = self.open_variant(variant)?;
+        Container::new(self, prefix::OBJECT, Some(outer))
+    }
+}
+
+/// Encoder の container
+///
+/// Forward TON では Object の要素も key, value の順なので、受け取った順にそのまま書きます
+struct Container<'a, 'w, W>
+where
+    W: Write,
+{
+    encoder: &'a mut Encoder<'w, W>,
+    inner: Open,
+    // enum の variant を包む Object
+    outer: Option<Open>,
+}
+
+impl<'a, 'w, W> Container<'a, 'w, W>
+where
+    W: Write,
+{
+    #[inline]
+    fn new(encoder: &'a mut Encoder<'w, W>, prefix: u8, outer: Option<Open>) -> Result<Self> {
+        let inner = encoder.open(prefix)?;
+        Ok(Self { encoder, inner, outer })
+    }
+
+    #[inline]
+    fn element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut *self.encoder)
+    }
+
+    #[inline]
+    fn close(self) -> Result<()> {
+        self.encoder.close(self.inner)?;
+        match self.outer {
+            Some(outer) => self.encoder.close(outer),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, 'w, W> ser::SerializeSeq for Container<'a, 'w, W>
+where
+    W: Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl<'a, 'w, W> ser::SerializeTuple for Container<'a, 'w, W>
+where
+    W: Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl<'a, 'w, W> ser::SerializeTupleStruct for Container<'a, 'w, W>
+where
+    W: Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl<'a, 'w, W> ser::SerializeTupleVariant for Container<'a, 'w, W>
+where
+    W: Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl<'a, 'w, W> ser::SerializeMap for Container<'a, 'w, W>
+where
+    W: Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(key)
+    }
+
+    #[inline]
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl<'a, 'w, W> ser::SerializeStruct for Container<'a, 'w, W>
+where
+    W: Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(key)?;
+        self.element(value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl<'a, 'w, W> ser::SerializeStructVariant for Container<'a, 'w, W>
+where
+    W: Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(key)?;
+        self.element(value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+/// Reverse TON を Forward TON に並べ替える
+///
+/// input 全体が 1 つの値である必要があります
+/// PADDING は読み飛ばすので出力には含まれず、container の size は書いた分に直します
+///
+/// input: &[u8]
+///
+/// return: Result<Vec<u8>>
+pub fn reverse_to_forward(input: &[u8]) -> Result<Vec<u8>> {
+    let mut de = Deserializer::from_tail(SliceRead::new(input))?;
+    de.end()?;
+    let root = de.pos();
+    let mut out = Vec::with_capacity(input.len());
+    write_forward(&mut de, input, root, &mut out)?;
+    Ok(out)
+}
+
+/// Forward TON を Reverse TON に並べ替える
+///
+/// input 全体が 1 つの値である必要があります
+///
+/// input: &[u8]
+///
+/// return: Result<Vec<u8>>
+pub fn forward_to_reverse(input: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len());
+    let end = write_reverse(input, 0, 0, &mut out)?;
+    if end != input.len() {
+        return Err(Error::syntax(ErrorCode::TrailingBytes, end));
+    }
+    Ok(out)
+}
+
+/// 先頭にある Forward TON の値全体のサイズを返す
+///
+/// header だけで判定できるので、受信途中のバッファにも使えます
+/// header が揃っていない場合は None を返します
+///
+/// input: &[u8]
+///
+/// return: Result<Option<usize>>
+pub fn forward_value_size(input: &[u8]) -> Result<Option<usize>> {
+    match read_forward_header(input, 0) {
+        Ok((_, header_size, body_size)) => Ok(Some(header_size + body_size)),
+        Err(e) if e.is_eof() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reverse TON の値を Forward TON で書き出す
+///
+/// de: &mut Deserializer<SliceRead<'_>> // header と要素の位置を読むのに使う、入れ子の深さも de の Limits で制限する
+/// input: &[u8]
+/// pos: usize // prefix の位置
+/// out: &mut Vec<u8>
+///
+/// return: Result<()>
+fn write_forward(de: &mut Deserializer<SliceRead<'_>>, input: &[u8], pos: usize, out: &mut Vec<u8>) -> Result<()> {
+    let header = de.read_header(pos)?;
+    // 反転した header をそのまま戻す
+    let header_start = out.len();
+    out.extend(input[header.body_end(pos)..=pos].iter().rev());
+    let body_start = out.len();
+    match header.ton_type {
+        TonTypes::Array | TonTypes::Meta => de.nested(pos, |de| {
+            for elem_pos in de.read_elements(pos, &header)? {
+                write_forward(de, input, elem_pos, out)?;
+            }
+            Ok(())
+        })?,
+        TonTypes::Object => de.nested(pos, |de| {
+            // value, key の順から key, value の順にする
+            for pair in de.read_elements(pos, &header)?.chunks(2) {
+                match *pair {
+                    [value_pos, key_pos] => {
+                        write_forward(de, input, key_pos, out)?;
+                        write_forward(de, input, value_pos, out)?;
+                    }
+                    _ => return Err(Error::syntax(ErrorCode::InvalidType, pos)),
+                }
+            }
+            Ok(())
+        })?,
+        _ => {
+            out.extend_from_slice(&input[header.body_start(pos)..header.body_end(pos)]);
+            return Ok(());
+        }
+    }
+    // 内側の PADDING は書かないので、size フィールドを書いたサイズに直す
+    // 元のサイズ以下なので同じ幅に収まる
+    let body_size = (out.len() - body_start) as u64;
+    let size_field = &mut out[header_start + 1..body_start];
+    let width = size_field.len();
+    size_field.copy_from_slice(&body_size.to_le_bytes()[..width]);
+    Ok(())
+}
+
+/// Forward TON の値を Reverse TON で書き出す
+///
+/// input: &[u8]
+/// pos: usize // header の先頭の位置
+/// depth: usize // 値の入れ子の深さ
+/// out: &mut Vec<u8>
+///
+/// return: Result<usize> // 値の終端の位置
+fn write_reverse(input: &[u8], pos: usize, depth: usize, out: &mut Vec<u8>) -> Result<usize> {
+    let (ton_type, header_size, body_size) = read_forward_header(input, pos)?;
+    if matches!(ton_type, TonTypes::Array | TonTypes::Object | TonTypes::Meta) && depth >= Limits::DEFAULT_MAX_DEPTH {
+        return Err(Error::syntax(ErrorCode::DepthLimit, pos));
+    }
+    let body_start = pos + header_size;
+    let body_end = body_start.checked_add(body_size)
+        .filter(|end| *end <= input.len())
+        .ok_or_else(|| Error::syntax(ErrorCode::SizeOverflow, pos))?;
+    match ton_type {
+        TonTypes::Array | TonTypes::Meta => {
+            let mut cur = body_start;
+            while cur < body_end {
+                cur = write_reverse(input, cur, depth + 1, out)?;
+            }
+            if cur != body_end {
+                return Err(Error::syntax(ErrorCode::SizeOverflow, pos));
+            }
+        }
+        TonTypes::Object => {
+            // key, value の順から value, key の順にする
+            let mut cur = body_start;
+            let mut key = Vec::new();
+            while cur < body_end {
+                key.clear();
+                cur = write_reverse(input, cur, depth + 1, &mut key)?;
+                if cur >= body_end {
+                    return Err(Error::syntax(ErrorCode::InvalidType, pos));
+                }
+                cur = write_reverse(input, cur, depth + 1, out)?;
+                out.extend_from_slice(&key);
+            }
+            if cur != body_end {
+                return Err(Error::syntax(ErrorCode::SizeOverflow, pos));
+            }
+        }
+        _ => out.extend_from_slice(&input[body_start..body_end]),
+    }
+    out.extend(input[pos..body_start].iter().rev());
+    Ok(body_end)
+}
+
+/// Forward TON の header を読む
+///
+/// input: &[u8]
+/// pos: usize // header の先頭の位置
+///
+/// return: Result<(TonTypes, usize, usize)> // 型, header のサイズ, body のサイズ
+fn read_forward_header(input: &[u8], pos: usize) -> Result<(TonTypes, usize, usize)> {
+    let byte = *input.get(pos).ok_or_else(|| Error::syntax(ErrorCode::NotFoundTarget, pos))?;
+    match decode_prefix(byte).ok_or_else(|| prefix_error(byte, pos))? {
+        (ton_type, Some(body_size)) => Ok((ton_type, 1, body_size)),
+        (ton_type, None) => {
+            // size フィールドは little endian
+            let size_width = 1usize << (byte & SIZE_PREFIX_8BYTE);
+            let field = input.get(pos + 1..pos + 1 + size_width).ok_or_else(|| Error::syntax(ErrorCode::TruncatedHeader, pos))?;
+            let body_size = field.iter().rev().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+            let body_size = usize::try_from(body_size).map_err(|_| Error::syntax(ErrorCode::SizeOverflow, pos))?;
+            Ok((ton_type, 1 + size_width, body_size))
+        }
+    }
+}
+
+#[cfg(test)]
+mod forward_tests {
+    use core::cell::Cell;
+    use std::collections::BTreeMap;
+
+    use chrono::{TimeZone, Utc};
+    use half::f16;
+    use serde::Deserialize;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::ton::{from_slice, to_vec, Value};
+    use crate::ton::serde::value::{map::Map, num::{Float, Int, UInt}, value::KeyValue};
+    use crate::ton::serde::value::prefix::{prefix, size_prefix::{SIZE_PREFIX_1BYTE, SIZE_PREFIX_2BYTE}};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        id: u64,
+        name: String,
+        tags: Vec<String>,
+        attrs: BTreeMap<String, i32>,
+        parent: Option<Box<Record>>,
+    }
+
+    fn sample() -> Record {
+        Record {
+            id: 1,
+            name: "root".to_string(),
+            tags: vec!["a".to_string(), "b".to_string()],
+            attrs: [("x".to_string(), -1), ("y".to_string(), 2)].into_iter().collect(),
+            parent: Some(Box::new(Record {
+                id: 0,
+                name: "parent".to_string(),
+                tags: Vec::new(),
+                attrs: BTreeMap::new(),
+                parent: None,
+            })),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let reverse = to_vec(&sample()).unwrap();
+        let forward = reverse_to_forward(&reverse).unwrap();
+        assert_eq!(forward.len(), reverse.len());
+        assert_eq!(forward_to_reverse(&forward).unwrap(), reverse);
+        assert_eq!(from_slice::<Record>(&forward_to_reverse(&forward).unwrap()).unwrap(), sample());
+    }
+
+    #[test]
+    fn test_layout() {
+        let forward = reverse_to_forward(&to_vec("hi").unwrap()).unwrap();
+        assert_eq!(forward, vec![prefix::STRING, 2, b'h', b'i']);
+
+        // Object は key, value の順
+        let mut map = BTreeMap::new();
+        map.insert("k".to_string(), true);
+        let forward = reverse_to_forward(&to_vec(&map).unwrap()).unwrap();
+        assert_eq!(forward, vec![prefix::OBJECT, 4, prefix::STRING, 1, b'k', prefix::BOOL | 1]);
+    }
+
+    #[test]
+    fn test_forward_serializer_appends() {
+        let mut serializer = ForwardSerializer::new(Vec::new());
+        serializer.serialize(&sample()).unwrap();
+        serializer.serialize(&Value::String("next".to_string())).unwrap();
+        let stream = serializer.into_inner();
+
+        // 先頭の header だけで値の区切りが分かる
+        let first = forward_value_size(&stream).unwrap().unwrap();
+        let second = forward_value_size(&stream[first..]).unwrap().unwrap();
+        assert_eq!(first + second, stream.len());
+        assert_eq!(from_slice::<Record>(&forward_to_reverse(&stream[..first]).unwrap()).unwrap(), sample());
+        assert_eq!(
+            from_slice::<Value>(&forward_to_reverse(&stream[first..]).unwrap()).unwrap(),
+            Value::String("next".to_string())
+        );
+    }
+
+    #[derive(Serialize)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Point(i32, i32),
+        Rect { w: u16, h: u16 },
+    }
+
+    fn typed_value() -> Value {
+        let mut map = Map::new();
+        map.insert(KeyValue::String("owner".to_string()), Value::UUID(Uuid::from_u128(1)));
+        map.insert(KeyValue::String("at".to_string()), Value::DateTime(Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap()));
+        map.insert(KeyValue::String("ts".to_string()), Value::Timestamp(1_700_000_000));
+        map.insert(KeyValue::String("ttl".to_string()), Value::Duration(chrono::Duration::seconds(30)));
+        map.insert(KeyValue::String("half".to_string()), Value::Float(Float::F16(f16::from_f32(1.5))));
+        map.insert(KeyValue::String("gone".to_string()), Value::Undefined);
+        map.insert(KeyValue::String("raw".to_string()), Value::WrappedJSON(serde_json::json!({"k": [1, 2]})));
+        map.insert(KeyValue::String("big".to_string()), Value::Int(Int::I128(-(1 << 100))));
+        map.insert(KeyValue::UInt(UInt::U32(5)), Value::Bytes(vec![0; 300]));
+        map.insert(KeyValue::String("noted".to_string()), Value::Bool(true).with_meta(Value::String("m".to_string())));
+        Value::Object(map)
+    }
+
+    /// 書き込みの回数と 1 回の最大サイズを記録する writer
+    #[derive(Default)]
+    struct ChunkWriter {
+        out: Vec<u8>,
+        writes: usize,
+        max_chunk: usize,
+    }
+
+    impl Write for ChunkWriter {
+        fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+            self.out.extend_from_slice(buf);
+            self.writes += 1;
+            self.max_chunk = self.max_chunk.max(buf.len());
+            Ok(())
+        }
+    }
+
+    fn forward_of<T: ?Sized + Serialize>(value: &T) -> Vec<u8> {
+        let mut serializer = ForwardSerializer::new(Vec::new());
+        serializer.serialize(value).unwrap();
+        serializer.into_inner()
+    }
+
+    #[test]
+    fn test_forward_serializer_layout() {
+        // 並べ替えた Reverse TON と同じバイト列になる
+        assert_eq!(forward_of(&sample()), reverse_to_forward(&to_vec(&sample()).unwrap()).unwrap());
+        assert_eq!(forward_of(&typed_value()), reverse_to_forward(&to_vec(&typed_value()).unwrap()).unwrap());
+        let shapes = vec![Shape::Empty, Shape::Circle(0.5), Shape::Point(-1, 1 << 20), Shape::Rect { w: 3, h: 4 }];
+        assert_eq!(forward_of(&shapes), reverse_to_forward(&to_vec(&shapes).unwrap()).unwrap());
+        assert_eq!(from_slice::<Value>(&forward_to_reverse(&forward_of(&typed_value())).unwrap()).unwrap(), typed_value());
+    }
+
+    #[test]
+    fn test_forward_serializer_streams() {
+        // 値全体を溜めずに、header と末端の値ごとに書き込む
+        let lines: Vec<String> = (0..1000).map(|i| format!("{i:064}")).collect();
+        let mut serializer = ForwardSerializer::new(ChunkWriter::default());
+        serializer.serialize(&lines).unwrap();
+        let writer = serializer.into_inner();
+        assert!(writer.writes > lines.len());
+        assert_eq!(writer.max_chunk, 64);
+        assert_eq!(writer.out, reverse_to_forward(&to_vec(&lines).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_forward_serializer_changed_value() {
+        // 呼ぶたびに要素が増える値は header を書いた後でサイズが合わなくなる
+        struct Growing(Cell<usize>);
+        impl Serialize for Growing {
+            fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+                self.0.set(self.0.get() + 1);
+                serializer.collect_seq(0..self.0.get())
+            }
+        }
+        let mut serializer = ForwardSerializer::new(Vec::new());
+        assert!(serializer.serialize(&Growing(Cell::new(0))).is_err());
+    }
+
+    #[test]
+    fn test_padding_dropped() {
+        // Array の要素の間にある PADDING
+        let padding = [0, 0, 2, prefix::PADDING | SIZE_PREFIX_1BYTE];
+        let mut reverse = to_vec(&1u8).unwrap();
+        reverse.extend(padding);
+        reverse.extend(to_vec(&2u8).unwrap());
+        reverse.extend([8, prefix::ARRAY | SIZE_PREFIX_1BYTE]);
+        assert_eq!(from_slice::<Vec<u8>>(&reverse).unwrap(), vec![1, 2]);
+
+        let forward = reverse_to_forward(&reverse).unwrap();
+        let mut expected = vec![prefix::ARRAY | SIZE_PREFIX_1BYTE, 4];
+        expected.extend(to_vec(&1u8).unwrap().iter().rev());
+        expected.extend(to_vec(&2u8).unwrap().iter().rev());
+        assert_eq!(forward, expected);
+        assert_eq!(from_slice::<Vec<u8>>(&forward_to_reverse(&forward).unwrap()).unwrap(), vec![1, 2]);
+
+        // pad_to で要素の間に足した PADDING と、値の後ろの PADDING
+        let mut serializer = ReverseSerializer::new(Vec::new());
+        sample().serialize(&mut serializer).unwrap();
+        serializer.pad_to(512).unwrap();
+        "x".serialize(&mut serializer).unwrap();
+        let mut aligned = serializer.into_inner();
+        aligned.extend((aligned.len() as u16).to_be_bytes());
+        aligned.push(prefix::ARRAY | SIZE_PREFIX_2BYTE);
+        aligned.extend(padding);
+        assert_eq!(from_slice::<(Record, String)>(&aligned).unwrap(), (sample(), "x".to_string()));
+
+        let forward = reverse_to_forward(&aligned).unwrap();
+        assert_eq!(forward_value_size(&forward).unwrap(), Some(forward.len()));
+        let value: (Record, String) = from_slice(&forward_to_reverse(&forward).unwrap()).unwrap();
+        assert_eq!(value, (sample(), "x".to_string()));
+    }
+
+    #[test]
+    fn test_partial_input() {
+        let forward = reverse_to_forward(&to_vec(&sample()).unwrap()).unwrap();
+        assert_eq!(forward_value_size(&forward[..1]).unwrap(), None);
+        assert_eq!(forward_value_size(&forward[..2]).unwrap(), Some(forward.len()));
+        assert!(forward_to_reverse(&forward[..forward.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        // 外側から順に header を並べた、depth 段入れ子の空の Array
+        let deep = |depth: usize| {
+            let total = (depth - 1) * 9 + 2;
+            let mut bytes = Vec::with_capacity(total);
+            for level in 1..depth {
+                bytes.push(prefix::ARRAY | SIZE_PREFIX_8BYTE);
+                bytes.extend(((total - level * 9) as u64).to_le_bytes());
+            }
+            bytes.extend([prefix::ARRAY | SIZE_PREFIX_1BYTE, 0]);
+            bytes
+        };
+        let reverse = forward_to_reverse(&deep(Limits::DEFAULT_MAX_DEPTH)).unwrap();
+        assert_eq!(reverse_to_forward(&reverse).unwrap(), deep(Limits::DEFAULT_MAX_DEPTH));
+        assert!(forward_to_reverse(&deep(Limits::DEFAULT_MAX_DEPTH + 1)).unwrap_err().is_limit());
+        assert!(forward_to_reverse(&deep(200_000)).unwrap_err().is_limit());
+    }
+}
@@ -0,0 +1,355 @@
+//! TON と JSON の変換
+//!
+//! API の境界で JSON を話すクライアントとやり取りするための変換です
+//!
+//! タグ付きの変換では TON の型を `prefix_pua_utf8` のタグで保つので、JSON を経由しても値は変わりません
+//!
+//! - u64 と負の i64 と有限の f64 は JSON の数値のままです
+//! - それ以外の幅の数値は `{"$int": "-3i32"}` `{"$uint": "5u8"}` `{"$float": "1.5f32"}` のように
+//!   テキスト表現と同じ suffix 付きの文字列で表します
+//! - Bytes UUID DateTime Timestamp Duration Undefined Meta などはテキスト表現と同じタグで表します
+//! - WrappedJSON は `{"$wrapped_json": JSON}` として中身をそのまま埋め込みます
+//!
+//! plain な変換は型の情報を捨てて、serde_json で書いた場合と同じ素直な JSON にします
+
+use alloc::{string::{String, ToString}, format, vec};
+
+use serde_json::{Map as JsonMap, Number, Value as Json};
+
+use super::{error::{Error, ErrorCode, Result}, text::{bytes_to_hex, format_number, parse_number, tagged, TAGS}, types, value::{map::Map, num::{Float, Int, UInt}, prefix::prefix_pua_utf8, value::{KeyValue, Value}}};
+
+/// Value をタグ付きの JSON にする
+///
+/// from_json で同じ Value に戻せます
+///
+/// value: &Value
+///
+/// return: serde_json::Value
+pub fn to_json(value: &Value) -> Json {
+    match value {
+        Value::None => Json::Null,
+        Value::Bool(v) => Json::Bool(*v),
+        // JSON の数値から読むと u64, i64, f64 になるので、それ以外の幅はタグを付ける
+        Value::UInt(UInt::U64(v)) => Json::Number(Number::from(*v)),
+        Value::Int(Int::I64(v)) if *v < 0 => Json::Number(Number::from(*v)),
+        Value::Float(Float::F64(v)) if v.is_finite() => Number::from_f64(*v).map(Json::Number).unwrap_or(Json::Null),
+        Value::Int(_) => tag(prefix_pua_utf8::INT, Json::String(format_number(value).unwrap_or_default())),
+        Value::UInt(_) => tag(prefix_pua_utf8::UINT, Json::String(format_number(value).unwrap_or_default())),
+        Value::Float(_) => tag(prefix_pua_utf8::FLOAT, Json::String(format_number(value).unwrap_or_default())),
+        Value::String(v) => Json::String(v.clone()),
+        Value::Bytes(v) => tag(prefix_pua_utf8::BYTES, Json::String(bytes_to_hex(v))),
+        Value::Timestamp(v) => tag(prefix_pua_utf8::TIMESTAMP, Json::Number(Number::from(*v))),
+        Value::Duration(v) => tag(prefix_pua_utf8::DURATION, match v.num_nanoseconds() {
+            Some(v) => Json::Number(Number::from(v)),
+            // i64 の nanoseconds に収まらない場合は [秒, 秒未満の nanoseconds] にして値を落とさない
+            None => Json::Array(vec![Json::Number(Number::from(v.num_seconds())), Json::Number(Number::from(v.subsec_nanos()))]),
+        }),
+        Value::WrappedJSON(v) => tag(prefix_pua_utf8::WRAPPED_JSON, v.clone()),
+        Value::Array(v) => Json::Array(v.iter().map(to_json).collect()),
+        Value::Object(v) => {
+            let plain = v.keys().all(|k| matches!(k, KeyValue::String(_)))
+                && !(v.len() == 1 && v.keys().any(|k| matches!(k, KeyValue::String(k) if is_tag(k))));
+            if plain {
+                let map = v.iter().filter_map(|(k, v)| match k {
+                    KeyValue::String(k) => Some((k.clone(), to_json(v))),
+                    _ => None,
+                });
+                return Json::Object(map.collect());
+            }
+            // key が String でない Object は [key, value] の組の Array にする
+            let pairs = v.iter().map(|(k, v)| Json::Array(vec![to_json(&Value::from(k.clone())), to_json(v)]));
+            tag(prefix_pua_utf8::OBJECT, Json::Array(pairs.collect()))
+        }
+        other => match types::to_tagged(other) {
+            Some((name, payload)) => tag(name, to_json(&payload)),
+            None => Json::Null,
+        },
+    }
+}
+
+/// タグ付きの JSON から Value を読む
+///
+/// タグの無い JSON もそのまま読めます
+/// タグだけを key に持つ Object は中身が不正な場合にエラーになります
+///
+/// json: &serde_json::Value
+///
+/// return: Result<Value>
+pub fn from_json(json: &Json) -> Result<Value> {
+    let value = match json {
+        Json::Null => Value::None,
+        Json::Bool(v) => Value::Bool(*v),
+        Json::Number(v) => number_from_json(v),
+        Json::String(v) => Value::String(v.clone()),
+        Json::Array(v) => Value::Array(
+            v.iter()
+                .enumerate()
+                .map(|(i, v)| from_json(v).map_err(|e| e.in_index(i)))
+                .collect::<Result<_>>()?,
+        ),
+        Json::Object(map) => match map.iter().next() {
+            Some((name, payload)) if map.len() == 1 && is_tag(name) => from_tagged_json(name, payload)
+                .ok_or_else(|| Error::new(ErrorCode::Message(format!("invalid payload for `{}`", name))))?,
+            _ => Value::Object(
+                map.iter()
+                    .map(|(k, v)| match from_json(v) {
+                        Ok(v) => Ok((KeyValue::String(k.clone()), v)),
+                        Err(e) => Err(e.in_key(k.clone())),
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+        },
+    };
+    Ok(value)
+}
+
+/// Value を型の情報を捨てた素直な JSON にする
+///
+/// - 数値は JSON の数値に、JSON の数値に収まらない 128bit の整数は文字列に、NaN と無限大は null にします
+/// - Bytes は数値の Array に、UUID と DateTime は文字列に、Timestamp と Duration (nanoseconds) は数値にします
+/// - Undefined は null にし、Meta は metadata を捨てて値だけにします
+/// - String でない key は文字列にします
+///
+/// value: &Value
+///
+/// return: serde_json::Value
+pub fn to_plain_json(value: &Value) -> Json {
+    match value {
+        Value::Undefined | Value::None => Json::Null,
+        Value::Bool(v) => Json::Bool(*v),
+        Value::Int(Int::I128(v)) => match i64::try_from(*v) {
+            Ok(v) => Json::Number(Number::from(v)),
+            Err(_) => Json::String(v.to_string()),
+        },
+        Value::UInt(UInt::U128(v)) => match u64::try_from(*v) {
+            Ok(v) => Json::Number(Number::from(v)),
+            Err(_) => Json::String(v.to_string()),
+        },
+        Value::Int(v) => Json::Number(Number::from(match v {
+            Int::I8(v) => *v as i64,
+            Int::I16(v) => *v as i64,
+            Int::I32(v) => *v as i64,
+            Int::I64(v) => *v,
+            Int::I128(_) => unreachable!(),
+        })),
+        Value::UInt(v) => Json::Number(Number::from(match v {
+            UInt::U8(v) => *v as u64,
+            UInt::U16(v) => *v as u64,
+            UInt::U32(v) => *v as u64,
+            UInt::U64(v) => *v,
+            UInt::U128(_) => unreachable!(),
+        })),
+        Value::Float(v) => {
+            // f32 の 0.1 が 0.10000000149011612 にならないように 10 進数の表現を経由する
+            let v = match v {
+                Float::F16(v) => v.to_f32().to_string().parse().unwrap_or(f64::NAN),
+                Float::F32(v) => v.to_string().parse().unwrap_or(f64::NAN),
+                Float::F64(v) => *v,
+            };
+            Number::from_f64(v).map(Json::Number).unwrap_or(Json::Null)
+        }
+        Value::String(v) => Json::String(v.clone()),
+        Value::Bytes(v) => Json::Array(v.iter().map(|b| Json::Number(Number::from(*b))).collect()),
+        Value::UUID(v) => Json::String(v.to_string()),
+        Value::DateTime(v) => Json::String(types::datetime_to_string(v)),
+        Value::Timestamp(v) => Json::Number(Number::from(*v)),
+        Value::Duration(v) => v.num_nanoseconds().map(|v| Json::Number(Number::from(v))).unwrap_or(Json::Null),
+        Value::Array(v) => Json::Array(v.iter().map(to_plain_json).collect()),
+        Value::Object(v) => Json::Object(v.iter().map(|(k, v)| (key_to_string(k), to_plain_json(v))).collect()),
+        Value::WrappedJSON(v) => v.clone(),
+        Value::Meta(_, v) => to_plain_json(v),
+    }
+}
+
+/// JSON をタグを解釈せずに Value にする
+///
+/// 整数は u64 か i64 に、それ以外の数値は f64 になります
+///
+/// json: &serde_json::Value
+///
+/// return: Value
+pub fn from_plain_json(json: &Json) -> Value {
+    match json {
+        Json::Null => Value::None,
+        Json::Bool(v) => Value::Bool(*v),
+        Json::Number(v) => number_from_json(v),
+        Json::String(v) => Value::String(v.clone()),
+        Json::Array(v) => Value::Array(v.iter().map(from_plain_json).collect()),
+        Json::Object(v) => Value::Object(
+            v.iter().map(|(k, v)| (KeyValue::String(k.clone()), from_plain_json(v))).collect::<Map<_, _>>()
+        ),
+    }
+}
+
+/// JSON の数値を Value にする
+///
+/// number: &serde_json::Number
+///
+/// return: Value
+#[inline]
+fn number_from_json(number: &Number) -> Value {
+    if let Some(v) = number.as_u64() {
+        Value::UInt(UInt::U64(v))
+    } else if let Some(v) = number.as_i64() {
+        Value::Int(Int::I64(v))
+    } else {
+        Value::Float(Float::F64(number.as_f64().unwrap_or(f64::NAN)))
+    }
+}
+
+/// JSON で特別な意味を持つタグかどうか
+///
+/// name: &str
+///
+/// return: bool
+#[inline]
+fn is_tag(name: &str) -> bool {
+    TAGS.contains(&name) || name == prefix_pua_utf8::INT || name == prefix_pua_utf8::UINT
+}
+
+/// `{tag: payload}` の 1 要素の Object を作る
+///
+/// name: &str
+/// payload: serde_json::Value
+///
+/// return: serde_json::Value
+#[inline]
+fn tag(name: &str, payload: Json) -> Json {
+    let mut map = JsonMap::with_capacity(1);
+    map.insert(name.to_string(), payload);
+    Json::Object(map)
+}
+
+/// タグと JSON の中身から Value を組み立てる
+///
+/// name: &str
+/// payload: &serde_json::Value
+///
+/// return: Option<Value> // 中身が不正な場合は None
+fn from_tagged_json(name: &str, payload: &Json) -> Option<Value> {
+    match (name, payload) {
+        // 数値は suffix で幅が決まるが、タグと種類が合わないものは受け付けない
+        (prefix_pua_utf8::INT, Json::String(v)) => parse_number(v).filter(|v| matches!(v, Value::Int(_))),
+        (prefix_pua_utf8::UINT, Json::String(v)) => parse_number(v).filter(|v| matches!(v, Value::UInt(_))),
+        (prefix_pua_utf8::FLOAT, Json::String(v)) => parse_number(v).filter(|v| matches!(v, Value::Float(_))),
+        (prefix_pua_utf8::INT | prefix_pua_utf8::UINT, _) => None,
+        (prefix_pua_utf8::WRAPPED_JSON, v) => Some(Value::WrappedJSON(v.clone())),
+        (name, payload) => tagged(name, &from_json(payload).ok()?),
+    }
+}
+
+/// key を JSON の key の文字列にする
+///
+/// key: &KeyValue
+///
+/// return: String
+fn key_to_string(key: &KeyValue) -> String {
+    match key {
+        KeyValue::String(v) => v.clone(),
+        KeyValue::Bytes(v) => bytes_to_hex(v),
+        key => match to_plain_json(&Value::from(key.clone())) {
+            Json::String(v) => v,
+            other => other.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod json_tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn sample() -> Value {
+        let mut map = Map::new();
+        map.insert(KeyValue::String("id".to_string()), Value::UInt(UInt::U64(42)));
+        map.insert(KeyValue::String("small".to_string()), Value::UInt(UInt::U8(7)));
+        map.insert(KeyValue::String("delta".to_string()), Value::Int(Int::I16(-3)));
+        map.insert(KeyValue::String("big".to_string()), Value::Int(Int::I128(-(1 << 100))));
+        map.insert(KeyValue::String("ratio".to_string()), Value::Float(Float::F32(0.1)));
+        map.insert(KeyValue::String("exact".to_string()), Value::Float(Float::F64(2.5)));
+        map.insert(KeyValue::String("blob".to_string()), Value::Bytes(vec![0xde, 0xad]));
+        map.insert(KeyValue::String("owner".to_string()), Value::UUID(Uuid::from_u128(1)));
+        map.insert(KeyValue::String("at".to_string()), Value::DateTime(Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap()));
+        map.insert(KeyValue::String("ts".to_string()), Value::Timestamp(1_700_000_000));
+        map.insert(KeyValue::String("ttl".to_string()), Value::Duration(chrono::Duration::seconds(30)));
+        map.insert(KeyValue::String("gone".to_string()), Value::Undefined);
+        map.insert(KeyValue::String("raw".to_string()), Value::WrappedJSON(json!({"k": [1, 2]})));
+        map.insert(KeyValue::String("by_id".to_string()), Value::Object(
+            [(KeyValue::UInt(UInt::U32(5)), Value::String("five".to_string()))].into_iter().collect()
+        ));
+        map.insert(KeyValue::String("noted".to_string()), Value::Bool(true).with_meta(Value::String("m".to_string())));
+        Value::Object(map)
+    }
+
+    #[test]
+    fn test_tagged_round_trip() {
+        let json = to_json(&sample());
+        assert_eq!(json["id"], json!(42));
+        assert_eq!(json["small"], json!({"$uint": "7u8"}));
+        assert_eq!(json["exact"], json!(2.5));
+        assert_eq!(json["blob"], json!({"$bytes": "dead"}));
+        assert_eq!(json["owner"], json!({"$uuid": "00000000-0000-0000-0000-000000000001"}));
+        assert_eq!(json["raw"], json!({"$wrapped_json": {"k": [1, 2]}}));
+        assert_eq!(from_json(&json).unwrap(), sample());
+
+        // 文字列を経由しても変わらない
+        let text = serde_json::to_string(&json).unwrap();
+        assert_eq!(from_json(&serde_json::from_str(&text).unwrap()).unwrap(), sample());
+    }
+
+    #[test]
+    fn test_plain() {
+        let json = to_plain_json(&sample());
+        assert_eq!(json["small"], json!(7));
+        assert_eq!(json["delta"], json!(-3));
+        assert_eq!(json["big"], json!((-(1i128 << 100)).to_string()));
+        assert_eq!(json["ratio"], json!(0.1));
+        assert_eq!(json["blob"], json!([0xde, 0xad]));
+        assert_eq!(json["owner"], json!("00000000-0000-0000-0000-000000000001"));
+        assert_eq!(json["ttl"], json!(30_000_000_000i64));
+        assert_eq!(json["gone"], Json::Null);
+        assert_eq!(json["by_id"], json!({"5": "five"}));
+        assert_eq!(json["noted"], json!(true));
+
+        // plain ではタグを解釈しない
+        let value = from_plain_json(&json!({"$uuid": "x", "n": -1}));
+        assert_eq!(value["$uuid"], Value::String("x".to_string()));
+        assert_eq!(value["n"], Value::Int(Int::I64(-1)));
+    }
+
+    #[test]
+    fn test_invalid_tag() {
+        assert!(from_json(&json!({"$uint": "-1u8"})).is_err());
+        assert!(from_json(&json!({"$int": "1u8"})).is_err());
+        assert!(from_json(&json!({"$datetime": 5})).is_err());
+        let err = from_json(&json!({"a": [1, {"$uint": "x"}]})).unwrap_err();
+        assert_eq!(err.path(), ".a[1]");
+        // タグでない key はそのまま
+        assert!(matches!(from_json(&json!({"$other": 1})).unwrap(), Value::Object(_)));
+    }
+
+    #[test]
+    fn test_duration_out_of_range() {
+        let durations = [
+            chrono::Duration::MAX,
+            chrono::Duration::MIN,
+            chrono::Duration::days(365 * 300) + chrono::Duration::nanoseconds(123),
+            -chrono::Duration::days(365 * 300) - chrono::Duration::nanoseconds(123),
+        ];
+        for duration in durations {
+            assert_eq!(duration.num_nanoseconds(), None);
+            let value = Value::Duration(duration);
+            let json = to_json(&value);
+            assert_eq!(json, json!({"$duration": [duration.num_seconds(), duration.subsec_nanos()]}));
+            assert_eq!(from_json(&json).unwrap(), value);
+            // バイナリと同じ Serialize を通るテキストは黙って落とさずにエラーにする
+            assert!(crate::ton::to_text(&value).is_err());
+        }
+        // 秒未満に 1 秒以上は書けない
+        assert!(from_json(&json!({"$duration": [1, 1_000_000_000]})).is_err());
+        assert!(from_json(&json!({"$duration": [i64::MAX, 0]})).is_err());
+    }
+}
@@ -1,5 +1,12 @@
 pub mod ser;
+pub mod de;
+pub mod forward;
 pub mod error;
+pub mod types;
 pub mod value;
 pub mod c_de;
-pub mod stream;
\ No newline at end of file
+pub mod stream;
+pub mod view;
+pub mod text;
+pub mod json;
+pub mod schema;
@@ -0,0 +1,595 @@
+//! TON の schema
+//!
+//! collection に書き込む document の形を宣言して、書き込む前に検査するための仕組みです
+//! schema 自体も TON の Value で書けるので、document と同じように保存できます
+//!
+//! - `"u8"` `"string"` `"uuid"` のような型名の文字列は、その型の値を表します
+//!   `"any"` は何でも受け付けます
+//! - `{"type": "array", "items": schema}` は要素が全て items に合う Array を表します
+//! - `{"type": "object", "fields": {name: schema}, "additional": bool}` は field を持つ Object を表します
+//!   field の schema に `"optional": true` を付けると、その field は無くても None でも構いません
+//!   additional が false の場合は fields に無い key を拒否します (既定は true)
+//!
+//! 整数は値が宣言した型に収まれば幅や符号の型が違っても受け付け、浮動小数点数は宣言した幅以下なら受け付けます
+//! compact mode で狭い幅に書かれた値も読み戻したまま検査できます
+
+use alloc::{collections::BTreeMap, string::{String, ToString}, boxed::Box, format};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{de::TonTypes, error::{Error, ErrorCode, Result}, value::{map::Map, num::{Float, Int, UInt}, value::{KeyValue, Value}}};
+
+/// 値の形の宣言
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    /// 何でも受け付ける
+    Any,
+
+    /// 指定した型の値
+    ///
+    /// Array と Object は中身を検査しません
+    Type(TonTypes),
+
+    /// 要素が全て schema に合う Array
+    Array(Box<Schema>),
+
+    /// field を持つ Object
+    Object {
+        fields: BTreeMap<String, Field>,
+        /// fields に無い key を許すかどうか
+        additional: bool,
+    },
+}
+
+/// Object の field の宣言
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub schema: Schema,
+    /// 無い場合と None の場合を許すかどうか
+    pub optional: bool,
+}
+
+impl Field {
+    /// 必須の field を作る
+    ///
+    /// schema: Schema
+    ///
+    /// return: Field
+    #[inline]
+    pub fn required(schema: Schema) -> Self {
+        Self { schema, optional: false }
+    }
+
+    /// 省略できる field を作る
+    ///
+    /// schema: Schema
+    ///
+    /// return: Field
+    #[inline]
+    pub fn optional(schema: Schema) -> Self {
+        Self { schema, optional: true }
+    }
+}
+
+impl Schema {
+    /// field の無い Object の schema を作る
+    ///
+    /// additional は false になります
+    ///
+    /// return: Schema
+    #[inline]
+    pub fn object() -> Self {
+        Schema::Object { fields: BTreeMap::new(), additional: false }
+    }
+
+    /// 要素が schema に合う Array の schema を作る
+    ///
+    /// items: Schema
+    ///
+    /// return: Schema
+    #[inline]
+    pub fn array(items: Schema) -> Self {
+        Schema::Array(Box::new(items))
+    }
+
+    /// Object の schema に field を足す
+    ///
+    /// Object でない schema はそのまま返します
+    ///
+    /// name: &str
+    /// field: Field
+    ///
+    /// return: Schema
+    pub fn field(mut self, name: &str, field: Field) -> Self {
+        if let Schema::Object { fields, .. } = &mut self {
+            fields.insert(name.to_string(), field);
+        }
+        self
+    }
+
+    /// Object の schema が fields に無い key を許すかどうかを設定する
+    ///
+    /// additional: bool
+    ///
+    /// return: Schema
+    pub fn additional(mut self, additional: bool) -> Self {
+        if let Schema::Object { additional: v, .. } = &mut self {
+            *v = additional;
+        }
+        self
+    }
+
+    /// Value で書かれた schema を読む
+    ///
+    /// value: &Value
+    ///
+    /// return: Result<Schema>
+    pub fn from_value(value: &Value) -> Result<Self> {
+        Ok(field_from_value(value)?.schema)
+    }
+
+    /// schema を Value で表す
+    ///
+    /// from_value で同じ schema に戻せます
+    ///
+    /// return: Value
+    pub fn to_value(&self) -> Value {
+        match self {
+            Schema::Any => Value::String("any".to_string()),
+            Schema::Type(ty) => Value::String(type_name(*ty).to_string()),
+            Schema::Array(items) => Value::Object([
+                (KeyValue::from("type"), Value::String("array".to_string())),
+                (KeyValue::from("items"), items.to_value()),
+            ].into_iter().collect()),
+            Schema::Object { fields, additional } => {
+                let fields = fields.iter().map(|(name, field)| {
+                    let mut value = field.schema.to_value();
+                    if field.optional {
+                        value = match value {
+                            Value::Object(mut map) => {
+                                map.insert(KeyValue::from("optional"), Value::Bool(true));
+                                Value::Object(map)
+                            }
+                            other => Value::Object([
+                                (KeyValue::from("type"), other),
+                                (KeyValue::from("optional"), Value::Bool(true)),
+                            ].into_iter().collect()),
+                        };
+                    }
+                    (KeyValue::from(name.as_str()), value)
+                });
+                Value::Object([
+                    (KeyValue::from("type"), Value::String("object".to_string())),
+                    (KeyValue::from("fields"), Value::Object(fields.collect())),
+                    (KeyValue::from("additional"), Value::Bool(*additional)),
+                ].into_iter().collect())
+            }
+        }
+    }
+}
+
+impl Serialize for Schema {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Schema {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        Schema::from_value(&value).map_err(de::Error::custom)
+    }
+}
+
+/// value が schema に合うかを検査する
+///
+/// 最初に見つかった合わない値を、その位置の path (Error::path) と共にエラーで返します
+/// Meta は schema が Meta を求める場合を除いて読み飛ばします
+///
+/// value: &Value
+/// schema: &Schema
+///
+/// return: Result<()>
+pub fn validate(value: &Value, schema: &Schema) -> Result<()> {
+    let value = match schema {
+        Schema::Type(TonTypes::Meta) => value,
+        _ => value.payload(),
+    };
+    match schema {
+        Schema::Any => Ok(()),
+        Schema::Type(ty) if type_matches(value, *ty) => Ok(()),
+        Schema::Type(ty) => Err(mismatch(type_name(*ty), value)),
+        Schema::Array(items) => {
+            let Value::Array(elements) = value else {
+                return Err(mismatch("array", value));
+            };
+            for (i, element) in elements.iter().enumerate() {
+                validate(element, items).map_err(|e| e.in_index(i))?;
+            }
+            Ok(())
+        }
+        Schema::Object { fields, additional } => {
+            let Value::Object(map) = value else {
+                return Err(mismatch("object", value));
+            };
+            validate_object(map, fields, *additional)
+        }
+    }
+}
+
+/// Object の field を検査する
+///
+/// map: &Map<KeyValue, Value>
+/// fields: &BTreeMap<String, Field>
+/// additional: bool
+///
+/// return: Result<()>
+fn validate_object(map: &Map<KeyValue, Value>, fields: &BTreeMap<String, Field>, additional: bool) -> Result<()> {
+    for (name, field) in fields {
+        match map.get(&KeyValue::String(name.clone())) {
+            Some(Value::None) | None if field.optional => {}
+            None => return Err(error(format!("missing field `{}`", name))),
+            Some(value) => validate(value, &field.schema).map_err(|e| e.in_key(name.clone()))?,
+        }
+    }
+    if !additional {
+        let unknown = map.keys().find(|key| match key {
+            KeyValue::String(key) => !fields.contains_key(key),
+            _ => true,
+        });
+        if let Some(key) = unknown {
+            return Err(error(format!("unknown field `{:?}`", key)));
+        }
+    }
+    Ok(())
+}
+
+/// value が ty の値として受け付けられるかどうか
+///
+/// value: &Value
+/// ty: TonTypes
+///
+/// return: bool
+fn type_matches(value: &Value, ty: TonTypes) -> bool {
+    match ty {
+        TonTypes::IntI8 => integer_fits(value, i8::MIN as i128, i8::MAX as u128),
+        TonTypes::IntI16 => integer_fits(value, i16::MIN as i128, i16::MAX as u128),
+        TonTypes::IntI32 => integer_fits(value, i32::MIN as i128, i32::MAX as u128),
+        TonTypes::IntI64 => integer_fits(value, i64::MIN as i128, i64::MAX as u128),
+        TonTypes::IntI128 => integer_fits(value, i128::MIN, i128::MAX as u128),
+        TonTypes::UIntU8 => integer_fits(value, 0, u8::MAX as u128),
+        TonTypes::UIntU16 => integer_fits(value, 0, u16::MAX as u128),
+        TonTypes::UIntU32 => integer_fits(value, 0, u32::MAX as u128),
+        TonTypes::UIntU64 => integer_fits(value, 0, u64::MAX as u128),
+        TonTypes::UIntU128 => integer_fits(value, 0, u128::MAX),
+        TonTypes::Float16 => matches!(value, Value::Float(Float::F16(_))),
+        TonTypes::Float32 => matches!(value, Value::Float(Float::F16(_) | Float::F32(_))),
+        TonTypes::Float64 => matches!(value, Value::Float(_)),
+        TonTypes::Padding => false,
+        ty => value_type(value) == ty,
+    }
+}
+
+/// value が min 以上 max 以下の整数かどうか
+///
+/// value: &Value
+/// min: i128
+/// max: u128
+///
+/// return: bool
+#[inline]
+fn integer_fits(value: &Value, min: i128, max: u128) -> bool {
+    match value {
+        Value::Int(v) => {
+            let v = match v {
+                Int::I8(v) => *v as i128,
+                Int::I16(v) => *v as i128,
+                Int::I32(v) => *v as i128,
+                Int::I64(v) => *v as i128,
+                Int::I128(v) => *v,
+            };
+            if v < 0 { v >= min } else { v as u128 <= max }
+        }
+        Value::UInt(v) => {
+            let v = match v {
+                UInt::U8(v) => *v as u128,
+                UInt::U16(v) => *v as u128,
+                UInt::U32(v) => *v as u128,
+                UInt::U64(v) => *v as u128,
+                UInt::U128(v) => *v,
+            };
+            v <= max
+        }
+        _ => false,
+    }
+}
+
+/// Value の型
+///
+/// value: &Value
+///
+/// return: TonTypes
+fn value_type(value: &Value) -> TonTypes {
+    match value {
+        Value::Undefined => TonTypes::Undefined,
+        Value::None => TonTypes::None,
+        Value::Bool(_) => TonTypes::Bool,
+        Value::Int(Int::I8(_)) => TonTypes::IntI8,
+        Value::Int(Int::I16(_)) => TonTypes::IntI16,
+        Value::Int(Int::I32(_)) => TonTypes::IntI32,
+        Value::Int(Int::I64(_)) => TonTypes::IntI64,
+        Value::Int(Int::I128(_)) => TonTypes::IntI128,
+        Value::UInt(UInt::U8(_)) => TonTypes::UIntU8,
+        Value::UInt(UInt::U16(_)) => TonTypes::UIntU16,
+        Value::UInt(UInt::U32(_)) => TonTypes::UIntU32,
+        Value::UInt(UInt::U64(_)) => TonTypes::UIntU64,
+        Value::UInt(UInt::U128(_)) => TonTypes::UIntU128,
+        Value::Float(Float::F16(_)) => TonTypes::Float16,
+        Value::Float(Float::F32(_)) => TonTypes::Float32,
+        Value::Float(Float::F64(_)) => TonTypes::Float64,
+        Value::String(_) => TonTypes::String,
+        Value::Bytes(_) => TonTypes::Bytes,
+        Value::UUID(_) => TonTypes::UUID,
+        Value::DateTime(_) => TonTypes::DateTime,
+        Value::Timestamp(_) => TonTypes::Timestamp,
+        Value::Duration(_) => TonTypes::Duration,
+        Value::Array(_) => TonTypes::Array,
+        Value::Object(_) => TonTypes::Object,
+        Value::WrappedJSON(_) => TonTypes::WrappedJSON,
+        Value::Meta(_, _) => TonTypes::Meta,
+    }
+}
+
+/// schema で使う型名
+///
+/// ty: TonTypes
+///
+/// return: &'static str
+fn type_name(ty: TonTypes) -> &'static str {
+    match ty {
+        TonTypes::Undefined => "undefined",
+        TonTypes::None => "null",
+        TonTypes::Bool => "bool",
+        TonTypes::IntI8 => "i8",
+        TonTypes::IntI16 => "i16",
+        TonTypes::IntI32 => "i32",
+        TonTypes::IntI64 => "i64",
+        TonTypes::IntI128 => "i128",
+        TonTypes::UIntU8 => "u8",
+        TonTypes::UIntU16 => "u16",
+        TonTypes::UIntU32 => "u32",
+        TonTypes::UIntU64 => "u64",
+        TonTypes::UIntU128 => "u128",
+        TonTypes::Float16 => "f16",
+        TonTypes::Float32 => "f32",
+        TonTypes::Float64 => "f64",
+        TonTypes::String => "string",
+        TonTypes::Bytes => "bytes",
+        TonTypes::UUID => "uuid",
+        TonTypes::DateTime => "datetime",
+        TonTypes::Timestamp => "timestamp",
+        TonTypes::Duration => "duration",
+        TonTypes::Array => "array",
+        TonTypes::Object => "object",
+        TonTypes::WrappedJSON => "json",
+        TonTypes::Meta => "meta",
+        TonTypes::Padding => "padding",
+    }
+}
+
+/// 型名から型を引く
+///
+/// padding は値にならないので受け付けません
+///
+/// name: &str
+///
+/// return: Option<TonTypes>
+fn type_from_name(name: &str) -> Option<TonTypes> {
+    const TYPES: [TonTypes; 26] = [
+        TonTypes::Undefined, TonTypes::None, TonTypes::Bool,
+        TonTypes::IntI8, TonTypes::IntI16, TonTypes::IntI32, TonTypes::IntI64, TonTypes::IntI128,
+        TonTypes::UIntU8, TonTypes::UIntU16, TonTypes::UIntU32, TonTypes::UIntU64, TonTypes::UIntU128,
+        TonTypes::Float16, TonTypes::Float32, TonTypes::Float64,
+        TonTypes::String, TonTypes::Bytes, TonTypes::UUID, TonTypes::DateTime, TonTypes::Timestamp, TonTypes::Duration,
+        TonTypes::Array, TonTypes::Object, TonTypes::WrappedJSON, TonTypes::Meta,
+    ];
+    TYPES.into_iter().find(|ty| type_name(*ty) == name)
+}
+
+/// Value で書かれた field の宣言を読む
+///
+/// value: &Value
+///
+/// return: Result<Field>
+fn field_from_value(value: &Value) -> Result<Field> {
+    let map = match value {
+        Value::String(name) => return Ok(Field::required(schema_from_name(name)?)),
+        Value::Object(map) => map,
+        _ => return Err(error("schema must be a type name or an object".to_string())),
+    };
+    let name = match map.get(&KeyValue::from("type")) {
+        Some(Value::String(name)) => name.as_str(),
+        _ => return Err(error("schema needs a `type` name".to_string())),
+    };
+    let optional = match map.get(&KeyValue::from("optional")) {
+        None => false,
+        Some(Value::Bool(v)) => *v,
+        Some(_) => return Err(error("`optional` must be a bool".to_string())),
+    };
+    let schema = match name {
+        "array" => match map.get(&KeyValue::from("items")) {
+            Some(items) => Schema::array(field_from_value(items).map_err(|e| e.in_key("items".to_string()))?.schema),
+            None => Schema::Type(TonTypes::Array),
+        },
+        "object" => {
+            let additional = match map.get(&KeyValue::from("additional")) {
+                None => true,
+                Some(Value::Bool(v)) => *v,
+                Some(_) => return Err(error("`additional` must be a bool".to_string())),
+            };
+            match map.get(&KeyValue::from("fields")) {
+                Some(Value::Object(fields)) => {
+                    let mut schema = Schema::object().additional(additional);
+                    for (key, value) in fields {
+                        let KeyValue::String(key) = key else {
+                            return Err(error("field names must be strings".to_string()));
+                        };
+                        let field = field_from_value(value).map_err(|e| e.in_key(key.clone()).in_key("fields".to_string()))?;
+                        schema = schema.field(key, field);
+                    }
+                    schema
+                }
+                Some(_) => return Err(error("`fields` must be an object".to_string())),
+                None if additional => Schema::Type(TonTypes::Object),
+                None => Schema::object(),
+            }
+        }
+        name => schema_from_name(name)?,
+    };
+    Ok(Field { schema, optional })
+}
+
+/// 型名だけで書かれた schema を読む
+///
+/// name: &str
+///
+/// return: Result<Schema>
+#[inline]
+fn schema_from_name(name: &str) -> Result<Schema> {
+    match name {
+        "any" => Ok(Schema::Any),
+        name => type_from_name(name)
+            .map(Schema::Type)
+            .ok_or_else(|| error(format!("unknown type `{}`", name))),
+    }
+}
+
+/// 型が合わないエラー
+///
+/// expected: &str
+/// found: &Value
+///
+/// return: Error
+#[cold]
+fn mismatch(expected: &str, found: &Value) -> Error {
+    error(format!("expected {}, found {}", expected, type_name(value_type(found))))
+}
+
+/// schema のエラー
+///
+/// msg: String
+///
+/// return: Error
+#[cold]
+fn error(msg: String) -> Error {
+    Error::new(ErrorCode::Message(msg))
+}
+
+#[cfg(test)]
+mod schema_tests {
+    use chrono::{DateTime, Utc};
+    use serde::Serialize;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::ton::{from_slice, serde::{ser::ReverseSerializer, value::ser::to_value}, to_vec};
+
+    #[derive(Serialize)]
+    struct User {
+        #[serde(with = "crate::ton::serde::types::uuid")]
+        id: Uuid,
+        name: String,
+        age: u8,
+        email: Option<String>,
+        tags: Vec<String>,
+        #[serde(with = "crate::ton::serde::types::datetime")]
+        created: DateTime<Utc>,
+    }
+
+    fn user_schema() -> Schema {
+        Schema::object()
+            .field("id", Field::required(Schema::Type(TonTypes::UUID)))
+            .field("name", Field::required(Schema::Type(TonTypes::String)))
+            .field("age", Field::required(Schema::Type(TonTypes::UIntU16)))
+            .field("email", Field::optional(Schema::Type(TonTypes::String)))
+            .field("tags", Field::required(Schema::array(Schema::Type(TonTypes::String))))
+            .field("created", Field::required(Schema::Any))
+    }
+
+    fn user() -> User {
+        User { id: Uuid::from_u128(1), name: "a".to_string(), age: 20, email: None, tags: vec!["x".to_string()], created: DateTime::from_timestamp(1_700_000_000, 0).unwrap() }
+    }
+
+    #[test]
+    fn test_validate() {
+        let value = to_value(&user()).unwrap();
+        validate(&value, &user_schema()).unwrap();
+
+        // compact で書いて読み戻しても通る
+        let mut value = value;
+        value["age"] = Value::UInt(UInt::U16(20));
+        let mut serializer = ReverseSerializer::new(Vec::new()).compact();
+        value.serialize(&mut serializer).unwrap();
+        let value: Value = from_slice(&serializer.into_inner()).unwrap();
+        // 宣言した u16 より狭い u8 で読み戻される
+        assert_eq!(value["age"], Value::UInt(UInt::U8(20)));
+        validate(&value, &user_schema()).unwrap();
+        validate(&Value::Int(Int::I64(-1)), &Schema::Type(TonTypes::IntI8)).unwrap();
+        validate(&Value::Float(Float::F16(half::f16::ONE)), &Schema::Type(TonTypes::Float64)).unwrap();
+        validate(&Value::Bool(true).with_meta(Value::None), &Schema::Type(TonTypes::Bool)).unwrap();
+    }
+
+    #[test]
+    fn test_validate_errors() {
+        let mut value = to_value(&user()).unwrap();
+        value["tags"] = Value::Array(vec![Value::String("x".to_string()), Value::UInt(UInt::U8(1))]);
+        let err = validate(&value, &user_schema()).unwrap_err();
+        assert_eq!(err.to_string(), "expected string, found u8 at `.tags[1]`");
+
+        let mut value = to_value(&user()).unwrap();
+        value["age"] = Value::Int(Int::I8(-1));
+        assert_eq!(validate(&value, &user_schema()).unwrap_err().to_string(), "expected u16, found i8 at `.age`");
+
+        let mut value = to_value(&user()).unwrap();
+        let Value::Object(map) = &mut value else { unreachable!() };
+        map.remove(&KeyValue::from("name"));
+        assert_eq!(validate(&value, &user_schema()).unwrap_err().to_string(), "missing field `name`");
+
+        let value = to_value(&user()).unwrap();
+        let strict = Schema::object().field("id", Field::required(Schema::Any));
+        assert!(validate(&value, &strict).unwrap_err().to_string().starts_with("unknown field"));
+        validate(&value, &strict.additional(true)).unwrap();
+        assert!(validate(&Value::UInt(UInt::U16(256)), &Schema::Type(TonTypes::UIntU8)).is_err());
+        assert!(validate(&Value::Float(Float::F64(1.0)), &Schema::Type(TonTypes::Float32)).is_err());
+    }
+
+    #[test]
+    fn test_schema_as_value() {
+        let schema = user_schema().additional(true);
+        assert_eq!(Schema::from_value(&schema.to_value()).unwrap(), schema);
+
+        // TON に保存して読み戻せる
+        let stored: Schema = from_slice(&to_vec(&schema).unwrap()).unwrap();
+        assert_eq!(stored, schema);
+
+        let text = r#"{"type": "object", "additional": false, "fields": {
+            "id": "u64",
+            "note": {"type": "string", "optional": true},
+            "items": {"type": "array", "items": {"type": "object", "fields": {"a/b": "bool"}}}
+        }}"#;
+        let schema = Schema::from_value(&crate::ton::from_text::<Value>(text).unwrap()).unwrap();
+        let doc = crate::ton::from_text::<Value>(r#"{"id": 1, "items": [{"a/b": true}, {"a/b": 1}]}"#).unwrap();
+        assert_eq!(validate(&doc, &schema).unwrap_err().to_string(), r#"expected bool, found u64 at `.items[1]["a/b"]`"#);
+
+        let err = Schema::from_value(&crate::ton::from_text::<Value>(r#"{"type": "object", "fields": {"x": "u7"}}"#).unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "unknown type `u7` at `.fields.x`");
+    }
+}
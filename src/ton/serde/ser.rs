@@ -1,8 +1,53 @@
-use std::io::Write;
+use alloc::{vec::Vec, string::ToString};
 
+use half::f16;
 use serde::{ser, Serialize, Serializer};
 
-use super::{error::Error, value::prefix::{prefix, size_prefix::{SIZE_PREFIX_1BYTE, SIZE_PREFIX_2BYTE, SIZE_PREFIX_4BYTE, SIZE_PREFIX_8BYTE}}};
+use super::{error::Error, value::prefix::{prefix, prefix_pua_utf8, size_prefix::{SIZE_PREFIX_1BYTE, SIZE_PREFIX_2BYTE, SIZE_PREFIX_4BYTE, SIZE_PREFIX_8BYTE}}};
+
+/// バイト列を書き出すためのトレイト
+///
+/// std が有効な場合は io::Write を実装した型でそのまま使えます
+/// no_std では Vec<u8> と &mut W に実装しています
+pub trait Write {
+    /// buf を全て書き込む
+    ///
+    /// buf: &[u8]
+    ///
+    /// return: Result<(), Error>
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error>;
+}
+
+#[cfg(feature = "std")]
+impl<W> Write for W
+where
+    W: std::io::Write + ?Sized,
+{
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        std::io::Write::write_all(self, buf).map_err(Error::io)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Write for Vec<u8> {
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<W> Write for &mut W
+where
+    W: Write + ?Sized,
+{
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        (**self).write_all(buf)
+    }
+}
 
 /// Reverse TON シリアライザー
 /// 
@@ -15,6 +60,14 @@ where
     buffer: Vec<u8>,
     size: usize,
     deep: usize,
+    /// TON 固有の型として書き込む prefix
+    /// 
+    /// `prefix_pua_utf8` の名前の newtype struct の中でだけ Some になります
+    magic: Option<u8>,
+    /// 整数と浮動小数点数を値が収まる最小の幅で書く
+    compact: bool,
+    /// 同じ値から常に同じバイト列を書く
+    canonical: bool,
 }
 
 impl<W> ReverseSerializer<W>
@@ -33,6 +86,9 @@ where
             size: 0,
             buffer: Vec::with_capacity(256/*default capacity*/),
             deep: 0,
+            magic: None,
+            compact: false,
+            canonical: false,
         }
     }
 
@@ -43,9 +99,60 @@ where
             size: 0,
             buffer: Vec::with_capacity(capacity),
             deep: 0,
+            magic: None,
+            compact: false,
+            canonical: false,
         }
     }
 
+    /// compact モードにする
+    /// 
+    /// 整数は値が収まる最小の幅で、f64 は f32 で表せる場合は f32 で書きます
+    /// 型の符号は保ち、f16 は別の型として扱うので使いません
+    /// Deserializer は要求された Rust の型の幅に戻して返します
+    /// 
+    /// return: ReverseSerializer
+    #[inline]
+    pub fn compact(mut self) -> Self {
+        self.compact = true;
+        self
+    }
+
+    /// compact モードかどうか
+    /// 
+    /// return: bool
+    #[inline]
+    pub fn is_compact(&self) -> bool {
+        self.compact
+    }
+
+    /// canonical モードにする
+    /// 
+    /// 同じ値から常に同じバイト列を書くので、ハッシュや署名、重複の検出に使えます
+    /// 
+    /// - Object の要素は key のバイト列の順に並べ替えます
+    /// - compact モードと同じく、整数と浮動小数点数は最小の幅で書きます
+    /// - f16 も f32 と f64 と同じく値が変わらない最小の幅で書くので、型によらず等しい値は同じバイト列になります
+    /// - NaN と -0.0 を正規化します
+    /// 
+    /// Array の要素の順は変えないので、HashSet などの順は呼び出し側で揃える必要があります
+    /// 
+    /// return: ReverseSerializer
+    #[inline]
+    pub fn canonical(mut self) -> Self {
+        self.compact = true;
+        self.canonical = true;
+        self
+    }
+
+    /// canonical モードかどうか
+    /// 
+    /// return: bool
+    #[inline]
+    pub fn is_canonical(&self) -> bool {
+        self.canonical
+    }
+
     /// バッファに書き込む
     /// 
     /// バッファのキャパがいっぱいになるとflashします
@@ -79,10 +186,126 @@ where
         Ok(())
     }
 
+    /// 固定長の body と 1byte の prefix を書き込む
+    /// 
+    /// UUID TIMESTAMP DURATION など size フィールドを持たない型に使います
+    /// 
+    /// body: &[u8]
+    /// prefix: u8
+    /// 
+    /// return: Result<(), Error>
+    #[inline]
+    fn write_fixed(&mut self, body: &[u8], prefix: u8) -> Result<(), Error> {
+        self.write_iter(body.iter().chain([prefix].iter()))?;
+        self.size += body.len() + 1;
+        Ok(())
+    }
+
+    /// 値が収まる最小の幅で INT を書く
+    /// 
+    /// v: i128
+    /// 
+    /// return: Result<(), Error>
+    #[inline]
+    fn write_min_int(&mut self, v: i128) -> Result<(), Error> {
+        if let Ok(v) = i8::try_from(v) {
+            self.write_fixed(&v.to_le_bytes(), prefix::INT | SIZE_PREFIX_1BYTE)
+        } else if let Ok(v) = i16::try_from(v) {
+            self.write_fixed(&v.to_le_bytes(), prefix::INT | SIZE_PREFIX_2BYTE)
+        } else if let Ok(v) = i32::try_from(v) {
+            self.write_fixed(&v.to_le_bytes(), prefix::INT | SIZE_PREFIX_4BYTE)
+        } else if let Ok(v) = i64::try_from(v) {
+            self.write_fixed(&v.to_le_bytes(), prefix::INT | SIZE_PREFIX_8BYTE)
+        } else {
+            self.write_fixed(&v.to_le_bytes(), prefix::INT128)
+        }
+    }
+
+    /// 値が収まる最小の幅で UINT を書く
+    /// 
+    /// v: u128
+    /// 
+    /// return: Result<(), Error>
+    #[inline]
+    fn write_min_uint(&mut self, v: u128) -> Result<(), Error> {
+        if let Ok(v) = u8::try_from(v) {
+            self.write_fixed(&v.to_le_bytes(), prefix::UINT | SIZE_PREFIX_1BYTE)
+        } else if let Ok(v) = u16::try_from(v) {
+            self.write_fixed(&v.to_le_bytes(), prefix::UINT | SIZE_PREFIX_2BYTE)
+        } else if let Ok(v) = u32::try_from(v) {
+            self.write_fixed(&v.to_le_bytes(), prefix::UINT | SIZE_PREFIX_4BYTE)
+        } else if let Ok(v) = u64::try_from(v) {
+            self.write_fixed(&v.to_le_bytes(), prefix::UINT | SIZE_PREFIX_8BYTE)
+        } else {
+            self.write_fixed(&v.to_le_bytes(), prefix::UINT128)
+        }
+    }
+
+    /// 値が変わらない最小の幅で FLOAT を書く
+    /// 
+    /// canonical モードでは NaN を 1 つの表現にまとめ、-0.0 を 0.0 にします
+    /// 
+    /// v: f64
+    /// 
+    /// return: Result<(), Error>
+    #[inline]
+    fn write_min_float(&mut self, v: f64) -> Result<(), Error> {
+        let v = match self.canonical {
+            true if v.is_nan() => f64::NAN,
+            true if v == 0.0 => 0.0,
+            _ => v,
+        };
+        let narrow = v as f32;
+        // NaN は f32 にすると payload が変わるので canonical モードでだけ縮める
+        if narrow as f64 == v || (self.canonical && v.is_nan()) {
+            self.write_fixed(&narrow.to_le_bytes(), prefix::FLOAT | SIZE_PREFIX_4BYTE)
+        } else {
+            self.write_fixed(&v.to_le_bytes(), prefix::FLOAT | SIZE_PREFIX_8BYTE)
+        }
+    }
+
+    /// ここまでに書いたサイズが alignment の倍数になるように PADDING を書き込む
+    /// 
+    /// PADDING はすべての reader が読み飛ばすので、値の後ろに書いても読み方は変わりません
+    /// ブロック単位で書けば、サイズが変わらない範囲で値をその場で書き換えられます
+    /// PADDING は最小でも 2byte なので、1byte だけ足りない場合は次の境界まで伸ばします
+    /// 
+    /// alignment: usize // DriverCash::block_size などの境界
+    /// 
+    /// return: Result<(), Error>
+    pub fn pad_to(&mut self, alignment: usize) -> Result<(), Error> {
+        if alignment == 0 {
+            return Err(ser::Error::custom("alignment must not be zero"));
+        }
+        let mut len = (alignment - self.size % alignment) % alignment;
+        if len == 1 {
+            len += alignment;
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        // body のサイズが収まる幅の size フィールドを選ぶ
+        // 最小の幅で無くても読めるので、len ちょうどにできる
+        let (size_prefix, width) = match len {
+            l if l - 2 <= u8::MAX as usize => (SIZE_PREFIX_1BYTE, 1),
+            l if l - 3 <= u16::MAX as usize => (SIZE_PREFIX_2BYTE, 2),
+            l if l - 5 <= u32::MAX as usize => (SIZE_PREFIX_4BYTE, 4),
+            _ => (SIZE_PREFIX_8BYTE, 8),
+        };
+        let body_size = len - 1 - width;
+        let size_field = (body_size as u64).to_le_bytes();
+        let prefix = [prefix::PADDING | size_prefix];
+        let header = size_field[..width].iter().rev().chain(prefix.iter());
+        let value = core::iter::repeat_n(&0u8, body_size).chain(header);
+        self.write_iter(value)?;
+        self.size += len;
+        Ok(())
+    }
+
     /// バッファの内容をフラッシュする
     #[inline]
     fn flash(&mut self) -> Result<(), Error> {
-        self.writer.write_all(&self.buffer).map_err(Error::io)?;
+        self.writer.write_all(&self.buffer)?;
         self.buffer.truncate(0);
         Ok(())
     }
@@ -119,6 +342,11 @@ where
     type SerializeStruct = Compound<'a, W>;
     type SerializeStructVariant = Compound<'a, W>;
 
+    #[inline]
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
     #[inline]
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
         // <header: <prefix: 6bit, value: 1bit>
@@ -140,6 +368,9 @@ where
 
     #[inline]
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        if self.compact {
+            return self.write_min_int(v as i128);
+        }
         let mut buf: [u8; 3] = [0; 3];
         buf[0..2].copy_from_slice(&v.to_le_bytes());
         buf[2] = prefix::INT | SIZE_PREFIX_2BYTE;
@@ -150,6 +381,9 @@ where
 
     #[inline]
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        if self.compact {
+            return self.write_min_int(v as i128);
+        }
         let mut buf: [u8; 5] = [0; 5];
         buf[0..4].copy_from_slice(&v.to_le_bytes());
         buf[4] = prefix::INT | SIZE_PREFIX_4BYTE;
@@ -159,6 +393,13 @@ where
     }
     #[inline]
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        // TIMESTAMP DURATION は 8byte の body を持つ
+        if let Some(magic @ (prefix::TIMESTAMP | prefix::DURATION)) = self.magic.take() {
+            return self.write_fixed(&v.to_le_bytes(), magic);
+        }
+        if self.compact {
+            return self.write_min_int(v as i128);
+        }
         let mut buf: [u8; 9] = [0; 9];
         buf[0..8].copy_from_slice(&v.to_le_bytes());
         buf[8] = prefix::INT | SIZE_PREFIX_8BYTE;
@@ -179,6 +420,9 @@ where
 
     #[inline]
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        if self.compact {
+            return self.write_min_uint(v as u128);
+        }
         let mut buf: [u8; 3] = [0; 3];
         buf[0..2].copy_from_slice(&v.to_le_bytes());
         buf[2] = prefix::UINT | SIZE_PREFIX_2BYTE;
@@ -189,6 +433,9 @@ where
 
     #[inline]
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        if self.compact {
+            return self.write_min_uint(v as u128);
+        }
         let mut buf: [u8; 5] = [0; 5];
         buf[0..4].copy_from_slice(&v.to_le_bytes());
         buf[4] = prefix::UINT | SIZE_PREFIX_4BYTE;
@@ -199,6 +446,9 @@ where
 
     #[inline]
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        if self.compact {
+            return self.write_min_uint(v as u128);
+        }
         let mut buf: [u8; 9] = [0; 9];
         buf[0..8].copy_from_slice(&v.to_le_bytes());
         buf[8] = prefix::UINT | SIZE_PREFIX_8BYTE;
@@ -207,8 +457,36 @@ where
         Ok(())
     }
 
+    #[inline]
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        if self.compact {
+            return self.write_min_int(v);
+        }
+        // size_prefix は 8byte までなので専用の prefix で書く
+        self.write_fixed(&v.to_le_bytes(), prefix::INT128)
+    }
+
+    #[inline]
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        if self.compact {
+            return self.write_min_uint(v);
+        }
+        self.write_fixed(&v.to_le_bytes(), prefix::UINT128)
+    }
+
     #[inline]
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        // FLOAT の 2byte は f16
+        if let Some(prefix::FLOAT) = self.magic.take() {
+            // canonical モードでは f32, f64 と同じ値を同じバイト列にする
+            if self.canonical {
+                return self.write_min_float(v as f64);
+            }
+            return self.write_fixed(&f16::from_f32(v).to_le_bytes(), prefix::FLOAT | SIZE_PREFIX_2BYTE);
+        }
+        if self.canonical {
+            return self.write_min_float(v as f64);
+        }
         let mut buf: [u8; 5] = [0; 5];
         buf[0..4].copy_from_slice(&v.to_le_bytes());
         buf[4] = prefix::FLOAT | SIZE_PREFIX_4BYTE;
@@ -219,6 +497,9 @@ where
 
     #[inline]
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        if self.compact {
+            return self.write_min_float(v);
+        }
         let mut buf: [u8; 9] = [0; 9];
         buf[0..8].copy_from_slice(&v.to_le_bytes());
         buf[8] = prefix::FLOAT | SIZE_PREFIX_8BYTE;
@@ -236,7 +517,12 @@ where
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
         let bytes = v.as_bytes();
         let size = bytes.len();
-        let (header, header_size) = generate_header(prefix::STRING, size);
+        // DATETIME は ISO8601、WRAPPED_JSON は JSON の文字列を body に持つ
+        let prefix = match self.magic.take() {
+            Some(magic @ (prefix::DATETIME | prefix::WRAPPED_JSON)) => magic,
+            _ => prefix::STRING,
+        };
+        let (header, header_size) = generate_header(prefix, size);
         // 文字列データを逆順に格納
         let value = bytes.iter().chain(header[..header_size].iter().rev());
         self.write_iter(value)?;
@@ -246,6 +532,13 @@ where
     
     #[inline]
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        // UUID は 16byte の body を持つ
+        if let Some(prefix::UUID) = self.magic.take() {
+            if v.len() != 16 {
+                return Err(ser::Error::custom("uuid must be 16 bytes"));
+            }
+            return self.write_fixed(v, prefix::UUID);
+        }
         let size = v.len();
         let (header, header_size) = generate_header(prefix::BYTES, size);
         // バイトデータを逆順に格納
@@ -274,7 +567,10 @@ where
         self.serialize_none()
     }
 
-    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
+        if name == prefix_pua_utf8::UNDEFINED {
+            return self.write_fixed(&[], prefix::UNDEFINED);
+        }
         self.serialize_none()
     }
 
@@ -289,12 +585,25 @@ where
 
     fn serialize_newtype_struct<T>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: ?Sized + ser::Serialize {
-        value.serialize(&mut *self)
+        // TON 固有の型は名前で判別する
+        self.magic = match name {
+            prefix_pua_utf8::UUID => Some(prefix::UUID),
+            prefix_pua_utf8::DATETIME => Some(prefix::DATETIME),
+            prefix_pua_utf8::TIMESTAMP => Some(prefix::TIMESTAMP),
+            prefix_pua_utf8::DURATION => Some(prefix::DURATION),
+            prefix_pua_utf8::FLOAT => Some(prefix::FLOAT),
+            prefix_pua_utf8::WRAPPED_JSON => Some(prefix::WRAPPED_JSON),
+            prefix_pua_utf8::META => Some(prefix::META),
+            _ => None,
+        };
+        let result = value.serialize(&mut *self);
+        self.magic = None;
+        result
     }
 
     fn serialize_newtype_variant<T>(
@@ -320,6 +629,10 @@ where
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        // META は (metadata, 値) の組を body に持つ
+        if let Some(prefix::META) = self.magic.take() {
+            return Ok(Compound::with_prefix(self, prefix::META));
+        }
         Ok(Compound::new(self))
     }
 
@@ -342,7 +655,7 @@ where
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        Ok(Compound::new(self))
+        Ok(Compound::new(self).sorted())
     }
 
     fn serialize_struct(
@@ -350,7 +663,7 @@ where
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        Ok(Compound::new(self))
+        Ok(Compound::new(self).sorted())
     }
 
     fn serialize_struct_variant(
@@ -360,7 +673,7 @@ where
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        Ok(Compound::with_variant(self, variant))
+        Ok(Compound::with_variant(self, variant).sorted())
     }
 }
 
@@ -371,6 +684,12 @@ where
     ser: &'a mut ReverseSerializer<W>,
     start_pos: usize,
     variant_name: Option<&'static str>,
+    // tuple を書くときの prefix
+    prefix: u8,
+    // canonical モードで並べ替える前の Object の要素 (key, value)
+    entries: Option<Vec<(Vec<u8>, Vec<u8>)>>,
+    // serialize_key で受け取った value 待ちの key
+    pending_key: Option<Vec<u8>>,
 }
 
 impl<'a, W> Compound<'a, W>
@@ -380,25 +699,110 @@ where
     #[inline]
     pub fn new(ser: &'a mut ReverseSerializer<W>) -> Self {
         let start_pos = ser.size;
+        // container の中身は TON 固有の型にしない
+        ser.magic = None;
         // ネストの深さを増やす
         ser.deep += 1;
         Self {
             ser,
             start_pos,
             variant_name: None,
+            prefix: prefix::ARRAY,
+            entries: None,
+            pending_key: None,
+        }
+    }
+
+    #[inline]
+    pub fn with_prefix(ser: &'a mut ReverseSerializer<W>, prefix: u8) -> Self {
+        Self {
+            prefix,
+            ..Self::new(ser)
         }
     }
 
     #[inline]
     pub fn with_variant(ser: &'a mut ReverseSerializer<W>, variant_name: &'static str) -> Self {
         let start_pos = ser.size;
+        // container の中身は TON 固有の型にしない
+        ser.magic = None;
         // ネストの深さを増やす
         ser.deep += 2;
         Self {
             ser,
             start_pos,
             variant_name: Some(variant_name),
+            prefix: prefix::ARRAY,
+            entries: None,
+            pending_key: None,
+        }
+    }
+
+    /// Object の要素を並べ替えて書くようにする
+    /// 
+    /// canonical モードでない場合はそのまま書きます
+    /// 
+    /// return: Compound
+    #[inline]
+    fn sorted(mut self) -> Self {
+        if self.ser.canonical {
+            self.entries = Some(Vec::new());
+        }
+        self
+    }
+
+    /// 値を canonical モードの別の ReverseSerializer で書いてバイト列にする
+    /// 
+    /// value: &T
+    /// 
+    /// return: Result<Vec<u8>, Error>
+    #[inline]
+    fn encode<T>(value: &T) -> Result<Vec<u8>, Error>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        let mut ser = ReverseSerializer::new(Vec::new()).canonical();
+        value.serialize(&mut ser)?;
+        Ok(ser.into_inner())
+    }
+
+    /// Object の要素を 1 つ書く
+    /// 
+    /// 並べ替える場合は end まで溜めておきます
+    /// 
+    /// key: &K
+    /// value: &V
+    /// 
+    /// return: Result<(), Error>
+    #[inline]
+    fn write_entry<K, V>(&mut self, key: &K, value: &V) -> Result<(), Error>
+    where
+        K: ?Sized + ser::Serialize,
+        V: ?Sized + ser::Serialize,
+    {
+        if let Some(entries) = &mut self.entries {
+            entries.push((Self::encode(key)?, Self::encode(value)?));
+            return Ok(());
+        }
+        // 逆順のため、valueを先にシリアライズ
+        value.serialize(&mut *self.ser)?;
+        key.serialize(&mut *self.ser)?;
+        Ok(())
+    }
+
+    /// 溜めておいた Object の要素を key のバイト列の順に書く
+    /// 
+    /// return: Result<(), Error>
+    #[inline]
+    fn flush_entries(&mut self) -> Result<(), Error> {
+        if let Some(mut entries) = self.entries.take() {
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, value) in entries {
+                self.ser.write_iter(value.iter().chain(key.iter()))?;
+                self.ser.size += value.len() + key.len();
+            }
         }
+        Ok(())
     }
 }
 
@@ -458,7 +862,7 @@ where
         // シーケンスの合計サイズを計算
         let seq_size = self.ser.size - self.start_pos;
         // ヘッダを生成
-        let (header, header_size) = generate_header(prefix::ARRAY, seq_size);
+        let (header, header_size) = generate_header(self.prefix, seq_size);
         // ヘッダを書き込み
         self.ser.write_iter(header[..header_size].iter().rev())?;
         // ヘッダ分のサイズを加算
@@ -554,18 +958,17 @@ where
         where
             K: ?Sized + ser::Serialize,
             V: ?Sized + ser::Serialize, {
-        
-        // 逆順のため、valueを先にシリアライズ
-        value.serialize(&mut *self.ser)?;
-        key.serialize(&mut *self.ser)?;
-
-        Ok(())
+        self.write_entry(key, value)
     }
 
     #[inline]
     fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
     where
         T: ?Sized + ser::Serialize {
+        if self.entries.is_some() {
+            self.pending_key = Some(Self::encode(key)?);
+            return Ok(());
+        }
         key.serialize(&mut *self.ser)?;
         Ok(())
     }
@@ -574,12 +977,18 @@ where
     fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
         T: ?Sized + ser::Serialize {
+        if let Some(entries) = &mut self.entries {
+            let key = self.pending_key.take().ok_or_else(|| <Error as ser::Error>::custom("serialize_value called before serialize_key"))?;
+            entries.push((key, Self::encode(value)?));
+            return Ok(());
+        }
         value.serialize(&mut *self.ser)?;
         Ok(())
     }
 
     #[inline]
-    fn end(self) -> Result<Self::Ok, Self::Error> {
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        self.flush_entries()?;
         // ネストを抜ける
         self.ser.deep -= 1;
         // Mapの合計サイズを計算
@@ -606,14 +1015,12 @@ where
     fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
         T: ?Sized + ser::Serialize {
-        // 逆順のため、valueを先にシリアライズ
-        value.serialize(&mut *self.ser)?;
-        key.serialize(&mut *self.ser)?;
-        Ok(())
+        self.write_entry(key, value)
     }
 
     #[inline]
-    fn end(self) -> Result<Self::Ok, Self::Error> {
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        self.flush_entries()?;
         // ネストを抜ける
         self.ser.deep -= 1;
         // Structの合計サイズを計算
@@ -640,14 +1047,12 @@ where
     fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
         T: ?Sized + ser::Serialize {
-        // 逆順のため、valueを先にシリアライズ
-        value.serialize(&mut *self.ser)?;
-        key.serialize(&mut *self.ser)?;
-        Ok(())
+        self.write_entry(key, value)
     }
 
     #[inline]
-    fn end(self) -> Result<Self::Ok, Self::Error> {
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        self.flush_entries()?;
         // ネストを抜ける(map と map 分)
         self.ser.deep -= 2;
         // structの合計サイズを計算
@@ -710,10 +1115,11 @@ pub fn generate_header(prefix: u8, size_of_byte: usize) -> ([u8; 9], usize) {
 #[cfg(test)]
 mod ser_tests {
     use serde::ser::SerializeSeq;
-    use std::collections::HashMap;
+    use std::collections::BTreeMap;
     use serde::{Serialize, Serializer};
 
     use super::*;
+    use crate::ton::serde::value::{num::Float, value::Value};
     // Test ReverseSerializer using an in-memory Vec<u8>
     #[test]
     fn test_serialize_bool() {
@@ -1066,7 +1472,7 @@ mod ser_tests {
     fn test_serialize_map() {
         let mut out = Vec::new();
         {
-            let mut map = HashMap::new();
+            let mut map = BTreeMap::new();
             map.insert("Hello, world!", 42u8);
             let mut serializer = ReverseSerializer::new(&mut out);
             map.serialize(&mut serializer).unwrap();
@@ -1161,4 +1567,129 @@ mod ser_tests {
         let expected = vec![prefix::UINT | SIZE_PREFIX_8BYTE, 0, 0, 0, 0, 1, 0, 0, 0];
         assert_eq!(header[..header_size].to_vec(), expected);
     }
-}
\ No newline at end of file
+
+    fn canonical_bytes<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut serializer = ReverseSerializer::new(Vec::new()).canonical();
+        value.serialize(&mut serializer).unwrap();
+        serializer.into_inner()
+    }
+
+    #[test]
+    fn test_canonical_key_order() {
+        /// 渡した順に entry を書く map
+        struct Entries(Vec<(&'static str, u8)>);
+        impl Serialize for Entries {
+            fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+                serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Ab {
+            a: u8,
+            b: u8,
+        }
+        #[derive(Serialize)]
+        struct Ba {
+            b: u8,
+            a: u8,
+        }
+
+        // entry の順や struct のフィールドの順によらず同じバイト列になる
+        let entries: Vec<(&str, u8)> = ["a", "b", "c", "d"].into_iter().zip(0..).collect();
+        let expected = canonical_bytes(&Entries(entries.clone()));
+        for shift in 1..entries.len() {
+            let mut rotated = entries.clone();
+            rotated.rotate_left(shift);
+            assert_eq!(canonical_bytes(&Entries(rotated)), expected);
+        }
+        let reversed = entries.iter().rev().copied().collect();
+        assert_eq!(canonical_bytes(&Entries(reversed)), expected);
+        // canonical でなければ渡した順のまま書く
+        assert_ne!(canonical_bytes(&Entries(entries.clone())), {
+            let mut plain = ReverseSerializer::new(Vec::new());
+            Entries(entries.iter().rev().copied().collect()).serialize(&mut plain).unwrap();
+            plain.into_inner()
+        });
+        assert_eq!(canonical_bytes(&Ab { a: 1, b: 2 }), canonical_bytes(&Ba { b: 2, a: 1 }));
+        assert_ne!(canonical_bytes(&Ab { a: 1, b: 2 }), canonical_bytes(&Ba { b: 1, a: 2 }));
+    }
+
+    #[test]
+    fn test_canonical_integer_width() {
+        assert_eq!(canonical_bytes(&1u64), vec![1, prefix::UINT | SIZE_PREFIX_1BYTE]);
+        assert_eq!(canonical_bytes(&1u64), canonical_bytes(&1u16));
+        assert_eq!(canonical_bytes(&-2i64), canonical_bytes(&-2i8));
+        assert_eq!(canonical_bytes(&300i128), vec![44, 1, prefix::INT | SIZE_PREFIX_2BYTE]);
+        assert_eq!(canonical_bytes(&u128::MAX).len(), 17);
+        // 符号は保つ
+        assert_ne!(canonical_bytes(&1u8), canonical_bytes(&1i8));
+    }
+
+    #[test]
+    fn test_canonical_float() {
+        assert_eq!(canonical_bytes(&1.5f64), canonical_bytes(&1.5f32));
+        assert_eq!(canonical_bytes(&-0.0f64), canonical_bytes(&0.0f32));
+        assert_eq!(canonical_bytes(&f64::from_bits(0x7ff8_0000_0000_0001)), canonical_bytes(&f32::NAN));
+        assert_eq!(canonical_bytes(&0.1f64).len(), 9);
+    }
+
+    #[test]
+    fn test_canonical_float_types() {
+        // 等しい Value は幅によらず同じバイト列になる
+        for (a, b) in [
+            (Float::F16(f16::from_f32(1.0)), Float::F32(1.0)),
+            (Float::F16(f16::from_f32(1.0)), Float::F64(1.0)),
+            (Float::F16(f16::from_f32(0.5)), Float::F64(0.5)),
+            (Float::F16(f16::NAN), Float::F64(f64::NAN)),
+            (Float::F16(f16::INFINITY), Float::F32(f32::INFINITY)),
+        ] {
+            let (a, b) = (Value::Float(a), Value::Float(b));
+            assert_eq!(a, b);
+            assert_eq!(canonical_bytes(&a), canonical_bytes(&b));
+        }
+        assert_eq!(canonical_bytes(&Value::Float(Float::F16(f16::from_f32(1.0)))), vec![0, 0, 0x80, 0x3F, prefix::FLOAT | SIZE_PREFIX_4BYTE]);
+        // canonical モードでなければ f16 のまま書く
+        assert_eq!(crate::ton::to_vec(&Value::Float(Float::F16(f16::from_f32(1.0)))).unwrap(), vec![0x00, 0x3C, prefix::FLOAT | SIZE_PREFIX_2BYTE]);
+    }
+
+    #[test]
+    fn test_compact_width() {
+        fn compact_bytes<T: Serialize>(value: &T) -> Vec<u8> {
+            let mut serializer = ReverseSerializer::new(Vec::new()).compact();
+            value.serialize(&mut serializer).unwrap();
+            serializer.into_inner()
+        }
+
+        assert_eq!(compact_bytes(&1u64), vec![1, prefix::UINT | SIZE_PREFIX_1BYTE]);
+        assert_eq!(compact_bytes(&-129i64), vec![0x7f, 0xff, prefix::INT | SIZE_PREFIX_2BYTE]);
+        assert_eq!(compact_bytes(&u64::MAX).len(), 9);
+        assert_eq!(compact_bytes(&0.5f64), compact_bytes(&0.5f32));
+        assert_eq!(compact_bytes(&0.1f64).len(), 9);
+        // canonical モードと違い、NaN の payload と -0.0 は保つ
+        assert_eq!(compact_bytes(&-0.0f64), vec![0, 0, 0, 0x80, prefix::FLOAT | SIZE_PREFIX_4BYTE]);
+        assert_eq!(compact_bytes(&f64::from_bits(0x7ff8_0000_0000_0001)).len(), 9);
+    }
+
+    #[test]
+    fn test_pad_to() {
+        for written in 0..600usize {
+            for alignment in [1, 2, 3, 256, 512] {
+                let mut serializer = ReverseSerializer::new(Vec::new());
+                serializer.serialize_bytes(&vec![0xAA; written]).unwrap();
+                serializer.pad_to(alignment).unwrap();
+                let size = serializer.size();
+                let out = serializer.into_inner();
+                assert_eq!(out.len(), size);
+                assert_eq!(size % alignment, 0);
+                assert!(size - written < 2 * alignment + 3);
+            }
+        }
+        // 2byte の PADDING は size 0 の body を持つ
+        let mut serializer = ReverseSerializer::new(Vec::new());
+        serializer.serialize_none().unwrap();
+        serializer.pad_to(3).unwrap();
+        assert_eq!(serializer.into_inner(), vec![prefix::NONE, 0, prefix::PADDING | SIZE_PREFIX_1BYTE]);
+        assert!(ReverseSerializer::new(Vec::new()).pad_to(0).is_err());
+    }
+}
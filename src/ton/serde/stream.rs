@@ -0,0 +1,330 @@
+//! TON のストリームリーダー
+//!
+//! ドキュメント全体を読み込まずに、値を前から順にイベントとして取り出します
+//! 読み込みは `Read::get_chunk` を使ったチャンク単位で行われ、
+//! メモリに載るのは読み込み中のチャンクと container ごとの要素の位置だけです
+//!
+//! 信頼できない入力は `StreamReader::limits` で上限を決めて読みます
+//! 文字列の長さと要素数はイベントごとに、入れ子の深さは開いている container の数で比べます
+//! 確保したバイト数はイベントごとに数え直すので、max_alloc は 1 つのイベントで読む量の上限になります
+
+use alloc::vec::Vec;
+
+use serde::Deserialize;
+
+use super::{de::{Deserializer, Limits, Read, TonTypes}, error::{Error, ErrorCode, Result}, value::value::{KeyValue, Value}};
+
+/// ストリームリーダーが返すイベント
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Array の開始
+    ///
+    /// 要素数を持ちます
+    StartArray(usize),
+    /// Array の終了
+    EndArray,
+    /// Object の開始
+    ///
+    /// 要素数を持ちます
+    StartObject(usize),
+    /// Object の終了
+    EndObject,
+    /// Object の key
+    ///
+    /// 続くイベントがこの key の値です
+    Key(KeyValue),
+    /// Meta の開始
+    ///
+    /// metadata, 値 の順にイベントが続きます
+    StartMeta,
+    /// Meta の終了
+    EndMeta,
+    /// container 以外の値
+    Scalar(Value),
+}
+
+/// 読み込み中の container
+enum Frame {
+    Array(alloc::vec::IntoIter<usize>),
+    Object(alloc::vec::IntoIter<usize>, Option<usize>),
+    Meta(alloc::vec::IntoIter<usize>),
+}
+
+/// Pull 型の TON ストリームリーダー
+///
+/// `next_event` を呼ぶたびに 1 つのイベントを返します
+pub struct StreamReader<R> {
+    de: Deserializer<R>,
+    next: Option<usize>,
+    stack: Vec<Frame>,
+    limits: Limits,
+}
+
+impl<'de, R> StreamReader<R>
+where
+    R: Read<'de>,
+{
+    /// reader の末尾にある値を読むStreamReaderを作る
+    ///
+    /// 再帰せずに読むので、上限を設定しない場合は入れ子の深さも制限しません
+    ///
+    /// reader: R
+    ///
+    /// return: Result<StreamReader>
+    #[inline]
+    pub fn new(reader: R) -> Result<Self> {
+        let limits = Limits { max_depth: usize::MAX, ..Limits::new() };
+        let mut de = Deserializer::from_tail(reader)?.limits(limits);
+        de.end()?;
+        let root = de.pos();
+        Ok(Self {
+            de,
+            next: Some(root),
+            stack: Vec::new(),
+            limits,
+        })
+    }
+
+    /// 資源の上限を設定する
+    ///
+    /// limits: Limits // Limits::untrusted() など
+    ///
+    /// return: StreamReader
+    #[inline]
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.de = self.de.limits(limits);
+        self.limits = limits;
+        self
+    }
+
+    /// 次のイベントを読む
+    ///
+    /// return: Result<Option<Event>> // 最後まで読んだら None
+    pub fn next_event(&mut self) -> Result<Option<Event>> {
+        self.de.reset_allocated();
+        if let Some(pos) = self.next.take() {
+            return self.open(pos).map(Some);
+        }
+        let event = match self.stack.last_mut() {
+            None => return Ok(None),
+            Some(Frame::Array(elements)) => match elements.next() {
+                Some(pos) => return self.open(pos).map(Some),
+                None => Event::EndArray,
+            },
+            Some(Frame::Meta(elements)) => match elements.next() {
+                Some(pos) => return self.open(pos).map(Some),
+                None => Event::EndMeta,
+            },
+            Some(Frame::Object(elements, value_pos)) => {
+                if let Some(pos) = value_pos.take() {
+                    return self.open(pos).map(Some);
+                }
+                // 要素は value, key の順に並んでいる
+                match (elements.next(), elements.next()) {
+                    (Some(value), Some(key)) => {
+                        *value_pos = Some(value);
+                        self.de.set_pos(key);
+                        return KeyValue::deserialize(&mut self.de).map(|key| Some(Event::Key(key)));
+                    }
+                    _ => Event::EndObject,
+                }
+            }
+        };
+        self.stack.pop();
+        Ok(Some(event))
+    }
+
+    /// 読み込み中の container の残りを読み飛ばす
+    ///
+    /// 直前に Start のイベントを受け取った container を読まない場合に使います
+    /// 対応する End のイベントは返されません
+    #[inline]
+    pub fn skip_container(&mut self) {
+        self.stack.pop();
+    }
+
+    /// readerを取り出す
+    ///
+    /// return: R
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.de.into_inner()
+    }
+
+    /// pos にある値を開く
+    ///
+    /// container はフレームを積んで Start のイベントを返します
+    ///
+    /// pos: usize // prefix の位置
+    ///
+    /// return: Result<Event>
+    fn open(&mut self, pos: usize) -> Result<Event> {
+        let header = self.de.read_header(pos)?;
+        if matches!(header.ton_type, TonTypes::Array | TonTypes::Object | TonTypes::Meta) && self.stack.len() >= self.limits.max_depth {
+            return Err(Error::syntax(ErrorCode::DepthLimit, pos));
+        }
+        match header.ton_type {
+            TonTypes::Array => {
+                let elements = self.de.read_elements(pos, &header)?;
+                let event = Event::StartArray(elements.len());
+                self.stack.push(Frame::Array(elements.into_iter()));
+                Ok(event)
+            }
+            TonTypes::Object => {
+                let elements = self.de.read_elements(pos, &header)?;
+                if elements.len() % 2 != 0 {
+                    return Err(Error::syntax(ErrorCode::InvalidType, pos));
+                }
+                let event = Event::StartObject(elements.len() / 2);
+                self.stack.push(Frame::Object(elements.into_iter(), None));
+                Ok(event)
+            }
+            TonTypes::Meta => {
+                let elements = self.de.read_elements(pos, &header)?;
+                if elements.len() != 2 {
+                    return Err(Error::syntax(ErrorCode::InvalidType, pos));
+                }
+                self.stack.push(Frame::Meta(elements.into_iter()));
+                Ok(Event::StartMeta)
+            }
+            _ => {
+                self.de.set_pos(pos);
+                Value::deserialize(&mut self.de).map(Event::Scalar)
+            }
+        }
+    }
+}
+
+impl<'de, R> Iterator for StreamReader<R>
+where
+    R: Read<'de>,
+{
+    type Item = Result<Event>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use serde::Serialize;
+
+    use super::*;
+    use crate::ton::{serde::de::SliceRead, to_vec, WithMeta};
+    use crate::ton::serde::value::num::{Int, UInt};
+
+    #[derive(Serialize)]
+    struct Record {
+        id: u8,
+        tags: Vec<i32>,
+    }
+
+    fn events(bytes: &[u8]) -> Vec<Event> {
+        StreamReader::new(SliceRead::new(bytes)).unwrap().collect::<Result<_>>().unwrap()
+    }
+
+    fn key(name: &str) -> Event {
+        Event::Key(KeyValue::String(name.to_string()))
+    }
+
+    #[test]
+    fn test_events() {
+        let bytes = to_vec(&Record { id: 7, tags: vec![-1, 2] }).unwrap();
+        assert_eq!(events(&bytes), vec![
+            Event::StartObject(2),
+            key("id"),
+            Event::Scalar(Value::UInt(UInt::U8(7))),
+            key("tags"),
+            Event::StartArray(2),
+            Event::Scalar(Value::Int(Int::I32(-1))),
+            Event::Scalar(Value::Int(Int::I32(2))),
+            Event::EndArray,
+            Event::EndObject,
+        ]);
+    }
+
+    #[test]
+    fn test_meta_and_scalar_root() {
+        assert_eq!(events(&to_vec(&true).unwrap()), vec![Event::Scalar(Value::Bool(true))]);
+
+        let bytes = to_vec(&WithMeta::new(Value::UInt(UInt::U8(1)), "v")).unwrap();
+        assert_eq!(events(&bytes), vec![
+            Event::StartMeta,
+            Event::Scalar(Value::UInt(UInt::U8(1))),
+            Event::Scalar(Value::String("v".to_string())),
+            Event::EndMeta,
+        ]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_io_read_over_chunks() {
+        use std::{collections::BTreeMap, io::Cursor};
+
+        use crate::ton::serde::de::IoRead;
+
+        // チャンクをまたぐ大きさのドキュメント
+        let mut map = BTreeMap::new();
+        for i in 0..2000u32 {
+            map.insert(format!("key{:05}", i), vec![i; 3]);
+        }
+        let bytes = to_vec(&map).unwrap();
+        assert!(bytes.len() > 4096 * 4);
+
+        let mut reader = StreamReader::new(IoRead::new(Cursor::new(bytes))).unwrap();
+        assert_eq!(reader.next_event().unwrap(), Some(Event::StartObject(2000)));
+        assert_eq!(reader.next_event().unwrap(), Some(key("key00000")));
+        let mut scalars = 0;
+        for event in reader {
+            if let Event::Scalar(_) = event.unwrap() {
+                scalars += 1;
+            }
+        }
+        assert_eq!(scalars, 2000 * 3);
+    }
+
+    #[test]
+    fn test_limits() {
+        let limited = |bytes: &[u8], limits: Limits| -> Result<Vec<Event>> {
+            StreamReader::new(SliceRead::new(bytes))?.limits(limits).collect()
+        };
+
+        let bytes = to_vec(&vec!["a".repeat(100)]).unwrap();
+        assert!(limited(&bytes, Limits { max_string_len: 100, ..Limits::new() }).is_ok());
+        let err = limited(&bytes, Limits { max_string_len: 99, ..Limits::new() }).unwrap_err();
+        assert!(matches!(err.code(), ErrorCode::StringLimit));
+
+        let bytes = to_vec(&Record { id: 7, tags: vec![1, 2, 3] }).unwrap();
+        assert!(limited(&bytes, Limits { max_elements: 3, ..Limits::new() }).is_ok());
+        let err = limited(&bytes, Limits { max_elements: 2, ..Limits::new() }).unwrap_err();
+        assert!(matches!(err.code(), ErrorCode::ElementLimit));
+
+        let bytes = to_vec(&vec![vec![vec![1u8]]]).unwrap();
+        assert!(limited(&bytes, Limits { max_depth: 3, ..Limits::new() }).is_ok());
+        let err = limited(&bytes, Limits { max_depth: 2, ..Limits::new() }).unwrap_err();
+        assert!(matches!(err.code(), ErrorCode::DepthLimit));
+
+        // max_alloc はイベントごとに数えるので、合計が上限を超える文字列の列も読める
+        let bytes = to_vec(&vec!["a".repeat(64); 16]).unwrap();
+        let limits = Limits { max_alloc: 16 * size_of::<Value>() + 64, ..Limits::new() };
+        assert_eq!(limited(&bytes, limits).unwrap().len(), 18);
+        let err = limited(&bytes, Limits { max_alloc: 63, ..Limits::new() }).unwrap_err();
+        assert!(matches!(err.code(), ErrorCode::AllocLimit));
+    }
+
+    #[test]
+    fn test_skip_container() {
+        let bytes = to_vec(&vec![vec![1u8, 2], vec![3]]).unwrap();
+        let mut reader = StreamReader::new(SliceRead::new(&bytes)).unwrap();
+        assert_eq!(reader.next_event().unwrap(), Some(Event::StartArray(2)));
+        assert_eq!(reader.next_event().unwrap(), Some(Event::StartArray(2)));
+        reader.skip_container();
+        assert_eq!(reader.next_event().unwrap(), Some(Event::StartArray(1)));
+        assert_eq!(reader.next_event().unwrap(), Some(Event::Scalar(Value::UInt(UInt::U8(3)))));
+        assert_eq!(reader.next_event().unwrap(), Some(Event::EndArray));
+        assert_eq!(reader.next_event().unwrap(), Some(Event::EndArray));
+        assert_eq!(reader.next_event().unwrap(), None);
+    }
+}
@@ -0,0 +1,665 @@
+//! TON のテキスト表現
+//!
+//! 人が読んで手で直せるように、TON の値を JSON を拡張したテキストで表します
+//! JSON のテキストはそのまま読めます
+//!
+//! - 数値は `5u8` `-3i32` `1.5f32` のように Rust と同じ suffix で幅を表します
+//!   suffix の無い整数は負なら i64、それ以外は u64 に、小数は f64 になります
+//!   `NaN` `Infinity` `-Infinity` も書けます
+//! - TON 固有の型は `{"$uuid": "..."}` のように `prefix_pua_utf8` のタグを key にした 1 要素の Object で表します
+//!   Bytes は `{"$bytes": "16 進数"}`、Undefined は `{"$undefined": null}` です
+//!   タグの中身が決まった形でない場合は Object にせずエラーになります
+//! - key が String でない Object は `{"$object": [[key, value], ...]}` で表します
+//!   タグと同じ key を 1 つだけ持つ Object もこの形にして区別します
+//!
+//! バイナリの TON とは Value を通して相互に変換でき、NaN の payload を除いて値は変わりません
+//! 入れ子の深さはバイナリの decoder と同じく `Limits::DEFAULT_MAX_DEPTH` までです
+
+use alloc::{vec::Vec, string::{String, ToString}, format, vec};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{de::Limits, error::{Error, ErrorCode, Result}, types, value::{map::Map, num::{Float, Int, UInt}, prefix::prefix_pua_utf8, value::{KeyValue, Value}}};
+
+/// テキストで特別な意味を持つタグ
+pub(crate) const TAGS: [&str; 10] = [
+    prefix_pua_utf8::UNDEFINED,
+    prefix_pua_utf8::BYTES,
+    prefix_pua_utf8::UUID,
+    prefix_pua_utf8::DATETIME,
+    prefix_pua_utf8::TIMESTAMP,
+    prefix_pua_utf8::DURATION,
+    prefix_pua_utf8::FLOAT,
+    prefix_pua_utf8::OBJECT,
+    prefix_pua_utf8::WRAPPED_JSON,
+    prefix_pua_utf8::META,
+];
+
+/// T をテキストにする
+///
+/// value: &T
+///
+/// return: Result<String>
+#[inline]
+pub fn to_text<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    let value = super::value::ser::to_value(value)?;
+    Ok(Printer::new(None).print(&value))
+}
+
+/// T を改行とインデント付きのテキストにする
+///
+/// value: &T
+///
+/// return: Result<String>
+#[inline]
+pub fn to_text_pretty<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    let value = super::value::ser::to_value(value)?;
+    Ok(Printer::new(Some("  ")).print(&value))
+}
+
+/// テキストから T を読む
+///
+/// text: &str
+///
+/// return: Result<T>
+#[inline]
+pub fn from_text<T>(text: &str) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let value = Parser::new(text).parse()?;
+    super::value::de::from_value(value)
+}
+
+/// バイナリの TON をテキストにする
+///
+/// input: &[u8]
+///
+/// return: Result<String>
+#[inline]
+pub fn binary_to_text(input: &[u8]) -> Result<String> {
+    let value: Value = super::super::from_slice(input)?;
+    Ok(Printer::new(Some("  ")).print(&value))
+}
+
+/// テキストをバイナリの TON にする
+///
+/// text: &str
+///
+/// return: Result<Vec<u8>>
+#[inline]
+pub fn text_to_binary(text: &str) -> Result<Vec<u8>> {
+    let value = Parser::new(text).parse()?;
+    super::super::to_vec(&value)
+}
+
+/// Value をテキストに書き出す
+struct Printer {
+    out: String,
+    indent: Option<&'static str>,
+    level: usize,
+}
+
+impl Printer {
+    #[inline]
+    fn new(indent: Option<&'static str>) -> Self {
+        Self { out: String::new(), indent, level: 0 }
+    }
+
+    /// value を書き出して返す
+    ///
+    /// value: &Value
+    ///
+    /// return: String
+    fn print(mut self, value: &Value) -> String {
+        self.value(value);
+        self.out
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::None => self.out.push_str("null"),
+            Value::Bool(v) => self.out.push_str(if *v { "true" } else { "false" }),
+            Value::Int(_) | Value::UInt(_) | Value::Float(_) => self.out.push_str(&format_number(value).unwrap_or_default()),
+            Value::String(v) => self.string(v),
+            Value::Bytes(v) => self.tagged(prefix_pua_utf8::BYTES, &Value::String(bytes_to_hex(v))),
+            Value::Array(v) => self.array(v),
+            Value::Object(v) => self.object(v),
+            other => match types::to_tagged(other) {
+                Some((tag, payload)) => self.tagged(tag, &payload),
+                // タグの無い型は上で書いている
+                None => self.out.push_str("null"),
+            },
+        }
+    }
+
+    fn string(&mut self, v: &str) {
+        // JSON と同じエスケープ
+        self.out.push_str(&serde_json::to_string(v).unwrap_or_default());
+    }
+
+    fn tagged(&mut self, tag: &str, payload: &Value) {
+        self.out.push('{');
+        self.string(tag);
+        self.out.push_str(": ");
+        self.value(payload);
+        self.out.push('}');
+    }
+
+    fn array(&mut self, v: &[Value]) {
+        self.out.push('[');
+        if !v.is_empty() {
+            self.level += 1;
+            for (i, elem) in v.iter().enumerate() {
+                self.separator(i);
+                self.value(elem);
+            }
+            self.level -= 1;
+            self.newline();
+        }
+        self.out.push(']');
+    }
+
+    fn object(&mut self, v: &Map<KeyValue, Value>) {
+        let plain = v.keys().all(|k| matches!(k, KeyValue::String(_)))
+            && !(v.len() == 1 && v.keys().any(|k| matches!(k, KeyValue::String(k) if TAGS.contains(&k.as_str()))));
+        if !plain {
+            // key が String でない Object は [key, value] の組の Array にする
+            let pairs = v.iter().map(|(k, v)| Value::Array(vec![Value::from(k.clone()), v.clone()])).collect();
+            return self.tagged(prefix_pua_utf8::OBJECT, &Value::Array(pairs));
+        }
+        self.out.push('{');
+        if !v.is_empty() {
+            self.level += 1;
+            for (i, (key, value)) in v.iter().enumerate() {
+                self.separator(i);
+                if let KeyValue::String(key) = key {
+                    self.string(key);
+                }
+                self.out.push_str(": ");
+                self.value(value);
+            }
+            self.level -= 1;
+            self.newline();
+        }
+        self.out.push('}');
+    }
+
+    /// 要素の前の区切りを書く
+    fn separator(&mut self, index: usize) {
+        if index > 0 {
+            self.out.push(',');
+            if self.indent.is_none() {
+                self.out.push(' ');
+            }
+        }
+        self.newline();
+    }
+
+    fn newline(&mut self) {
+        if let Some(indent) = self.indent {
+            self.out.push('\n');
+            for _ in 0..self.level {
+                self.out.push_str(indent);
+            }
+        }
+    }
+}
+
+/// テキストから Value を読む
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    #[inline]
+    fn new(input: &'a str) -> Self {
+        Self { input, pos: 0, depth: 0 }
+    }
+
+    /// 全体を 1 つの値として読む
+    ///
+    /// return: Result<Value>
+    fn parse(mut self) -> Result<Value> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        if self.pos != self.input.len() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    #[cold]
+    fn error(&self, msg: &str) -> Error {
+        Error::syntax(ErrorCode::Message(msg.to_string()), self.pos)
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b) if b == byte => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected `{}`", byte as char))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.nested(Self::object),
+            Some(b'[') => self.nested(Self::array).map(Value::Array),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'-' | b'0'..=b'9' | b'N' | b'I') => self.number(),
+            Some(_) if self.input[self.pos..].starts_with("null") => self.keyword("null", Value::None),
+            Some(_) if self.input[self.pos..].starts_with("true") => self.keyword("true", Value::Bool(true)),
+            Some(_) if self.input[self.pos..].starts_with("false") => self.keyword("false", Value::Bool(false)),
+            Some(_) => Err(self.error("expected value")),
+            None => Err(Error::syntax(ErrorCode::NotFoundTarget, self.pos)),
+        }
+    }
+
+    /// 1 段深い container として f を呼ぶ
+    ///
+    /// 深さはバイナリのデコーダーと同じ上限で制限します
+    ///
+    /// f: F
+    ///
+    /// return: Result<T>
+    #[inline]
+    fn nested<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        if self.depth >= Limits::DEFAULT_MAX_DEPTH {
+            return Err(Error::syntax(ErrorCode::DepthLimit, self.pos));
+        }
+        self.depth += 1;
+        let value = f(self);
+        self.depth -= 1;
+        value
+    }
+
+    #[inline]
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value> {
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect(b'"')?;
+        let start = self.pos - 1;
+        let bytes = self.input.as_bytes();
+        let mut escaped = false;
+        while let Some(&b) = bytes.get(self.pos) {
+            self.pos += 1;
+            match b {
+                b'\\' if !escaped => escaped = true,
+                b'"' if !escaped => {
+                    // エスケープの解釈は JSON と同じ
+                    return serde_json::from_str(&self.input[start..self.pos])
+                        .map_err(|e| Error::syntax(ErrorCode::Message(e.to_string()), start));
+                }
+                _ => escaped = false,
+            }
+        }
+        Err(Error::syntax(ErrorCode::NotFoundTarget, self.pos))
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        let rest = &self.input[start..];
+        let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.'))).unwrap_or(rest.len());
+        self.pos += len;
+        parse_number(&rest[..len])
+            .ok_or_else(|| Error::syntax(ErrorCode::Message(format!("invalid number `{}`", &rest[..len])), start))
+    }
+
+    fn array(&mut self) -> Result<Vec<Value>> {
+        self.expect(b'[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(values);
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(values);
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        let start = self.pos;
+        self.expect(b'{')?;
+        let mut map = Map::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(map));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':')?;
+            let value = self.value()?;
+            map.insert(KeyValue::String(key), value);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+        // タグ 1 つだけの Object は TON 固有の型
+        match map.iter().next() {
+            Some((KeyValue::String(tag), payload)) if map.len() == 1 && TAGS.contains(&tag.as_str()) => tagged(tag, payload)
+                .ok_or_else(|| Error::syntax(ErrorCode::Message(format!("invalid payload for `{}`", tag)), start)),
+            _ => Ok(Value::Object(map)),
+        }
+    }
+}
+
+/// バイト列を 16 進数の文字列にする
+///
+/// v: &[u8]
+///
+/// return: String
+#[inline]
+pub(crate) fn bytes_to_hex(v: &[u8]) -> String {
+    v.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 数値を suffix 付きのテキストにする
+///
+/// value: &Value
+///
+/// return: Option<String> // 数値でない場合は None
+pub(crate) fn format_number(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::Int(Int::I8(v)) => format!("{}i8", v),
+        Value::Int(Int::I16(v)) => format!("{}i16", v),
+        Value::Int(Int::I32(v)) => format!("{}i32", v),
+        Value::Int(Int::I64(v)) => format!("{}i64", v),
+        Value::Int(Int::I128(v)) => format!("{}i128", v),
+        Value::UInt(UInt::U8(v)) => format!("{}u8", v),
+        Value::UInt(UInt::U16(v)) => format!("{}u16", v),
+        Value::UInt(UInt::U32(v)) => format!("{}u32", v),
+        Value::UInt(UInt::U64(v)) => format!("{}u64", v),
+        Value::UInt(UInt::U128(v)) => format!("{}u128", v),
+        Value::Float(v) => {
+            let (v, suffix) = match v {
+                Float::F16(v) => (v.to_f64(), "f16"),
+                Float::F32(v) => (*v as f64, "f32"),
+                Float::F64(v) => (*v, "f64"),
+            };
+            let body = match v {
+                v if v.is_nan() => "NaN".to_string(),
+                v if v.is_infinite() && v > 0.0 => "Infinity".to_string(),
+                v if v.is_infinite() => "-Infinity".to_string(),
+                // f32 の値は f32 として一番短い表現で書く
+                v if suffix != "f64" => format!("{:?}", v as f32),
+                v => format!("{:?}", v),
+            };
+            format!("{}{}", body, suffix)
+        }
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// suffix 付きのテキストから数値を読む
+///
+/// suffix の無い整数は負なら i64、それ以外は u64 に、小数は f64 になります
+///
+/// text: &str
+///
+/// return: Option<Value> // 数値として読めない場合は None
+pub(crate) fn parse_number(text: &str) -> Option<Value> {
+    let special = ["NaN", "Infinity", "-Infinity"].into_iter().find(|s| text.starts_with(s));
+    let body_len = match special {
+        Some(s) => s.len(),
+        None => text.find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))).unwrap_or(text.len()),
+    };
+    let (body, suffix) = text.split_at(body_len);
+    let is_float = special.is_some() || body.contains(['.', 'e', 'E']);
+    let suffix = match suffix {
+        "" if is_float => "f64",
+        "" if body.starts_with('-') => "i64",
+        "" => "u64",
+        s if is_float && !s.starts_with('f') => return None,
+        s => s,
+    };
+    let float = |body: &str| match body {
+        "NaN" => Some(f64::NAN),
+        "Infinity" => Some(f64::INFINITY),
+        "-Infinity" => Some(f64::NEG_INFINITY),
+        body => body.parse().ok(),
+    };
+    let value = match suffix {
+        "i8" => Value::Int(Int::I8(body.parse().ok()?)),
+        "i16" => Value::Int(Int::I16(body.parse().ok()?)),
+        "i32" => Value::Int(Int::I32(body.parse().ok()?)),
+        "i64" => Value::Int(Int::I64(body.parse().ok()?)),
+        "i128" => Value::Int(Int::I128(body.parse().ok()?)),
+        "u8" => Value::UInt(UInt::U8(body.parse().ok()?)),
+        "u16" => Value::UInt(UInt::U16(body.parse().ok()?)),
+        "u32" => Value::UInt(UInt::U32(body.parse().ok()?)),
+        "u64" => Value::UInt(UInt::U64(body.parse().ok()?)),
+        "u128" => Value::UInt(UInt::U128(body.parse().ok()?)),
+        "f16" => Value::Float(Float::F16(half::f16::from_f64(float(body)?))),
+        // f32 は f64 を経由すると丸めが 2 回になるので直接読む
+        "f32" => Value::Float(Float::F32(match special {
+            Some(_) => float(body)? as f32,
+            None => body.parse().ok()?,
+        })),
+        "f64" => Value::Float(Float::F64(float(body)?)),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// タグと中身から Value を組み立てる
+///
+/// tag: &str
+/// payload: &Value
+///
+/// return: Option<Value> // 中身が不正な場合は None
+pub(crate) fn tagged(tag: &str, payload: &Value) -> Option<Value> {
+    match (tag, payload) {
+        (prefix_pua_utf8::BYTES, Value::String(hex)) => {
+            if hex.len() % 2 != 0 || !hex.is_ascii() {
+                return None;
+            }
+            (0..hex.len()).step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+                .collect::<Option<Vec<u8>>>()
+                .map(Value::Bytes)
+        }
+        (prefix_pua_utf8::OBJECT, Value::Array(pairs)) => pairs.iter()
+            .map(|pair| match pair {
+                Value::Array(kv) if kv.len() == 2 => KeyValue::try_from(kv[0].clone()).ok().map(|k| (k, kv[1].clone())),
+                _ => None,
+            })
+            .collect::<Option<Map<KeyValue, Value>>>()
+            .map(Value::Object),
+        (prefix_pua_utf8::BYTES | prefix_pua_utf8::OBJECT, _) => None,
+        (tag, payload) => types::from_tagged(tag, payload),
+    }
+}
+
+#[cfg(test)]
+mod text_tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{TimeZone, Utc};
+    use serde::Deserialize;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::ton::{from_slice, to_vec, WithMeta};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        id: u64,
+        name: String,
+        delta: i8,
+        ratio: f32,
+        #[serde(with = "crate::ton::serde::types::uuid")]
+        owner: Uuid,
+        #[serde(with = "crate::ton::serde::types::datetime")]
+        created: chrono::DateTime<Utc>,
+        #[serde(with = "serde_bytes_like")]
+        blob: Vec<u8>,
+        tags: BTreeMap<u32, String>,
+    }
+
+    // Vec<u8> を Bytes として書く
+    mod serde_bytes_like {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(v: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(v)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+            crate::ton::Value::deserialize(deserializer).and_then(|v| match v {
+                crate::ton::Value::Bytes(v) => Ok(v),
+                _ => Err(serde::de::Error::custom("expected bytes")),
+            })
+        }
+    }
+
+    fn record() -> Record {
+        Record {
+            id: 42,
+            name: "idis \"text\"\n".to_string(),
+            delta: -3,
+            ratio: 0.1,
+            owner: Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef),
+            created: Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap(),
+            blob: vec![0, 1, 0xff],
+            tags: [(1, "a".to_string()), (20, "b".to_string())].into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let text = to_text_pretty(&record()).unwrap();
+        assert!(text.contains("\"$uuid\": \"01234567-89ab-cdef-0123-456789abcdef\""));
+        assert!(text.contains("\"$bytes\": \"0001ff\""));
+        assert!(text.contains("\"id\": 42u64"));
+        assert!(text.contains("0.1f32"));
+        assert_eq!(from_text::<Record>(&text).unwrap(), record());
+        assert_eq!(from_text::<Record>(&to_text(&record()).unwrap()).unwrap(), record());
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let value = Value::Array(vec![
+            Value::Undefined,
+            Value::Float(Float::F16(half::f16::from_f32(1.5))),
+            Value::Float(Float::F64(f64::NEG_INFINITY)),
+            Value::UInt(UInt::U128(u128::MAX)),
+            Value::Timestamp(-5),
+            Value::Duration(chrono::Duration::nanoseconds(1500)),
+            Value::WrappedJSON(serde_json::json!({"a": [1, null]})),
+            Value::String("plain".to_string()).with_meta(Value::UInt(UInt::U8(1))),
+            // タグと同じ key の Object
+            Value::Object([(KeyValue::String("$uuid".to_string()), Value::Bool(true))].into_iter().collect()),
+            // String でない key の Object
+            Value::Object([(KeyValue::from(3u32), Value::None), (KeyValue::from(-1i8), Value::Bool(false))].into_iter().collect()),
+        ]);
+        let binary = to_vec(&value).unwrap();
+        let text = binary_to_text(&binary).unwrap();
+        assert_eq!(text_to_binary(&text).unwrap(), binary);
+        assert_eq!(from_slice::<Value>(&text_to_binary(&text).unwrap()).unwrap(), value);
+
+        // WithMeta で書いた値も Value で比べると同じになる
+        let binary = to_vec(&WithMeta::new(Value::String("owner".to_string()), Value::String("v".to_string()))).unwrap();
+        let converted = text_to_binary(&binary_to_text(&binary).unwrap()).unwrap();
+        assert_eq!(from_slice::<Value>(&converted).unwrap(), from_slice::<Value>(&binary).unwrap());
+    }
+
+    #[test]
+    fn test_json_is_text() {
+        let value: Value = from_text(r#"{"a": [1, -2, 0.5, "x", null, true], "b": {}}"#).unwrap();
+        assert_eq!(value["a"][0], Value::UInt(UInt::U64(1)));
+        assert_eq!(value["a"][1], Value::Int(Int::I64(-2)));
+        assert_eq!(value["a"][2], Value::Float(Float::F64(0.5)));
+        assert_eq!(value["b"], Value::Object(Map::new()));
+    }
+
+    #[test]
+    fn test_invalid_text() {
+        assert!(from_text::<Value>("[1, 2").is_err());
+        assert!(from_text::<Value>("300u8").is_err());
+        assert!(from_text::<Value>("1.5i32").is_err());
+        assert!(from_text::<Value>(r#"{"$uuid": "not a uuid"}"#).is_err());
+        assert!(from_text::<Value>(r#"{"$bytes": "abc"}"#).is_err());
+        assert!(from_text::<Value>("1 2").is_err());
+    }
+
+    #[test]
+    fn test_invalid_payload() {
+        assert_eq!(from_text::<Value>(r#"{"$undefined": null}"#).unwrap(), Value::Undefined);
+        for text in [
+            r#"{"$undefined": 123}"#,
+            r#"{"$undefined": []}"#,
+            r#"{"$timestamp": "1"}"#,
+            r#"{"$duration": 1.5}"#,
+            r#"{"$float": "1"}"#,
+            r#"{"$meta": [1]}"#,
+            r#"{"$object": [[1]]}"#,
+        ] {
+            let err = from_text::<Value>(text).unwrap_err();
+            assert!(err.to_string().starts_with("invalid payload"), "{}: {}", text, err);
+            assert_eq!(err.offset(), Some(0));
+        }
+        // 入れ子の中でも位置は Object の先頭
+        let err = from_text::<Value>(r#"[1, {"$undefined": 123}]"#).unwrap_err();
+        assert_eq!(err.offset(), Some(4));
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let depth = Limits::DEFAULT_MAX_DEPTH;
+        let text = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(from_text::<Value>(&text).is_ok());
+        let text = format!("{}{}", "[".repeat(depth + 1), "]".repeat(depth + 1));
+        assert!(from_text::<Value>(&text).unwrap_err().is_limit());
+        assert!(from_text::<Value>(&"[".repeat(200_000)).unwrap_err().is_limit());
+        assert!(from_text::<Value>(&r#"{"a":"#.repeat(200_000)).unwrap_err().is_limit());
+    }
+}
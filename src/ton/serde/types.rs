@@ -0,0 +1,692 @@
+//! TON 固有の型を serde で扱うためのヘルパー
+//!
+//! `prefix_pua_utf8` のタグ名を newtype struct の名前として使い、
+//! ReverseSerializer はその名前を見て専用の prefix で書き込みます
+//! それ以外のフォーマットでは中身だけが透過的に書かれます
+//!
+//! `deserialize_any` では `{"$uuid": "..."}` のような 1 要素の map として渡されます
+
+use alloc::{string::{String, ToString}, boxed::Box, format, vec};
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::{de::{self, DeserializeSeed, MapAccess}, Deserialize, Deserializer, Serialize, Serializer};
+use ::uuid::Uuid;
+
+use super::{error::Error, value::{num::{Float, Int, UInt}, prefix::prefix_pua_utf8, value::Value}};
+
+/// `#[serde(with = "...::types::uuid")]` で Uuid を 16byte の UUID として書く
+pub mod uuid {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S>(v: &Uuid, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(prefix_pua_utf8::UUID, &UuidPayload(v))
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Uuid, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        uuid_from_value(&value).ok_or_else(|| de::Error::custom("invalid uuid"))
+    }
+}
+
+/// `#[serde(with = "...::types::datetime")]` で DateTime<Utc> を DATETIME として書く
+pub mod datetime {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S>(v: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(prefix_pua_utf8::DATETIME, &datetime_to_string(v))
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        datetime_from_value(&value).ok_or_else(|| de::Error::custom("invalid datetime"))
+    }
+}
+
+/// `#[serde(with = "...::types::timestamp")]` で i64 を 8byte の TIMESTAMP として書く
+pub mod timestamp {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S>(v: &i64, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(prefix_pua_utf8::TIMESTAMP, v)
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<i64, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        i64_from_value(&value).ok_or_else(|| de::Error::custom("invalid timestamp"))
+    }
+}
+
+/// `#[serde(with = "...::types::duration")]` で chrono::Duration をナノ秒 8byte の DURATION として書く
+pub mod duration {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S>(v: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let nanos = v.num_nanoseconds().ok_or_else(|| serde::ser::Error::custom("duration out of range"))?;
+        serializer.serialize_newtype_struct(prefix_pua_utf8::DURATION, &nanos)
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        duration_from_value(&value).ok_or_else(|| de::Error::custom("invalid duration"))
+    }
+}
+
+/// `#[serde(with = "...::types::float16")]` で half::f16 を 2byte の FLOAT として書く
+pub mod float16 {
+    use ::half::f16;
+
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S>(v: &f16, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(prefix_pua_utf8::FLOAT, &v.to_f32())
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<f16, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(f16::from_f32(f32::deserialize(deserializer)?))
+    }
+}
+
+/// `#[serde(with = "...::types::wrapped_json")]` で serde_json::Value を WRAPPED_JSON として書く
+pub mod wrapped_json {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S>(v: &serde_json::Value, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(prefix_pua_utf8::WRAPPED_JSON, &WrappedJsonPayload(v))
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<serde_json::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Value::deserialize(deserializer)? {
+            Value::WrappedJSON(v) => Ok(v),
+            other => serde_json::to_value(&other).map_err(de::Error::custom),
+        }
+    }
+}
+
+/// メタデータ付きの値
+///
+/// TON では META として metadata, 値 の順で書かれ、
+/// それ以外のフォーマットでは [metadata, 値] の組として書かれます
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithMeta<T> {
+    pub meta: Value,
+    pub value: T,
+}
+
+impl<T> WithMeta<T> {
+    #[inline]
+    pub fn new(meta: Value, value: T) -> Self {
+        Self { meta, value }
+    }
+}
+
+impl<T: Serialize> Serialize for WithMeta<T> {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(prefix_pua_utf8::META, &(&self.meta, &self.value))
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for WithMeta<T> {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(WithMetaVisitor(core::marker::PhantomData))
+    }
+}
+
+struct WithMetaVisitor<T>(core::marker::PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> de::Visitor<'de> for WithMetaVisitor<T> {
+    type Value = WithMeta<T>;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a value with metadata")
+    }
+
+    /// TON と Value からは {"$meta": [metadata, 値]} として渡される
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        match map.next_key::<String>()? {
+            Some(tag) if tag == prefix_pua_utf8::META => {
+                let (meta, value) = map.next_value()?;
+                Ok(WithMeta { meta, value })
+            }
+            _ => Err(de::Error::custom("expected metadata")),
+        }
+    }
+
+    /// human readable なフォーマットからは [metadata, 値] として渡される
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let meta = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let value = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(WithMeta { meta, value })
+    }
+}
+
+/// UUID の中身
+///
+/// human readable なフォーマットでは文字列、それ以外では 16byte で書きます
+pub(crate) struct UuidPayload<'a>(pub &'a Uuid);
+
+impl Serialize for UuidPayload<'_> {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self.0)
+        } else {
+            serializer.serialize_bytes(self.0.as_bytes())
+        }
+    }
+}
+
+/// Wrapped JSON の中身
+///
+/// human readable なフォーマットではそのまま、それ以外では JSON の文字列で書きます
+pub(crate) struct WrappedJsonPayload<'a>(pub &'a serde_json::Value);
+
+impl Serialize for WrappedJsonPayload<'_> {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            self.0.serialize(serializer)
+        } else {
+            serializer.collect_str(self.0)
+        }
+    }
+}
+
+/// DateTime を RFC 3339 の文字列にする
+///
+/// v: &DateTime<Utc>
+///
+/// return: String
+#[inline]
+pub(crate) fn datetime_to_string(v: &DateTime<Utc>) -> String {
+    v.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+#[inline]
+pub(crate) fn uuid_from_value(value: &Value) -> Option<Uuid> {
+    match value {
+        Value::UUID(v) => Some(*v),
+        Value::String(v) => Uuid::parse_str(v).ok(),
+        Value::Bytes(v) => Uuid::from_slice(v).ok(),
+        _ => None,
+    }
+}
+
+#[inline]
+pub(crate) fn datetime_from_value(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::DateTime(v) => Some(*v),
+        Value::String(v) => DateTime::parse_from_rfc3339(v).ok().map(|v| v.with_timezone(&Utc)),
+        _ => None,
+    }
+}
+
+#[inline]
+pub(crate) fn i64_from_value(value: &Value) -> Option<i64> {
+    match value {
+        Value::Timestamp(v) => Some(*v),
+        Value::Int(Int::I8(v)) => Some(*v as i64),
+        Value::Int(Int::I16(v)) => Some(*v as i64),
+        Value::Int(Int::I32(v)) => Some(*v as i64),
+        Value::Int(Int::I64(v)) => Some(*v),
+        Value::UInt(UInt::U8(v)) => Some(*v as i64),
+        Value::UInt(UInt::U16(v)) => Some(*v as i64),
+        Value::UInt(UInt::U32(v)) => Some(*v as i64),
+        Value::Int(Int::I128(v)) => i64::try_from(*v).ok(),
+        Value::UInt(UInt::U64(v)) => i64::try_from(*v).ok(),
+        Value::UInt(UInt::U128(v)) => i64::try_from(*v).ok(),
+        _ => None,
+    }
+}
+
+#[inline]
+pub(crate) fn duration_from_value(value: &Value) -> Option<Duration> {
+    match value {
+        Value::Duration(v) => Some(*v),
+        // i64 の nanoseconds に収まらない Duration は [秒, 秒未満の nanoseconds]
+        Value::Array(v) if v.len() == 2 => {
+            let secs = i64_from_value(&v[0])?;
+            let nanos = i64_from_value(&v[1]).filter(|n| n.abs() < 1_000_000_000)?;
+            Duration::try_seconds(secs)?.checked_add(&Duration::nanoseconds(nanos))
+        }
+        other => i64_from_value(other).map(Duration::nanoseconds),
+    }
+}
+
+/// Duration を human readable な中身にする
+///
+/// 普通は nanoseconds の i64 で、収まらない場合は [秒, 秒未満の nanoseconds] にして値を落としません
+///
+/// v: &Duration
+///
+/// return: Value
+#[inline]
+pub(crate) fn duration_to_value(v: &Duration) -> Value {
+    match v.num_nanoseconds() {
+        Some(nanos) => Value::Int(Int::I64(nanos)),
+        None => Value::Array(vec![
+            Value::Int(Int::I64(v.num_seconds())),
+            Value::Int(Int::I64(v.subsec_nanos() as i64)),
+        ]),
+    }
+}
+
+#[inline]
+pub(crate) fn f16_from_value(value: &Value) -> Option<::half::f16> {
+    match value {
+        Value::Float(Float::F16(v)) => Some(*v),
+        Value::Float(Float::F32(v)) => Some(::half::f16::from_f32(*v)),
+        Value::Float(Float::F64(v)) => Some(::half::f16::from_f64(*v)),
+        _ => None,
+    }
+}
+
+#[inline]
+pub(crate) fn json_from_value(value: &Value) -> Option<serde_json::Value> {
+    match value {
+        Value::WrappedJSON(v) => Some(v.clone()),
+        Value::String(v) => serde_json::from_str(v).ok(),
+        _ => None,
+    }
+}
+
+/// タグと中身から TON 固有の Value を組み立てる
+///
+/// 未知のタグや中身が不正な場合は None を返します
+///
+/// tag: &str
+/// payload: &Value
+///
+/// return: Option<Value>
+pub(crate) fn from_tagged(tag: &str, payload: &Value) -> Option<Value> {
+    match tag {
+        prefix_pua_utf8::UNDEFINED => matches!(payload, Value::None).then_some(Value::Undefined),
+        prefix_pua_utf8::UUID => uuid_from_value(payload).map(Value::UUID),
+        prefix_pua_utf8::DATETIME => datetime_from_value(payload).map(Value::DateTime),
+        prefix_pua_utf8::TIMESTAMP => i64_from_value(payload).map(Value::Timestamp),
+        prefix_pua_utf8::DURATION => duration_from_value(payload).map(Value::Duration),
+        prefix_pua_utf8::FLOAT => f16_from_value(payload).map(|v| Value::Float(Float::F16(v))),
+        prefix_pua_utf8::WRAPPED_JSON => json_from_value(payload).map(Value::WrappedJSON),
+        prefix_pua_utf8::META => match payload {
+            Value::Array(v) if v.len() == 2 => Some(Value::Meta(Box::new(v[0].clone()), Box::new(v[1].clone()))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// TON 固有の Value をタグと human readable な中身に分解する
+///
+/// value: &Value
+///
+/// return: Option<(&'static str, Value)>
+pub(crate) fn to_tagged(value: &Value) -> Option<(&'static str, Value)> {
+    match value {
+        Value::Undefined => Some((prefix_pua_utf8::UNDEFINED, Value::None)),
+        Value::UUID(v) => Some((prefix_pua_utf8::UUID, Value::String(v.to_string()))),
+        Value::DateTime(v) => Some((prefix_pua_utf8::DATETIME, Value::String(datetime_to_string(v)))),
+        Value::Timestamp(v) => Some((prefix_pua_utf8::TIMESTAMP, Value::Int(Int::I64(*v)))),
+        Value::Duration(v) => Some((prefix_pua_utf8::DURATION, duration_to_value(v))),
+        Value::Float(Float::F16(v)) => Some((prefix_pua_utf8::FLOAT, Value::Float(Float::F32(v.to_f32())))),
+        Value::WrappedJSON(v) => Some((prefix_pua_utf8::WRAPPED_JSON, Value::String(v.to_string()))),
+        Value::Meta(meta, v) => Some((prefix_pua_utf8::META, Value::Array(vec![(**meta).clone(), (**v).clone()]))),
+        _ => None,
+    }
+}
+
+/// タグ付き map の key を Value の Visitor にだけ見分けさせる印
+///
+/// key を `deserialize_newtype_struct` でこの名前を付けて読むと、タグの前にこの印を付けた文字列が返ります
+/// ほかの読み方ではタグだけが返るので、ユーザーの `{"$uuid": ...}` の map と区別できます
+pub(crate) const TAGGED_TOKEN: &str = "$ton::private::Tagged::";
+
+/// タグ付き map の key
+pub(crate) struct TagKeyDeserializer(pub(crate) &'static str);
+
+impl<'de> Deserializer<'de> for TagKeyDeserializer {
+    type Error = Error;
+
+    #[inline]
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match name {
+            TAGGED_TOKEN => visitor.visit_string(format!("{}{}", TAGGED_TOKEN, self.0)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// `{tag: payload}` の 1 要素の map として渡す
+///
+/// key は TAGGED_TOKEN の印を付けて読めます
+pub(crate) struct TaggedMapAccess {
+    tag: Option<&'static str>,
+    payload: Option<Value>,
+}
+
+impl TaggedMapAccess {
+    #[inline]
+    pub(crate) fn new(tag: &'static str, payload: Value) -> Self {
+        Self { tag: Some(tag), payload: Some(payload) }
+    }
+}
+
+impl<'de> MapAccess<'de> for TaggedMapAccess {
+    type Error = Error;
+
+    #[inline]
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.tag.take() {
+            Some(tag) => seed.deserialize(TagKeyDeserializer(tag)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.payload.take() {
+            Some(payload) => seed.deserialize(payload),
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.tag.is_some() as usize)
+    }
+}
+
+#[cfg(test)]
+mod types_tests {
+    use chrono::TimeZone;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::ton::{from_slice, from_value, to_value, to_vec};
+    use crate::ton::serde::value::{prefix::prefix, value::KeyValue};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        #[serde(with = "crate::ton::serde::types::uuid")]
+        id: Uuid,
+        #[serde(with = "crate::ton::serde::types::datetime")]
+        created: DateTime<Utc>,
+        #[serde(with = "crate::ton::serde::types::timestamp")]
+        updated: i64,
+        #[serde(with = "crate::ton::serde::types::duration")]
+        ttl: Duration,
+    }
+
+    fn sample_uuid() -> Uuid {
+        Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap()
+    }
+
+    fn sample_datetime() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap()
+    }
+
+    #[test]
+    fn test_uuid_layout() {
+        let uuid = sample_uuid();
+        let bytes = to_vec(&Value::UUID(uuid)).unwrap();
+        assert_eq!(bytes.len(), 17);
+        assert_eq!(&bytes[..16], uuid.as_bytes());
+        assert_eq!(bytes[16] & 0b111111_00, prefix::UUID);
+    }
+
+    #[test]
+    fn test_native_prefixes() {
+        let last = |value: Value| to_vec(&value).unwrap().last().copied().unwrap() & 0b111111_00;
+        assert_eq!(last(Value::DateTime(sample_datetime())), prefix::DATETIME);
+        assert_eq!(last(Value::Timestamp(1)), prefix::TIMESTAMP);
+        assert_eq!(last(Value::Duration(Duration::seconds(1))), prefix::DURATION);
+        assert_eq!(last(Value::Undefined), prefix::UNDEFINED);
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let values = vec![
+            Value::Undefined,
+            Value::UUID(sample_uuid()),
+            Value::DateTime(sample_datetime()),
+            Value::Timestamp(-1_700_000_000),
+            Value::Duration(Duration::milliseconds(1500)),
+        ];
+        for value in values {
+            let bytes = to_vec(&value).unwrap();
+            assert_eq!(from_slice::<Value>(&bytes).unwrap(), value);
+            assert_eq!(to_value(&value).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_tag_like_user_map() {
+        // タグと同じ key を持つだけの Object は TON 固有の型にしない
+        let maps = [
+            (prefix_pua_utf8::UNDEFINED, Value::UInt(UInt::U64(123))),
+            (prefix_pua_utf8::UNDEFINED, Value::None),
+            (prefix_pua_utf8::UUID, Value::String(sample_uuid().to_string())),
+            (prefix_pua_utf8::TIMESTAMP, Value::Int(Int::I64(-1))),
+            (prefix_pua_utf8::META, Value::Array(vec![Value::None, Value::None])),
+        ];
+        for (tag, payload) in maps {
+            let value = Value::Object([(KeyValue::String(tag.to_string()), payload)].into_iter().collect());
+            assert_eq!(from_slice::<Value>(&to_vec(&value).unwrap()).unwrap(), value);
+            assert_eq!(from_value::<Value>(value.clone()).unwrap(), value);
+            assert_eq!(Value::deserialize(&value).unwrap(), value);
+            let json = serde_json::to_value(&value).unwrap();
+            assert_eq!(Value::deserialize(json).unwrap(), value);
+        }
+
+        // 中身の形が違うタグは変換しない
+        assert_eq!(from_tagged(prefix_pua_utf8::UNDEFINED, &Value::UInt(UInt::U8(123))), None);
+        assert_eq!(from_tagged(prefix_pua_utf8::UNDEFINED, &Value::None), Some(Value::Undefined));
+    }
+
+    #[test]
+    fn test_with_modules() {
+        let record = Record {
+            id: sample_uuid(),
+            created: sample_datetime(),
+            updated: 1_700_000_000,
+            ttl: Duration::seconds(30),
+        };
+        let bytes = to_vec(&record).unwrap();
+        assert_eq!(from_slice::<Record>(&bytes).unwrap(), record);
+
+        let value = to_value(&record).unwrap();
+        assert_eq!(value["id"], Value::UUID(record.id));
+        assert_eq!(value["ttl"], Value::Duration(record.ttl));
+        assert_eq!(from_value::<Record>(value).unwrap(), record);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Half {
+        #[serde(with = "crate::ton::serde::types::float16")]
+        v: ::half::f16,
+    }
+
+    #[test]
+    fn test_float16() {
+        let value = Value::Float(Float::F16(::half::f16::from_f32(-2.5)));
+        let bytes = to_vec(&value).unwrap();
+        assert_eq!(bytes.len(), 3);
+        assert_eq!(bytes[2], prefix::FLOAT | 0b01);
+        assert_eq!(from_slice::<Value>(&bytes).unwrap(), value);
+        assert_eq!(to_value(&value).unwrap(), value);
+        assert_eq!(from_slice::<f32>(&bytes).unwrap(), -2.5);
+
+        let half = Half { v: ::half::f16::from_f32(0.25) };
+        assert_eq!(from_slice::<Half>(&to_vec(&half).unwrap()).unwrap(), half);
+    }
+
+    #[test]
+    fn test_128bit_value() {
+        let values = [Value::Int(Int::I128(i128::MIN)), Value::UInt(UInt::U128(u128::MAX))];
+        for value in values {
+            assert_eq!(from_slice::<Value>(&to_vec(&value).unwrap()).unwrap(), value);
+            assert_eq!(from_value::<Value>(value.clone()).unwrap(), value);
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Legacy {
+        id: u32,
+        #[serde(with = "crate::ton::serde::types::wrapped_json")]
+        payload: serde_json::Value,
+    }
+
+    #[test]
+    fn test_wrapped_json() {
+        let json = serde_json::json!({"name": "idis", "tags": [1, 2.5, null], "nested": {"ok": true}});
+        let value = Value::WrappedJSON(json.clone());
+        let bytes = to_vec(&value).unwrap();
+        assert_eq!(bytes.last().unwrap() & 0b111111_00, prefix::WRAPPED_JSON);
+        assert_eq!(from_slice::<Value>(&bytes).unwrap(), value);
+        assert_eq!(to_value(&value).unwrap(), value);
+        assert_eq!(from_value::<Value>(value.clone()).unwrap(), value);
+
+        let legacy = Legacy { id: 1, payload: json };
+        let bytes = to_vec(&legacy).unwrap();
+        assert_eq!(from_slice::<Legacy>(&bytes).unwrap(), legacy);
+        // human readable なフォーマットには JSON がそのまま出る
+        assert_eq!(serde_json::to_value(&legacy).unwrap()["payload"], legacy.payload);
+    }
+
+    fn sample_meta() -> Value {
+        let mut meta = crate::ton::serde::value::map::Map::new();
+        meta.insert(KeyValue::String("owner".to_string()), Value::UUID(sample_uuid()));
+        meta.insert(KeyValue::String("version".to_string()), Value::UInt(UInt::U32(3)));
+        Value::Object(meta)
+    }
+
+    #[test]
+    fn test_meta_value() {
+        let value = Value::String("payload".to_string()).with_meta(sample_meta());
+        assert_eq!(value.meta(), Some(&sample_meta()));
+        assert_eq!(value.payload(), &Value::String("payload".to_string()));
+
+        let bytes = to_vec(&value).unwrap();
+        assert_eq!(bytes.last().unwrap() & 0b111111_00, prefix::META);
+        assert_eq!(from_slice::<Value>(&bytes).unwrap(), value);
+        assert_eq!(to_value(&value).unwrap(), value);
+        assert_eq!(from_value::<Value>(value.clone()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_meta_skip() {
+        let record = Record {
+            id: sample_uuid(),
+            created: sample_datetime(),
+            updated: 0,
+            ttl: Duration::zero(),
+        };
+        let with_meta = WithMeta::new(sample_meta(), record);
+        let bytes = to_vec(&with_meta).unwrap();
+        assert_eq!(from_slice::<WithMeta<Record>>(&bytes).unwrap(), with_meta);
+        // 値だけ欲しい場合は metadata を読み飛ばす
+        assert_eq!(from_slice::<Record>(&bytes).unwrap(), with_meta.value);
+        assert_eq!(from_value::<Record>(to_value(&with_meta).unwrap()).unwrap(), with_meta.value);
+
+        let bytes = to_vec(&WithMeta::new(sample_meta(), Some(7u8))).unwrap();
+        assert_eq!(from_slice::<Option<u8>>(&bytes).unwrap(), Some(7));
+    }
+
+    #[test]
+    fn test_plain_types_from_native() {
+        // 専用の型で書いたものも素の型として読める
+        let bytes = to_vec(&Value::UUID(sample_uuid())).unwrap();
+        assert_eq!(from_slice::<String>(&bytes).unwrap(), sample_uuid().to_string());
+        let bytes = to_vec(&Value::Timestamp(5)).unwrap();
+        assert_eq!(from_slice::<i64>(&bytes).unwrap(), 5);
+    }
+}
@@ -0,0 +1,1218 @@
+use core::{fmt, slice};
+
+use alloc::{collections::btree_map, format, vec, vec::Vec, string::{String, ToString}};
+
+use serde::de::{self, value::BorrowedStrDeserializer, DeserializeOwned, DeserializeSeed, Deserialize, Deserializer, EnumAccess, Expected, IntoDeserializer, MapAccess, SeqAccess, Unexpected, VariantAccess, Visitor};
+
+use crate::ton::serde::{error::{Error, Result}, types::{self, TaggedMapAccess}};
+
+use super::{map::Map, num::{Float, Int, UInt}, prefix::prefix_pua_utf8, value::{KeyValue, Value}};
+
+impl<'de> Deserialize<'de> for Value {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyValue {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = deserializer.deserialize_any(ValueVisitor)?;
+        KeyValue::try_from(value).map_err(|_| de::Error::custom("invalid type: expected a key value"))
+    }
+}
+
+/// 任意のフォーマットから動的な Value を組み立てる Visitor
+pub struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any valid TON value")
+    }
+
+    #[inline]
+    fn visit_bool<E>(self, v: bool) -> core::result::Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    #[inline]
+    fn visit_i8<E>(self, v: i8) -> core::result::Result<Value, E> {
+        Ok(Value::Int(Int::I8(v)))
+    }
+
+    #[inline]
+    fn visit_i16<E>(self, v: i16) -> core::result::Result<Value, E> {
+        Ok(Value::Int(Int::I16(v)))
+    }
+
+    #[inline]
+    fn visit_i32<E>(self, v: i32) -> core::result::Result<Value, E> {
+        Ok(Value::Int(Int::I32(v)))
+    }
+
+    #[inline]
+    fn visit_i64<E>(self, v: i64) -> core::result::Result<Value, E> {
+        Ok(Value::Int(Int::I64(v)))
+    }
+
+    #[inline]
+    fn visit_u8<E>(self, v: u8) -> core::result::Result<Value, E> {
+        Ok(Value::UInt(UInt::U8(v)))
+    }
+
+    #[inline]
+    fn visit_u16<E>(self, v: u16) -> core::result::Result<Value, E> {
+        Ok(Value::UInt(UInt::U16(v)))
+    }
+
+    #[inline]
+    fn visit_u32<E>(self, v: u32) -> core::result::Result<Value, E> {
+        Ok(Value::UInt(UInt::U32(v)))
+    }
+
+    #[inline]
+    fn visit_u64<E>(self, v: u64) -> core::result::Result<Value, E> {
+        Ok(Value::UInt(UInt::U64(v)))
+    }
+
+    #[inline]
+    fn visit_i128<E>(self, v: i128) -> core::result::Result<Value, E> {
+        Ok(Value::Int(Int::I128(v)))
+    }
+
+    #[inline]
+    fn visit_u128<E>(self, v: u128) -> core::result::Result<Value, E> {
+        Ok(Value::UInt(UInt::U128(v)))
+    }
+
+    #[inline]
+    fn visit_f32<E>(self, v: f32) -> core::result::Result<Value, E> {
+        Ok(Value::Float(Float::F32(v)))
+    }
+
+    #[inline]
+    fn visit_f64<E>(self, v: f64) -> core::result::Result<Value, E> {
+        Ok(Value::Float(Float::F64(v)))
+    }
+
+    #[inline]
+    fn visit_char<E>(self, v: char) -> core::result::Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    #[inline]
+    fn visit_str<E>(self, v: &str) -> core::result::Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    #[inline]
+    fn visit_string<E>(self, v: String) -> core::result::Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    #[inline]
+    fn visit_bytes<E>(self, v: &[u8]) -> core::result::Result<Value, E> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    #[inline]
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> core::result::Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    #[inline]
+    fn visit_none<E>(self) -> core::result::Result<Value, E> {
+        Ok(Value::None)
+    }
+
+    #[inline]
+    fn visit_some<D>(self, deserializer: D) -> core::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Deserialize::deserialize(deserializer)
+    }
+
+    #[inline]
+    fn visit_unit<E>(self) -> core::result::Result<Value, E> {
+        Ok(Value::None)
+    }
+
+    #[inline]
+    fn visit_newtype_struct<D>(self, deserializer: D) -> core::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Deserialize::deserialize(deserializer)
+    }
+
+    #[inline]
+    fn visit_seq<A>(self, mut seq: A) -> core::result::Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Value::Array(values))
+    }
+
+    #[inline]
+    fn visit_map<A>(self, mut map: A) -> core::result::Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut values = Map::new();
+        match map.next_key_seed(KeySeed)? {
+            // TON の decoder が渡したタグ付き map は TON 固有の型に戻す
+            // 同じ key を持つだけのユーザーの map は印が無いので Object のまま
+            Some(Key::Tagged(tag)) => {
+                let payload: Value = map.next_value()?;
+                return types::from_tagged(&tag, &payload)
+                    .ok_or_else(|| de::Error::custom(format!("invalid payload for `{}`", tag)));
+            }
+            Some(Key::Plain(key)) => {
+                values.insert(key, map.next_value()?);
+            }
+            None => return Ok(Value::Object(values)),
+        }
+        while let Some((key, value)) = map.next_entry()? {
+            values.insert(key, value);
+        }
+        Ok(Value::Object(values))
+    }
+}
+
+/// map の最初の key
+enum Key {
+    /// TaggedMapAccess が渡したタグ
+    Tagged(String),
+    Plain(KeyValue),
+}
+
+/// TAGGED_TOKEN を付けて key を読む
+struct KeySeed;
+
+impl<'de> DeserializeSeed<'de> for KeySeed {
+    type Value = Key;
+
+    #[inline]
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<Key, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // TaggedMapAccess 以外は名前を見ずに普通の key を返す
+        match deserializer.deserialize_newtype_struct(types::TAGGED_TOKEN, ValueVisitor)? {
+            Value::String(v) if v.starts_with(types::TAGGED_TOKEN) => Ok(Key::Tagged(v[types::TAGGED_TOKEN.len()..].to_string())),
+            value => KeyValue::try_from(value).map(Key::Plain).map_err(|_| de::Error::custom("invalid type: expected a key value")),
+        }
+    }
+}
+
+/// Value から任意の T を組み立てる
+///
+/// value: Value
+///
+/// return: Result<T>
+#[inline]
+pub fn from_value<T>(value: Value) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(&value)
+}
+
+impl Value {
+    /// serde の Unexpected に変換する
+    ///
+    /// return: Unexpected
+    #[cold]
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            Value::Undefined | Value::None => Unexpected::Unit,
+            Value::Bool(v) => Unexpected::Bool(*v),
+            Value::Int(Int::I8(v)) => Unexpected::Signed(*v as i64),
+            Value::Int(Int::I16(v)) => Unexpected::Signed(*v as i64),
+            Value::Int(Int::I32(v)) => Unexpected::Signed(*v as i64),
+            Value::Int(Int::I64(v)) => Unexpected::Signed(*v),
+            Value::Int(Int::I128(_)) => Unexpected::Other("i128"),
+            Value::UInt(UInt::U8(v)) => Unexpected::Unsigned(*v as u64),
+            Value::UInt(UInt::U16(v)) => Unexpected::Unsigned(*v as u64),
+            Value::UInt(UInt::U32(v)) => Unexpected::Unsigned(*v as u64),
+            Value::UInt(UInt::U64(v)) => Unexpected::Unsigned(*v),
+            Value::UInt(UInt::U128(_)) => Unexpected::Other("u128"),
+            Value::Float(Float::F16(v)) => Unexpected::Float(v.to_f64()),
+            Value::Float(Float::F32(v)) => Unexpected::Float(*v as f64),
+            Value::Float(Float::F64(v)) => Unexpected::Float(*v),
+            Value::String(v) => Unexpected::Str(v),
+            Value::Bytes(v) => Unexpected::Bytes(v),
+            Value::UUID(_) => Unexpected::Other("uuid"),
+            Value::DateTime(_) => Unexpected::Other("datetime"),
+            Value::Timestamp(v) => Unexpected::Signed(*v),
+            Value::Duration(_) => Unexpected::Other("duration"),
+            Value::Array(_) => Unexpected::Seq,
+            Value::Object(_) => Unexpected::Map,
+            Value::WrappedJSON(_) => Unexpected::Other("wrapped json"),
+            Value::Meta(..) => Unexpected::Other("meta"),
+        }
+    }
+
+    #[cold]
+    fn invalid_type<E>(&self, exp: &dyn Expected) -> E
+    where
+        E: de::Error,
+    {
+        de::Error::invalid_type(self.unexpected(), exp)
+    }
+}
+
+/// Meta を読み飛ばしてから deserialize_any に渡す
+macro_rules! forward_to_payload {
+    ($payload:ident; $($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            #[inline]
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                self.$payload().deserialize_any(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for &'de Value {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Undefined => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::UNDEFINED, Value::None)),
+            Value::None => visitor.visit_none(),
+            Value::Bool(v) => visitor.visit_bool(*v),
+            Value::Int(Int::I8(v)) => visitor.visit_i8(*v),
+            Value::Int(Int::I16(v)) => visitor.visit_i16(*v),
+            Value::Int(Int::I32(v)) => visitor.visit_i32(*v),
+            Value::Int(Int::I64(v)) => visitor.visit_i64(*v),
+            Value::Int(Int::I128(v)) => visitor.visit_i128(*v),
+            Value::UInt(UInt::U8(v)) => visitor.visit_u8(*v),
+            Value::UInt(UInt::U16(v)) => visitor.visit_u16(*v),
+            Value::UInt(UInt::U32(v)) => visitor.visit_u32(*v),
+            Value::UInt(UInt::U64(v)) => visitor.visit_u64(*v),
+            Value::UInt(UInt::U128(v)) => visitor.visit_u128(*v),
+            Value::Float(Float::F32(v)) => visitor.visit_f32(*v),
+            Value::Float(Float::F64(v)) => visitor.visit_f64(*v),
+            Value::String(v) => visitor.visit_borrowed_str(v),
+            Value::Bytes(v) => visitor.visit_borrowed_bytes(v),
+            // TON 固有の型は {tag: payload} の map として渡す
+            Value::Float(Float::F16(v)) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::FLOAT, Value::Float(Float::F32(v.to_f32())))),
+            Value::UUID(v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::UUID, Value::String(v.to_string()))),
+            Value::DateTime(v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::DATETIME, Value::String(types::datetime_to_string(v)))),
+            Value::Timestamp(v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::TIMESTAMP, Value::Int(Int::I64(*v)))),
+            Value::Duration(v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::DURATION, types::duration_to_value(v))),
+            Value::WrappedJSON(v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::WRAPPED_JSON, Value::String(v.to_string()))),
+            Value::Meta(meta, v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::META, Value::Array(vec![(**meta).clone(), (**v).clone()]))),
+            Value::Array(v) => visitor.visit_seq(SeqRefDeserializer { iter: v.iter() }),
+            Value::Object(v) => visitor.visit_map(MapRefDeserializer { iter: v.iter(), value: None }),
+        }
+    }
+
+    #[inline]
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.payload() {
+            Value::Float(Float::F16(v)) => visitor.visit_f32(v.to_f32()),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_f32(visitor)
+    }
+
+    #[inline]
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let payload = self.payload();
+        match payload {
+            Value::Timestamp(v) => visitor.visit_i64(*v),
+            // i64 の nanoseconds に収まらない Duration は [秒, 秒未満の nanoseconds] のタグ付き map で渡す
+            Value::Duration(v) => match v.num_nanoseconds() {
+                Some(nanos) => visitor.visit_i64(nanos),
+                None => payload.deserialize_any(visitor),
+            },
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.payload() {
+            Value::UUID(v) => visitor.visit_string(v.to_string()),
+            Value::DateTime(v) => visitor.visit_string(types::datetime_to_string(v)),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    #[inline]
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.payload() {
+            Value::UUID(v) => visitor.visit_bytes(v.as_bytes()),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    #[inline]
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.payload() {
+            Value::None | Value::Undefined => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    #[inline]
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.payload() {
+            Value::None | Value::Undefined => visitor.visit_unit(),
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.payload() {
+            // unit variant は variant 名の文字列
+            Value::String(variant) => visitor.visit_enum(EnumRefDeserializer { variant, value: None }),
+            // それ以外は { variant: value } の object
+            Value::Object(map) => {
+                let mut iter = map.iter();
+                match (iter.next(), iter.next()) {
+                    (Some((KeyValue::String(variant), value)), None) => {
+                        visitor.visit_enum(EnumRefDeserializer { variant, value: Some(value) })
+                    }
+                    _ => Err(de::Error::invalid_value(Unexpected::Map, &"map with a single key")),
+                }
+            }
+            other => Err(other.invalid_type(&"string or map")),
+        }
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    forward_to_payload! {
+        payload;
+        deserialize_bool()
+        deserialize_i8()
+        deserialize_i16()
+        deserialize_i32()
+        deserialize_i128()
+        deserialize_u8()
+        deserialize_u16()
+        deserialize_u32()
+        deserialize_u64()
+        deserialize_u128()
+        deserialize_char()
+        deserialize_seq()
+        deserialize_tuple(_len: usize)
+        deserialize_tuple_struct(_name: &'static str, _len: usize)
+        deserialize_map()
+        deserialize_struct(_name: &'static str, _fields: &'static [&'static str])
+        deserialize_identifier()
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    #[inline]
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Undefined => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::UNDEFINED, Value::None)),
+            Value::None => visitor.visit_none(),
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::Int(Int::I8(v)) => visitor.visit_i8(v),
+            Value::Int(Int::I16(v)) => visitor.visit_i16(v),
+            Value::Int(Int::I32(v)) => visitor.visit_i32(v),
+            Value::Int(Int::I64(v)) => visitor.visit_i64(v),
+            Value::Int(Int::I128(v)) => visitor.visit_i128(v),
+            Value::UInt(UInt::U8(v)) => visitor.visit_u8(v),
+            Value::UInt(UInt::U16(v)) => visitor.visit_u16(v),
+            Value::UInt(UInt::U32(v)) => visitor.visit_u32(v),
+            Value::UInt(UInt::U64(v)) => visitor.visit_u64(v),
+            Value::UInt(UInt::U128(v)) => visitor.visit_u128(v),
+            Value::Float(Float::F32(v)) => visitor.visit_f32(v),
+            Value::Float(Float::F64(v)) => visitor.visit_f64(v),
+            Value::String(v) => visitor.visit_string(v),
+            Value::Bytes(v) => visitor.visit_byte_buf(v),
+            // TON 固有の型は {tag: payload} の map として渡す
+            Value::Float(Float::F16(v)) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::FLOAT, Value::Float(Float::F32(v.to_f32())))),
+            Value::UUID(v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::UUID, Value::String(v.to_string()))),
+            Value::DateTime(v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::DATETIME, Value::String(types::datetime_to_string(&v)))),
+            Value::Timestamp(v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::TIMESTAMP, Value::Int(Int::I64(v)))),
+            Value::Duration(v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::DURATION, types::duration_to_value(&v))),
+            Value::WrappedJSON(v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::WRAPPED_JSON, Value::String(v.to_string()))),
+            Value::Meta(meta, v) => visitor.visit_map(TaggedMapAccess::new(prefix_pua_utf8::META, Value::Array(vec![*meta, *v]))),
+            Value::Array(v) => visitor.visit_seq(SeqDeserializer { iter: v.into_iter() }),
+            Value::Object(v) => visitor.visit_map(MapDeserializer { iter: v.into_iter(), value: None }),
+        }
+    }
+
+    #[inline]
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.into_payload() {
+            Value::Float(Float::F16(v)) => visitor.visit_f32(v.to_f32()),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_f32(visitor)
+    }
+
+    #[inline]
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.into_payload() {
+            Value::Timestamp(v) => visitor.visit_i64(v),
+            // i64 の nanoseconds に収まらない Duration は [秒, 秒未満の nanoseconds] のタグ付き map で渡す
+            Value::Duration(v) => match v.num_nanoseconds() {
+                Some(nanos) => visitor.visit_i64(nanos),
+                None => Value::Duration(v).deserialize_any(visitor),
+            },
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.into_payload() {
+            Value::UUID(v) => visitor.visit_string(v.to_string()),
+            Value::DateTime(v) => visitor.visit_string(types::datetime_to_string(&v)),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    #[inline]
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.into_payload() {
+            Value::UUID(v) => visitor.visit_bytes(v.as_bytes()),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    #[inline]
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    #[inline]
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.into_payload() {
+            Value::None | Value::Undefined => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    #[inline]
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.into_payload() {
+            Value::None | Value::Undefined => visitor.visit_unit(),
+            other => Err(other.invalid_type(&visitor)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.into_payload() {
+            // unit variant は variant 名の文字列
+            Value::String(variant) => visitor.visit_enum(EnumDeserializer { variant, value: None }),
+            // それ以外は { variant: value } の object
+            Value::Object(map) => {
+                let mut iter = map.into_iter();
+                match (iter.next(), iter.next()) {
+                    (Some((KeyValue::String(variant), value)), None) => {
+                        visitor.visit_enum(EnumDeserializer { variant, value: Some(value) })
+                    }
+                    _ => Err(de::Error::invalid_value(Unexpected::Map, &"map with a single key")),
+                }
+            }
+            other => Err(other.invalid_type(&"string or map")),
+        }
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    forward_to_payload! {
+        into_payload;
+        deserialize_bool()
+        deserialize_i8()
+        deserialize_i16()
+        deserialize_i32()
+        deserialize_i128()
+        deserialize_u8()
+        deserialize_u16()
+        deserialize_u32()
+        deserialize_u64()
+        deserialize_u128()
+        deserialize_char()
+        deserialize_seq()
+        deserialize_tuple(_len: usize)
+        deserialize_tuple_struct(_name: &'static str, _len: usize)
+        deserialize_map()
+        deserialize_struct(_name: &'static str, _fields: &'static [&'static str])
+        deserialize_identifier()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Self;
+
+    #[inline]
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for &'de Value {
+    type Deserializer = Self;
+
+    #[inline]
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Map<KeyValue, Value> {
+    type Error = Error;
+
+    #[inline]
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(MapDeserializer { iter: self.into_iter(), value: None })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &'de Map<KeyValue, Value> {
+    type Error = Error;
+
+    #[inline]
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(MapRefDeserializer { iter: self.iter(), value: None })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Map<KeyValue, Value> {
+    type Deserializer = Self;
+
+    #[inline]
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for &'de Map<KeyValue, Value> {
+    type Deserializer = Self;
+
+    #[inline]
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+struct SeqDeserializer {
+    iter: vec::IntoIter<Value>,
+}
+
+impl<'de> SeqAccess<'de> for SeqDeserializer {
+    type Error = Error;
+
+    #[inline]
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some(value) => seed.deserialize(value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct SeqRefDeserializer<'de> {
+    iter: slice::Iter<'de, Value>,
+}
+
+impl<'de> SeqAccess<'de> for SeqRefDeserializer<'de> {
+    type Error = Error;
+
+    #[inline]
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some(value) => seed.deserialize(value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapDeserializer {
+    iter: btree_map::IntoIter<KeyValue, Value>,
+    value: Option<Value>,
+}
+
+impl<'de> MapAccess<'de> for MapDeserializer {
+    type Error = Error;
+
+    #[inline]
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Value::from(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn next_value_seed<T>(&mut self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapRefDeserializer<'de> {
+    iter: btree_map::Iter<'de, KeyValue, Value>,
+    value: Option<&'de Value>,
+}
+
+impl<'de> MapAccess<'de> for MapRefDeserializer<'de> {
+    type Error = Error;
+
+    #[inline]
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                match key {
+                    // 文字列の key は借用したまま渡す
+                    KeyValue::String(key) => seed.deserialize(BorrowedStrDeserializer::new(key)).map(Some),
+                    key => seed.deserialize(Value::from(key.clone())).map(Some),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn next_value_seed<T>(&mut self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: Option<Value>,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = VariantDeserializer;
+
+    #[inline]
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, VariantDeserializer)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantDeserializer { value: self.value }))
+    }
+}
+
+struct VariantDeserializer {
+    value: Option<Value>,
+}
+
+impl<'de> VariantAccess<'de> for VariantDeserializer {
+    type Error = Error;
+
+    #[inline]
+    fn unit_variant(self) -> Result<()> {
+        match self.value {
+            Some(value) => Deserialize::deserialize(value),
+            None => Ok(()),
+        }
+    }
+
+    #[inline]
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.value {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"newtype variant")),
+        }
+    }
+
+    #[inline]
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            Some(Value::Array(v)) => visitor.visit_seq(SeqDeserializer { iter: v.into_iter() }),
+            Some(other) => Err(other.invalid_type(&"tuple variant")),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"tuple variant")),
+        }
+    }
+
+    #[inline]
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            Some(Value::Object(v)) => visitor.visit_map(MapDeserializer { iter: v.into_iter(), value: None }),
+            Some(other) => Err(other.invalid_type(&"struct variant")),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"struct variant")),
+        }
+    }
+}
+
+struct EnumRefDeserializer<'de> {
+    variant: &'de str,
+    value: Option<&'de Value>,
+}
+
+impl<'de> EnumAccess<'de> for EnumRefDeserializer<'de> {
+    type Error = Error;
+    type Variant = VariantRefDeserializer<'de>;
+
+    #[inline]
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, VariantRefDeserializer<'de>)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(BorrowedStrDeserializer::new(self.variant))?;
+        Ok((variant, VariantRefDeserializer { value: self.value }))
+    }
+}
+
+struct VariantRefDeserializer<'de> {
+    value: Option<&'de Value>,
+}
+
+impl<'de> VariantAccess<'de> for VariantRefDeserializer<'de> {
+    type Error = Error;
+
+    #[inline]
+    fn unit_variant(self) -> Result<()> {
+        match self.value {
+            Some(value) => Deserialize::deserialize(value),
+            None => Ok(()),
+        }
+    }
+
+    #[inline]
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.value {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"newtype variant")),
+        }
+    }
+
+    #[inline]
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            Some(Value::Array(v)) => visitor.visit_seq(SeqRefDeserializer { iter: v.iter() }),
+            Some(other) => Err(other.invalid_type(&"tuple variant")),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"tuple variant")),
+        }
+    }
+
+    #[inline]
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            Some(Value::Object(v)) => visitor.visit_map(MapRefDeserializer { iter: v.iter(), value: None }),
+            Some(other) => Err(other.invalid_type(&"struct variant")),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"struct variant")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod de_tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::ton::serde::{de::{Deserializer, SliceRead}, ser::ReverseSerializer, value::ser::to_value};
+
+    #[test]
+    fn test_value_from_json() {
+        let json = r#"{"a": [1, -2, 3.5, "x", null, true], "b": {"c": "d"}}"#;
+        let value: Value = serde_json::from_str(json).unwrap();
+
+        let mut inner = Map::new();
+        inner.insert(KeyValue::String("c".to_string()), Value::String("d".to_string()));
+        let mut expected = Map::new();
+        expected.insert(KeyValue::String("a".to_string()), Value::Array(vec![
+            Value::UInt(UInt::U64(1)),
+            Value::Int(Int::I64(-2)),
+            Value::Float(Float::F64(3.5)),
+            Value::String("x".to_string()),
+            Value::None,
+            Value::Bool(true),
+        ]));
+        expected.insert(KeyValue::String("b".to_string()), Value::Object(inner));
+        assert_eq!(value, Value::Object(expected));
+
+        // JSON に戻しても同じになる
+        let back: serde_json::Value = serde_json::from_str(&serde_json::to_string(&value).unwrap()).unwrap();
+        assert_eq!(back, serde_json::from_str::<serde_json::Value>(json).unwrap());
+    }
+
+    #[test]
+    fn test_value_ton_round_trip() {
+        let mut map = Map::new();
+        map.insert(KeyValue::String("id".to_string()), Value::UInt(UInt::U32(42)));
+        map.insert(KeyValue::String("raw".to_string()), Value::Bytes(vec![1, 2, 3]));
+        map.insert(KeyValue::String("list".to_string()), Value::Array(vec![
+            Value::Int(Int::I16(-300)),
+            Value::Float(Float::F32(1.5)),
+            Value::None,
+        ]));
+        let value = Value::Object(map);
+
+        let mut out = Vec::new();
+        value.serialize(&mut ReverseSerializer::new(&mut out)).unwrap();
+        let mut deserializer = Deserializer::from_tail(SliceRead::new(&out)).unwrap();
+        assert_eq!(Value::deserialize(&mut deserializer).unwrap(), value);
+    }
+
+    #[test]
+    fn test_key_value_rejects_container() {
+        assert!(serde_json::from_str::<KeyValue>("[1]").is_err());
+        assert_eq!(serde_json::from_str::<KeyValue>("\"a\"").unwrap(), KeyValue::String("a".to_string()));
+    }
+
+    #[test]
+    fn test_key_value_round_trip() {
+        let keys = vec![
+            KeyValue::Undefined,
+            KeyValue::None,
+            KeyValue::Bool(true),
+            KeyValue::from(-3i16),
+            KeyValue::from(5u32),
+            KeyValue::from(u128::MAX),
+            KeyValue::Float(Float::F32(1.5)),
+            KeyValue::from("a"),
+            KeyValue::Bytes(vec![1, 2]),
+            KeyValue::from(uuid::Uuid::from_u128(7)),
+            KeyValue::from(chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap()),
+            KeyValue::Timestamp(-1),
+            KeyValue::Duration(chrono::Duration::milliseconds(1500)),
+        ];
+        for key in keys {
+            let mut out = Vec::new();
+            key.serialize(&mut ReverseSerializer::new(&mut out)).unwrap();
+            let mut deserializer = Deserializer::from_tail(SliceRead::new(&out)).unwrap();
+            assert_eq!(KeyValue::deserialize(&mut deserializer).unwrap(), key);
+        }
+
+        // String でない key を持つ Object も型を保ったまま戻る
+        let value = Value::Object([
+            (KeyValue::from(1u8), Value::Bool(true)),
+            (KeyValue::from(uuid::Uuid::from_u128(1)), Value::String("owner".to_string())),
+            (KeyValue::from("name"), Value::None),
+        ].into_iter().collect());
+        let mut out = Vec::new();
+        value.serialize(&mut ReverseSerializer::new(&mut out)).unwrap();
+        let mut deserializer = Deserializer::from_tail(SliceRead::new(&out)).unwrap();
+        assert_eq!(Value::deserialize(&mut deserializer).unwrap(), value);
+    }
+
+    #[test]
+    fn test_to_value_from_value() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum Kind {
+            Unit,
+            Newtype(i32),
+            Tuple(u8, String),
+            Struct { flag: bool },
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Record {
+            id: u64,
+            name: String,
+            tags: Vec<String>,
+            parent: Option<u32>,
+            kinds: Vec<Kind>,
+        }
+
+        let record = Record {
+            id: 42,
+            name: "idis".to_string(),
+            tags: vec!["a".to_string(), "b".to_string()],
+            parent: None,
+            kinds: vec![Kind::Unit, Kind::Newtype(-1), Kind::Tuple(7, "x".to_string()), Kind::Struct { flag: true }],
+        };
+
+        let value = to_value(&record).unwrap();
+        match &value {
+            Value::Object(map) => {
+                assert_eq!(map.get(&KeyValue::String("id".to_string())), Some(&Value::UInt(UInt::U64(42))));
+                assert_eq!(map.get(&KeyValue::String("parent".to_string())), Some(&Value::None));
+            }
+            _ => panic!("expected object"),
+        }
+
+        // 参照からも所有権付きからも戻せる
+        assert_eq!(Record::deserialize(&value).unwrap(), record);
+        assert_eq!(from_value::<Record>(value).unwrap(), record);
+    }
+
+    #[test]
+    fn test_map_into_deserializer() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let mut map = Map::new();
+        map.insert(KeyValue::String("x".to_string()), Value::Int(Int::I32(1)));
+        map.insert(KeyValue::String("y".to_string()), Value::Int(Int::I32(-1)));
+
+        assert_eq!(Point::deserialize((&map).into_deserializer()).unwrap(), Point { x: 1, y: -1 });
+        assert_eq!(Point::deserialize(map.into_deserializer()).unwrap(), Point { x: 1, y: -1 });
+    }
+
+    #[test]
+    fn test_from_value_invalid_type() {
+        assert!(from_value::<String>(Value::Bool(true)).is_err());
+        assert!(from_value::<Vec<u8>>(Value::Object(Map::new())).is_err());
+    }
+
+    #[test]
+    fn test_duration_beyond_nanoseconds() {
+        let duration = chrono::Duration::MAX;
+        let value = Value::Duration(duration);
+        // [秒, 秒未満の nanoseconds] のタグ付き map を経由しても値を落とさない
+        assert_eq!(Value::deserialize(&value).unwrap(), value);
+        assert_eq!(Value::deserialize(value.clone()).unwrap(), value);
+        assert_eq!(types::duration::deserialize(&value).unwrap(), duration);
+        assert_eq!(types::duration::deserialize(value.clone()).unwrap(), duration);
+        // i64 の nanoseconds には読めない
+        assert!(i64::deserialize(&value).is_err());
+        assert!(i64::deserialize(value).is_err());
+        assert_eq!(i64::deserialize(Value::Duration(chrono::Duration::seconds(1))).unwrap(), 1_000_000_000);
+    }
+}
@@ -0,0 +1,374 @@
+use core::ops;
+
+use alloc::string::{String, ToString};
+
+use super::{map::Map, value::{KeyValue, Value}};
+
+/// Value に添字でアクセスするためのトレイト
+///
+/// usize は Array、str / String / KeyValue は Object の添字になります
+pub trait Index: private::Sealed {
+    /// 値を参照で取る
+    ///
+    /// v: &Value
+    ///
+    /// return: Option<&Value> // 存在しない場合は None
+    #[doc(hidden)]
+    fn index_into<'v>(&self, v: &'v Value) -> Option<&'v Value>;
+
+    /// 値を可変参照で取る
+    ///
+    /// v: &mut Value
+    ///
+    /// return: Option<&mut Value> // 存在しない場合は None
+    #[doc(hidden)]
+    fn index_into_mut<'v>(&self, v: &'v mut Value) -> Option<&'v mut Value>;
+
+    /// 値を可変参照で取る
+    ///
+    /// Object の key が無い場合は Value::None を挿入します
+    /// Value::None は空の Object として扱います
+    ///
+    /// v: &mut Value
+    ///
+    /// return: &mut Value
+    #[doc(hidden)]
+    fn index_or_insert<'v>(&self, v: &'v mut Value) -> &'v mut Value;
+}
+
+impl Index for usize {
+    #[inline]
+    fn index_into<'v>(&self, v: &'v Value) -> Option<&'v Value> {
+        match v {
+            Value::Array(vec) => vec.get(*self),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn index_into_mut<'v>(&self, v: &'v mut Value) -> Option<&'v mut Value> {
+        match v {
+            Value::Array(vec) => vec.get_mut(*self),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn index_or_insert<'v>(&self, v: &'v mut Value) -> &'v mut Value {
+        match v {
+            Value::Array(vec) => {
+                let len = vec.len();
+                vec.get_mut(*self).unwrap_or_else(|| {
+                    panic!("cannot access index {} of TON array of length {}", self, len)
+                })
+            }
+            _ => panic!("cannot access index {} of TON {}", self, Type(v)),
+        }
+    }
+}
+
+impl Index for KeyValue {
+    #[inline]
+    fn index_into<'v>(&self, v: &'v Value) -> Option<&'v Value> {
+        match v {
+            Value::Object(map) => map.get(self),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn index_into_mut<'v>(&self, v: &'v mut Value) -> Option<&'v mut Value> {
+        match v {
+            Value::Object(map) => map.get_mut(self),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn index_or_insert<'v>(&self, v: &'v mut Value) -> &'v mut Value {
+        if let Value::None = v {
+            *v = Value::Object(Map::new());
+        }
+        match v {
+            Value::Object(map) => map.entry(self.clone()).or_insert(Value::None),
+            _ => panic!("cannot access key {:?} in TON {}", self, Type(v)),
+        }
+    }
+}
+
+impl Index for str {
+    #[inline]
+    fn index_into<'v>(&self, v: &'v Value) -> Option<&'v Value> {
+        KeyValue::String(self.to_string()).index_into(v)
+    }
+
+    #[inline]
+    fn index_into_mut<'v>(&self, v: &'v mut Value) -> Option<&'v mut Value> {
+        KeyValue::String(self.to_string()).index_into_mut(v)
+    }
+
+    #[inline]
+    fn index_or_insert<'v>(&self, v: &'v mut Value) -> &'v mut Value {
+        KeyValue::String(self.to_string()).index_or_insert(v)
+    }
+}
+
+impl Index for String {
+    #[inline]
+    fn index_into<'v>(&self, v: &'v Value) -> Option<&'v Value> {
+        self[..].index_into(v)
+    }
+
+    #[inline]
+    fn index_into_mut<'v>(&self, v: &'v mut Value) -> Option<&'v mut Value> {
+        self[..].index_into_mut(v)
+    }
+
+    #[inline]
+    fn index_or_insert<'v>(&self, v: &'v mut Value) -> &'v mut Value {
+        self[..].index_or_insert(v)
+    }
+}
+
+impl<T> Index for &T
+where
+    T: ?Sized + Index,
+{
+    #[inline]
+    fn index_into<'v>(&self, v: &'v Value) -> Option<&'v Value> {
+        (**self).index_into(v)
+    }
+
+    #[inline]
+    fn index_into_mut<'v>(&self, v: &'v mut Value) -> Option<&'v mut Value> {
+        (**self).index_into_mut(v)
+    }
+
+    #[inline]
+    fn index_or_insert<'v>(&self, v: &'v mut Value) -> &'v mut Value {
+        (**self).index_or_insert(v)
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for usize {}
+    impl Sealed for str {}
+    impl Sealed for alloc::string::String {}
+    impl Sealed for super::KeyValue {}
+    impl<T> Sealed for &T where T: ?Sized + Sealed {}
+}
+
+/// panic メッセージ用の型名
+struct Type<'a>(&'a Value);
+
+impl core::fmt::Display for Type<'_> {
+    fn fmt(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let name = match self.0 {
+            Value::Undefined => "undefined",
+            Value::None => "none",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::UInt(_) => "uint",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::UUID(_) => "uuid",
+            Value::DateTime(_) => "datetime",
+            Value::Timestamp(_) => "timestamp",
+            Value::Duration(_) => "duration",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+            Value::WrappedJSON(_) => "wrapped json",
+            Value::Meta(..) => "meta",
+        };
+        formatter.write_str(name)
+    }
+}
+
+/// 存在しない添字で返す値
+static NONE: Value = Value::None;
+
+impl<I> ops::Index<I> for Value
+where
+    I: Index,
+{
+    type Output = Value;
+
+    /// 添字で値を参照する
+    ///
+    /// 存在しない場合は Value::None を返します
+    #[inline]
+    fn index(&self, index: I) -> &Value {
+        index.index_into(self).unwrap_or(&NONE)
+    }
+}
+
+impl<I> ops::IndexMut<I> for Value
+where
+    I: Index,
+{
+    /// 添字で値を可変参照する
+    ///
+    /// Object の key が無い場合は挿入します
+    /// Array の範囲外や型が合わない場合は panic します
+    #[inline]
+    fn index_mut(&mut self, index: I) -> &mut Value {
+        index.index_or_insert(self)
+    }
+}
+
+impl Value {
+    /// 添字で値を参照で取る
+    ///
+    /// index: I
+    ///
+    /// return: Option<&Value>
+    #[inline]
+    pub fn get<I: Index>(&self, index: I) -> Option<&Value> {
+        index.index_into(self)
+    }
+
+    /// 添字で値を可変参照で取る
+    ///
+    /// index: I
+    ///
+    /// return: Option<&mut Value>
+    #[inline]
+    pub fn get_mut<I: Index>(&mut self, index: I) -> Option<&mut Value> {
+        index.index_into_mut(self)
+    }
+
+    /// JSON Pointer (RFC 6901) 形式のパスで値を参照で取る
+    ///
+    /// "/a/0/b" のように Object の key と Array の index を辿ります
+    /// "~1" は "/"、"~0" は "~" として扱います
+    ///
+    /// pointer: &str
+    ///
+    /// return: Option<&Value>
+    pub fn pointer(&self, pointer: &str) -> Option<&Value> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        if !pointer.starts_with('/') {
+            return None;
+        }
+        pointer
+            .split('/')
+            .skip(1)
+            .map(unescape_token)
+            .try_fold(self, |target, token| match target {
+                Value::Object(map) => map.get(&KeyValue::String(token)),
+                Value::Array(vec) => parse_index(&token).and_then(|i| vec.get(i)),
+                _ => None,
+            })
+    }
+
+    /// JSON Pointer (RFC 6901) 形式のパスで値を可変参照で取る
+    ///
+    /// pointer: &str
+    ///
+    /// return: Option<&mut Value>
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Value> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        if !pointer.starts_with('/') {
+            return None;
+        }
+        pointer
+            .split('/')
+            .skip(1)
+            .map(unescape_token)
+            .try_fold(self, |target, token| match target {
+                Value::Object(map) => map.get_mut(&KeyValue::String(token)),
+                Value::Array(vec) => parse_index(&token).and_then(move |i| vec.get_mut(i)),
+                _ => None,
+            })
+    }
+}
+
+/// pointer の token のエスケープを戻す
+///
+/// token: &str
+///
+/// return: String
+#[inline]
+pub(crate) fn unescape_token(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+/// Array の index として解釈する
+///
+/// 先頭の 0 や符号は許可しません
+///
+/// token: &str
+///
+/// return: Option<usize>
+#[inline]
+pub(crate) fn parse_index(token: &str) -> Option<usize> {
+    if token.starts_with('+') || (token.starts_with('0') && token.len() != 1) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+#[cfg(test)]
+mod index_tests {
+    use super::*;
+    use crate::ton::serde::value::num::{Int, UInt};
+
+    fn sample() -> Value {
+        let mut inner = Map::new();
+        inner.insert(KeyValue::String("b".to_string()), Value::Int(Int::I32(-1)));
+        inner.insert(KeyValue::String("c/d".to_string()), Value::Bool(true));
+        let mut root = Map::new();
+        root.insert(KeyValue::String("a".to_string()), Value::Array(vec![Value::Object(inner), Value::None]));
+        root.insert(KeyValue::UInt(UInt::U8(7)), Value::String("seven".to_string()));
+        Value::Object(root)
+    }
+
+    #[test]
+    fn test_index() {
+        let value = sample();
+        assert_eq!(value["a"][0]["b"], Value::Int(Int::I32(-1)));
+        assert_eq!(value[KeyValue::UInt(UInt::U8(7))], Value::String("seven".to_string()));
+        assert_eq!(value["missing"], Value::None);
+        assert_eq!(value["a"][10], Value::None);
+        assert_eq!(value.get("a").and_then(|a| a.get(1)), Some(&Value::None));
+    }
+
+    #[test]
+    fn test_index_mut() {
+        let mut value = sample();
+        value["a"][0]["b"] = Value::Bool(false);
+        assert_eq!(value["a"][0]["b"], Value::Bool(false));
+
+        // 無い key は挿入される
+        let mut value = Value::None;
+        value["x"]["y"] = Value::UInt(UInt::U8(1));
+        assert_eq!(value.pointer("/x/y"), Some(&Value::UInt(UInt::U8(1))));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_mut_out_of_range() {
+        let mut value = Value::Array(Vec::new());
+        value[0] = Value::None;
+    }
+
+    #[test]
+    fn test_pointer() {
+        let mut value = sample();
+        assert_eq!(value.pointer(""), Some(&value));
+        assert_eq!(value.pointer("/a/0/b"), Some(&Value::Int(Int::I32(-1))));
+        assert_eq!(value.pointer("/a/0/c~1d"), Some(&Value::Bool(true)));
+        assert_eq!(value.pointer("/a/01"), None);
+        assert_eq!(value.pointer("/a/2"), None);
+        assert_eq!(value.pointer("a"), None);
+
+        *value.pointer_mut("/a/1").unwrap() = Value::Bool(true);
+        assert_eq!(value["a"][1], Value::Bool(true));
+    }
+}
@@ -1,9 +1,11 @@
-use std::{borrow::Borrow, collections::{btree_map, BTreeMap}, hash::Hasher};
+use core::{borrow::Borrow, hash::Hasher};
 
-use serde::de;
+use alloc::collections::{btree_map, BTreeMap};
+
+use serde::{de, ser};
 
 use super::value::{KeyValue, Value};
-use std::hash::Hash;
+use core::hash::Hash;
 
 type MapImpl<K, V> = BTreeMap<K, V>;
 type VacantEntryImpl<'a> = btree_map::VacantEntry<'a, KeyValue, Value>;
@@ -61,6 +63,20 @@ impl Map<KeyValue, Value> {
         self.map.get(key)
     }
 
+    /// Keyに対応するValueを可変参照で取る
+    /// 
+    /// key: &KeyValue
+    /// 
+    /// return: Option<&mut Value>
+    #[inline]
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut Value>
+    where
+        KeyValue: Borrow<Q>,
+        Q: ?Sized + Ord + Eq + Hash,
+    {
+        self.map.get_mut(key)
+    }
+
     /// Key が存在するか確認する
     /// 
     /// key: &KeyValue
@@ -137,6 +153,54 @@ impl Map<KeyValue, Value> {
         self.map.append(&mut other.map);
     }
 
+    /// 要素数を取得する
+    /// 
+    /// return: usize
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// 空かどうか確認する
+    /// 
+    /// return: bool
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Keyの順に要素を参照で巡回する
+    /// 
+    /// return: Iter<KeyValue, Value>
+    #[inline]
+    pub fn iter(&self) -> btree_map::Iter<'_, KeyValue, Value> {
+        self.map.iter()
+    }
+
+    /// Keyの順に要素を可変参照で巡回する
+    /// 
+    /// return: IterMut<KeyValue, Value>
+    #[inline]
+    pub fn iter_mut(&mut self) -> btree_map::IterMut<'_, KeyValue, Value> {
+        self.map.iter_mut()
+    }
+
+    /// Keyを順に巡回する
+    /// 
+    /// return: Keys<KeyValue, Value>
+    #[inline]
+    pub fn keys(&self) -> btree_map::Keys<'_, KeyValue, Value> {
+        self.map.keys()
+    }
+
+    /// Valueを順に巡回する
+    /// 
+    /// return: Values<KeyValue, Value>
+    #[inline]
+    pub fn values(&self) -> btree_map::Values<'_, KeyValue, Value> {
+        self.map.values()
+    }
+
 
     /// Keyに一致する要素を取得する
     /// 
@@ -161,54 +225,99 @@ impl Hash for Map<KeyValue, Value> {
     }
 }
 
-// impl<'de> de::Deserialize<'de> for Map<KeyValue, Value> {
-//     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-//     where
-//         D: de::Deserializer<'de>,
-//     {
-//         struct MapVisitor;
-
-//         impl<'de> de::Visitor<'de> for MapVisitor {
-//             type Value = Map<KeyValue, Value>;
-
-//             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-//                 formatter.write_str("a map")
-//             }
-
-//             #[inline]
-//             fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
-//             where
-//                 V: de::MapAccess<'de>,
-//             {
-//                 let mut values = MapImpl::new();
-
-//                 while let Some((key, value)) = map.next_entry()? {
-//                     values.insert(key, value);
-//                 }
-
-//                 Ok(Map { map: values })
-//             }
-//         }
-
-//         deserializer.deserialize_map(MapVisitor)
-//     }
-// }
-
-// impl<'de> de::IntoDeserializer<'de, Error> for Map<KeyValue, Value> {
-//     type Deserializer = Self;
-
-//     fn into_deserializer(self) -> Self {
-//         self
-//     }
-// }
-
-// impl<'de> de::IntoDeserializer<'de, Error> for &'de Map<KeyValue, Value> {
-//     type Deserializer = Self;
-
-//     fn into_deserializer(self) -> Self {
-//         self
-//     }
-// }
+impl Default for Map<KeyValue, Value> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ser::Serialize for Map<KeyValue, Value> {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (k, v) in self {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Map<KeyValue, Value> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct MapVisitor;
+
+        impl<'de> de::Visitor<'de> for MapVisitor {
+            type Value = Map<KeyValue, Value>;
+
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                formatter.write_str("a map")
+            }
+
+            #[inline]
+            fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::MapAccess<'de>,
+            {
+                let mut values = MapImpl::new();
+
+                while let Some((key, value)) = map.next_entry()? {
+                    values.insert(key, value);
+                }
+
+                Ok(Map { map: values })
+            }
+        }
+
+        deserializer.deserialize_map(MapVisitor)
+    }
+}
+
+impl<'a> IntoIterator for &'a Map<KeyValue, Value> {
+    type Item = (&'a KeyValue, &'a Value);
+    type IntoIter = btree_map::Iter<'a, KeyValue, Value>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Map<KeyValue, Value> {
+    type Item = (&'a KeyValue, &'a mut Value);
+    type IntoIter = btree_map::IterMut<'a, KeyValue, Value>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter_mut()
+    }
+}
+
+impl IntoIterator for Map<KeyValue, Value> {
+    type Item = (KeyValue, Value);
+    type IntoIter = btree_map::IntoIter<KeyValue, Value>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+impl FromIterator<(KeyValue, Value)> for Map<KeyValue, Value> {
+    #[inline]
+    fn from_iter<T: IntoIterator<Item = (KeyValue, Value)>>(iter: T) -> Self {
+        Self {
+            map: MapImpl::from_iter(iter),
+        }
+    }
+}
 
 pub struct VacantEntry<'a> {
     vacant: VacantEntryImpl<'a>,
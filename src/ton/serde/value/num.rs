@@ -1,4 +1,4 @@
-use std::hash::Hasher;
+use core::hash::Hasher;
 
 use serde::{Deserialize, Serialize};
 use half::f16;
@@ -9,6 +9,7 @@ pub enum Int {
     I16(i16),
     I32(i32),
     I64(i64),
+    I128(i128),
 }
 
 #[derive(Serialize, Deserialize, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -17,45 +18,123 @@ pub enum UInt {
     U16(u16),
     U32(u32),
     U64(u64),
+    U128(u128),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialOrd)]
+/// 浮動小数点数
+///
+/// Map の key に使えるように、幅をそろえた f64 の IEEE 754 totalOrder で比較します
+/// 同じ数を表す値は幅が違っても等しく、NaN は bit が同じものどうしで等しく、-0.0 と 0.0 は区別します
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Float {
     F16(f16),
     F32(f32),
     F64(f64),
 }
 
+impl Float {
+    /// f64 に広げる
+    ///
+    /// f16 と f32 は f64 で正確に表せるので値は変わりません
+    ///
+    /// return: f64
+    #[inline]
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Float::F16(v) => v.to_f64(),
+            Float::F32(v) => *v as f64,
+            Float::F64(v) => *v,
+        }
+    }
+}
+
 impl Eq for Float {}
 
 impl Ord for Float {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match (self, other) {
-            (Float::F16(a), Float::F16(b)) => a.to_f32().partial_cmp(&b.to_f32()).unwrap(),
-            (Float::F32(a), Float::F32(b)) => a.partial_cmp(b).unwrap(),
-            (Float::F64(a), Float::F64(b)) => a.partial_cmp(b).unwrap(),
-            _ => std::cmp::Ordering::Less,
-        }
+    #[inline]
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.to_f64().total_cmp(&other.to_f64())
+    }
+}
+
+impl PartialOrd for Float {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-impl std::hash::Hash for Float {
+impl core::hash::Hash for Float {
+    #[inline]
     fn hash<H: Hasher>(&self, state: &mut H) {
-        match self {
-            Float::F16(v) => (v.to_f32() as i32).hash(state),
-            Float::F32(v) => (*v as i32).hash(state),
-            Float::F64(v) => (*v as i64).hash(state),
-        }
-    }    
+        // eq と同じく幅をそろえた bit を使う
+        self.to_f64().to_bits().hash(state)
+    }
 }
 
 impl PartialEq for Float {
+    #[inline]
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Float::F16(a), Float::F16(b)) => a.to_f32() == b.to_f32(),
-            (Float::F32(a), Float::F32(b)) => a == b,
-            (Float::F64(a), Float::F64(b)) => a == b,
-            _ => false,
-        }
+        self.to_f64().to_bits() == other.to_f64().to_bits()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod num_tests {
+    use std::{cmp::Ordering, collections::{hash_map::DefaultHasher, HashSet}, hash::Hash};
+
+    use super::*;
+    use crate::ton::serde::value::{map::Map, value::{KeyValue, Value}};
+
+    fn hash(v: &Float) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        v.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_float_eq_across_width() {
+        assert_eq!(Float::F16(f16::from_f32(1.0)), Float::F32(1.0));
+        assert_eq!(Float::F32(1.5), Float::F64(1.5));
+        assert_eq!(hash(&Float::F16(f16::from_f32(1.0))), hash(&Float::F64(1.0)));
+        // f32 の 0.1 と f64 の 0.1 は別の数
+        assert_ne!(Float::F32(0.1), Float::F64(0.1));
+        assert_ne!(Float::F64(0.0), Float::F64(-0.0));
+        assert_eq!(Float::F64(f64::NAN), Float::F64(f64::NAN));
+    }
+
+    #[test]
+    fn test_float_hash_distinct() {
+        let set: HashSet<Float> = [1.2, 1.7, 1.0].into_iter().map(Float::F64).collect();
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn test_float_total_order() {
+        let mut values = [
+            Float::F64(f64::NAN),
+            Float::F32(2.0),
+            Float::F64(f64::INFINITY),
+            Float::F16(f16::from_f32(-1.0)),
+            Float::F64(0.0),
+            Float::F32(-0.0),
+            Float::F64(f64::NEG_INFINITY),
+        ];
+        values.sort();
+        let sorted: Vec<f64> = values.iter().map(Float::to_f64).collect();
+        assert_eq!(&sorted[..6], &[f64::NEG_INFINITY, -1.0, -0.0, 0.0, 2.0, f64::INFINITY]);
+        assert!(sorted[6].is_nan());
+        assert_eq!(Float::F32(1.0).cmp(&Float::F64(2.0)), Ordering::Less);
+        assert_eq!(Float::F64(2.0).cmp(&Float::F32(1.0)), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_float_map_key() {
+        let mut map = Map::new();
+        map.insert(KeyValue::Float(Float::F32(1.5)), Value::Bool(true));
+        map.insert(KeyValue::Float(Float::F64(f64::NAN)), Value::Bool(true));
+        assert_eq!(map.get(&KeyValue::Float(Float::F64(1.5))), Some(&Value::Bool(true)));
+        assert_eq!(map.get(&KeyValue::Float(Float::F64(f64::NAN))), Some(&Value::Bool(true)));
+        assert_eq!(map.get(&KeyValue::Float(Float::F64(2.5))), None);
+    }
+}
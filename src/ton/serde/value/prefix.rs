@@ -17,6 +17,10 @@ pub mod prefix {
 
     pub const META:             u8 = 0b001110_00; // 0x38 ~ 0x3B
     pub const PADDING:          u8 = 0b001111_00; // 0x3C ~ 0x3F
+
+    // size_prefix に収まらない 16byte の整数
+    pub const INT128:           u8 = 0b010000_00; // 0x40
+    pub const UINT128:          u8 = 0b010001_00; // 0x44
 }
 
 pub mod prefix_pua_utf8 {
@@ -39,6 +43,9 @@ pub mod prefix_pua_utf8 {
 
     pub const META:             &str = "$meta";      // Meta
     pub const PADDING:          &str = "$padding";   // Padding
+
+    pub const INT128:           &str = "$int128";    // 128bit Integer
+    pub const UINT128:          &str = "$uint128";   // 128bit Unsigned Integer
 }
 
 pub mod size_prefix {
@@ -0,0 +1,550 @@
+use alloc::{vec::Vec, string::ToString, format};
+
+use serde::{ser::{self, SerializeSeq}, Serialize};
+
+use crate::ton::serde::{error::{Error, Result}, types::{self, UuidPayload, WrappedJsonPayload}};
+
+use super::{map::Map, num::{Float, Int, UInt}, prefix::prefix_pua_utf8, value::{KeyValue, Value}};
+
+impl Serialize for Value {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            // TON 固有の型はタグ付きの newtype struct として渡す
+            Value::Undefined => serializer.serialize_unit_struct(prefix_pua_utf8::UNDEFINED),
+            Value::None => serializer.serialize_none(),
+            Value::Bool(v) => serializer.serialize_bool(*v),
+            Value::Int(v) => v.serialize_inner(serializer),
+            Value::UInt(v) => v.serialize_inner(serializer),
+            Value::Float(v) => v.serialize_inner(serializer),
+            Value::String(v) => serializer.serialize_str(v),
+            Value::Bytes(v) => serializer.serialize_bytes(v),
+            Value::UUID(v) => serializer.serialize_newtype_struct(prefix_pua_utf8::UUID, &UuidPayload(v)),
+            Value::DateTime(v) => serializer.serialize_newtype_struct(prefix_pua_utf8::DATETIME, &types::datetime_to_string(v)),
+            Value::Timestamp(v) => serializer.serialize_newtype_struct(prefix_pua_utf8::TIMESTAMP, v),
+            Value::Duration(v) => types::duration::serialize(v, serializer),
+            Value::Array(v) => {
+                let mut seq = serializer.serialize_seq(Some(v.len()))?;
+                for element in v {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            Value::Object(v) => v.serialize(serializer),
+            Value::WrappedJSON(v) => serializer.serialize_newtype_struct(prefix_pua_utf8::WRAPPED_JSON, &WrappedJsonPayload(v)),
+            Value::Meta(meta, v) => serializer.serialize_newtype_struct(prefix_pua_utf8::META, &(meta, v)),
+        }
+    }
+}
+
+impl Serialize for KeyValue {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        // Value と同じ形で書き出して、読み戻したときに同じ型の key になるようにする
+        match self {
+            KeyValue::Undefined => serializer.serialize_unit_struct(prefix_pua_utf8::UNDEFINED),
+            KeyValue::None => serializer.serialize_none(),
+            KeyValue::Bool(v) => serializer.serialize_bool(*v),
+            KeyValue::Int(v) => v.serialize_inner(serializer),
+            KeyValue::UInt(v) => v.serialize_inner(serializer),
+            KeyValue::Float(v) => v.serialize_inner(serializer),
+            KeyValue::String(v) => serializer.serialize_str(v),
+            KeyValue::Bytes(v) => serializer.serialize_bytes(v),
+            KeyValue::UUID(v) => serializer.serialize_newtype_struct(prefix_pua_utf8::UUID, &UuidPayload(v)),
+            KeyValue::DateTime(v) => serializer.serialize_newtype_struct(prefix_pua_utf8::DATETIME, &types::datetime_to_string(v)),
+            KeyValue::Timestamp(v) => serializer.serialize_newtype_struct(prefix_pua_utf8::TIMESTAMP, v),
+            KeyValue::Duration(v) => types::duration::serialize(v, serializer),
+        }
+    }
+}
+
+impl Int {
+    /// enum のタグを付けずに中身の整数だけをシリアライズする
+    ///
+    /// serializer: S
+    ///
+    /// return: Result<S::Ok, S::Error>
+    #[inline]
+    pub fn serialize_inner<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Int::I8(v) => serializer.serialize_i8(*v),
+            Int::I16(v) => serializer.serialize_i16(*v),
+            Int::I32(v) => serializer.serialize_i32(*v),
+            Int::I64(v) => serializer.serialize_i64(*v),
+            Int::I128(v) => serializer.serialize_i128(*v),
+        }
+    }
+}
+
+impl UInt {
+    /// enum のタグを付けずに中身の整数だけをシリアライズする
+    ///
+    /// serializer: S
+    ///
+    /// return: Result<S::Ok, S::Error>
+    #[inline]
+    pub fn serialize_inner<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            UInt::U8(v) => serializer.serialize_u8(*v),
+            UInt::U16(v) => serializer.serialize_u16(*v),
+            UInt::U32(v) => serializer.serialize_u32(*v),
+            UInt::U64(v) => serializer.serialize_u64(*v),
+            UInt::U128(v) => serializer.serialize_u128(*v),
+        }
+    }
+}
+
+impl Float {
+    /// enum のタグを付けずに中身の浮動小数点数だけをシリアライズする
+    ///
+    /// serializer: S
+    ///
+    /// return: Result<S::Ok, S::Error>
+    #[inline]
+    pub fn serialize_inner<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            // serde に f16 は無いのでタグ付きの f32 として渡す
+            Float::F16(v) => serializer.serialize_newtype_struct(prefix_pua_utf8::FLOAT, &v.to_f32()),
+            Float::F32(v) => serializer.serialize_f32(*v),
+            Float::F64(v) => serializer.serialize_f64(*v),
+        }
+    }
+}
+
+/// 任意の T を Value に変換する
+///
+/// value: &T
+///
+/// return: Result<Value>
+#[inline]
+pub fn to_value<T>(value: &T) -> Result<Value>
+where
+    T: ?Sized + Serialize,
+{
+    value.serialize(Serializer)
+}
+
+/// Value を組み立てるシリアライザー
+pub struct Serializer;
+
+impl serde::Serializer for Serializer {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeTupleVariant;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    #[inline]
+    fn serialize_bool(self, v: bool) -> Result<Value> {
+        Ok(Value::Bool(v))
+    }
+
+    #[inline]
+    fn serialize_i8(self, v: i8) -> Result<Value> {
+        Ok(Value::Int(Int::I8(v)))
+    }
+
+    #[inline]
+    fn serialize_i16(self, v: i16) -> Result<Value> {
+        Ok(Value::Int(Int::I16(v)))
+    }
+
+    #[inline]
+    fn serialize_i32(self, v: i32) -> Result<Value> {
+        Ok(Value::Int(Int::I32(v)))
+    }
+
+    #[inline]
+    fn serialize_i64(self, v: i64) -> Result<Value> {
+        Ok(Value::Int(Int::I64(v)))
+    }
+
+    #[inline]
+    fn serialize_i128(self, v: i128) -> Result<Value> {
+        Ok(Value::Int(Int::I128(v)))
+    }
+
+    #[inline]
+    fn serialize_u8(self, v: u8) -> Result<Value> {
+        Ok(Value::UInt(UInt::U8(v)))
+    }
+
+    #[inline]
+    fn serialize_u16(self, v: u16) -> Result<Value> {
+        Ok(Value::UInt(UInt::U16(v)))
+    }
+
+    #[inline]
+    fn serialize_u32(self, v: u32) -> Result<Value> {
+        Ok(Value::UInt(UInt::U32(v)))
+    }
+
+    #[inline]
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        Ok(Value::UInt(UInt::U64(v)))
+    }
+
+    #[inline]
+    fn serialize_u128(self, v: u128) -> Result<Value> {
+        Ok(Value::UInt(UInt::U128(v)))
+    }
+
+    #[inline]
+    fn serialize_f32(self, v: f32) -> Result<Value> {
+        Ok(Value::Float(Float::F32(v)))
+    }
+
+    #[inline]
+    fn serialize_f64(self, v: f64) -> Result<Value> {
+        Ok(Value::Float(Float::F64(v)))
+    }
+
+    #[inline]
+    fn serialize_char(self, v: char) -> Result<Value> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    #[inline]
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    #[inline]
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    #[inline]
+    fn serialize_none(self) -> Result<Value> {
+        Ok(Value::None)
+    }
+
+    #[inline]
+    fn serialize_some<T>(self, value: &T) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_unit(self) -> Result<Value> {
+        Ok(Value::None)
+    }
+
+    #[inline]
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Value> {
+        match name {
+            prefix_pua_utf8::UNDEFINED => Ok(Value::Undefined),
+            _ => self.serialize_unit(),
+        }
+    }
+
+    #[inline]
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value> {
+        self.serialize_str(variant)
+    }
+
+    #[inline]
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        match name {
+            prefix_pua_utf8::UUID | prefix_pua_utf8::DATETIME | prefix_pua_utf8::TIMESTAMP | prefix_pua_utf8::DURATION | prefix_pua_utf8::FLOAT | prefix_pua_utf8::META => {
+                let payload = value.serialize(self)?;
+                types::from_tagged(name, &payload)
+                    .ok_or_else(|| ser::Error::custom(format!("invalid payload for {}", name)))
+            }
+            // JSON はそのまま保持する
+            prefix_pua_utf8::WRAPPED_JSON => serde_json::to_value(value)
+                .map(Value::WrappedJSON)
+                .map_err(ser::Error::custom),
+            _ => value.serialize(self),
+        }
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut values = Map::new();
+        values.insert(KeyValue::String(variant.to_string()), to_value(value)?);
+        Ok(Value::Object(values))
+    }
+
+    #[inline]
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(SerializeVec {
+            vec: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    #[inline]
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    #[inline]
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_seq(Some(len))
+    }
+
+    #[inline]
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Ok(SerializeTupleVariant {
+            name: variant,
+            vec: Vec::with_capacity(len),
+        })
+    }
+
+    #[inline]
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(SerializeMap {
+            map: Map::new(),
+            next_key: None,
+        })
+    }
+
+    #[inline]
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        self.serialize_map(Some(len))
+    }
+
+    #[inline]
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Ok(SerializeStructVariant {
+            name: variant,
+            map: Map::new(),
+        })
+    }
+}
+
+pub struct SerializeVec {
+    vec: Vec<Value>,
+}
+
+pub struct SerializeTupleVariant {
+    name: &'static str,
+    vec: Vec<Value>,
+}
+
+pub struct SerializeMap {
+    map: Map<KeyValue, Value>,
+    next_key: Option<KeyValue>,
+}
+
+pub struct SerializeStructVariant {
+    name: &'static str,
+    map: Map<KeyValue, Value>,
+}
+
+impl ser::SerializeSeq for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    #[inline]
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.vec.push(to_value(value)?);
+        Ok(())
+    }
+
+    #[inline]
+    fn end(self) -> Result<Value> {
+        Ok(Value::Array(self.vec))
+    }
+}
+
+impl ser::SerializeTuple for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    #[inline]
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant {
+    type Ok = Value;
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.vec.push(to_value(value)?);
+        Ok(())
+    }
+
+    #[inline]
+    fn end(self) -> Result<Value> {
+        let mut object = Map::new();
+        object.insert(KeyValue::String(self.name.to_string()), Value::Array(self.vec));
+        Ok(Value::Object(object))
+    }
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+
+    #[inline]
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.next_key = Some(to_key(key)?);
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        // serialize_key が先に呼ばれていないのは serde の規約違反
+        let key = self.next_key.take()
+            .ok_or_else(|| <Error as ser::Error>::custom("serialize_value called before serialize_key"))?;
+        self.map.insert(key, to_value(value)?);
+        Ok(())
+    }
+
+    #[inline]
+    fn end(self) -> Result<Value> {
+        Ok(Value::Object(self.map))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.map.insert(KeyValue::String(key.to_string()), to_value(value)?);
+        Ok(())
+    }
+
+    #[inline]
+    fn end(self) -> Result<Value> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = Value;
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.map.insert(KeyValue::String(key.to_string()), to_value(value)?);
+        Ok(())
+    }
+
+    #[inline]
+    fn end(self) -> Result<Value> {
+        let mut object = Map::new();
+        object.insert(KeyValue::String(self.name.to_string()), Value::Object(self.map));
+        Ok(Value::Object(object))
+    }
+}
+
+/// Object の key に変換する
+///
+/// Array Object などの key にできない値はエラーになります
+///
+/// key: &T
+///
+/// return: Result<KeyValue>
+#[inline]
+fn to_key<T>(key: &T) -> Result<KeyValue>
+where
+    T: ?Sized + Serialize,
+{
+    KeyValue::try_from(to_value(key)?)
+        .map_err(|_| <Error as ser::Error>::custom("key must be a scalar value"))
+}
@@ -1,7 +1,8 @@
-use std::hash::Hash;
+use core::hash::Hash;
+
+use alloc::{vec::Vec, string::{String, ToString}, boxed::Box};
 
 use chrono::{DateTime, Duration, Utc};
-use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::{map::Map, num::{Float, Int, UInt}};
@@ -111,8 +112,13 @@ pub enum Value {
     WrappedJSON(serde_json::Value),
 
     /// Meta 型
-    /// 値がメタデータであることを示す型
-    Meta(Box<Value>),
+    /// 値にメタデータを付けた型
+    /// type size: n byte
+    ///
+    /// (metadata, 値) の組を持ちます
+    /// body には metadata, 値 の順で格納され、値は header の直前にあるので
+    /// metadata を読まずに値だけを取り出せます
+    Meta(Box<Value>, Box<Value>),
 }
 
 impl Default for Value {
@@ -121,6 +127,55 @@ impl Default for Value {
     }
 }
 
+impl Value {
+    /// 値にメタデータを付ける
+    ///
+    /// meta: Value // owner, version, ACL などのメタデータ
+    ///
+    /// return: Value // Value::Meta
+    #[inline]
+    pub fn with_meta(self, meta: Value) -> Value {
+        Value::Meta(Box::new(meta), Box::new(self))
+    }
+
+    /// メタデータを取る
+    ///
+    /// return: Option<&Value> // Meta でない場合は None
+    #[inline]
+    pub fn meta(&self) -> Option<&Value> {
+        match self {
+            Value::Meta(meta, _) => Some(meta),
+            _ => None,
+        }
+    }
+
+    /// メタデータを読み飛ばして値を取る
+    ///
+    /// Meta が入れ子になっている場合は全て読み飛ばします
+    ///
+    /// return: &Value
+    #[inline]
+    pub fn payload(&self) -> &Value {
+        let mut value = self;
+        while let Value::Meta(_, v) = value {
+            value = v;
+        }
+        value
+    }
+
+    /// メタデータを捨てて値を取る
+    ///
+    /// return: Value
+    #[inline]
+    pub fn into_payload(self) -> Value {
+        let mut value = self;
+        while let Value::Meta(_, v) = value {
+            value = *v;
+        }
+        value
+    }
+}
+
 /// KeyValue 型
 /// Object型におけるKeyを表現する型
 /// Hash Ord を実装している
@@ -141,24 +196,90 @@ pub enum KeyValue {
     Duration(Duration),
 }
 
-impl Serialize for KeyValue {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        match self {
-            Self::Undefined => self.serialize(serializer),
-            Self::None => self.serialize(serializer),
-            Self::Bool(v) => v.serialize(serializer),
-            Self::Int(v) => v.serialize(serializer),
-            Self::UInt(v) => v.serialize(serializer),
-            Self::Float(v) => v.serialize(serializer),
-            Self::String(v) => v.serialize(serializer),
-            Self::Bytes(v) => v.serialize(serializer),
-            Self::UUID(v) => v.to_string().serialize(serializer),
-            Self::DateTime(v) => v.serialize(serializer),
-            Self::Timestamp(v) => v.serialize(serializer),
-            Self::Duration(v) => v.serialize(serializer),
+impl From<KeyValue> for Value {
+    fn from(key: KeyValue) -> Self {
+        match key {
+            KeyValue::Undefined => Value::Undefined,
+            KeyValue::None => Value::None,
+            KeyValue::Bool(v) => Value::Bool(v),
+            KeyValue::Int(v) => Value::Int(v),
+            KeyValue::UInt(v) => Value::UInt(v),
+            KeyValue::Float(v) => Value::Float(v),
+            KeyValue::String(v) => Value::String(v),
+            KeyValue::Bytes(v) => Value::Bytes(v),
+            KeyValue::UUID(v) => Value::UUID(v),
+            KeyValue::DateTime(v) => Value::DateTime(v),
+            KeyValue::Timestamp(v) => Value::Timestamp(v),
+            KeyValue::Duration(v) => Value::Duration(v),
+        }
+    }
+}
+
+/// Object WrappedJson Array Meta は key にできないのでそのまま返す
+impl TryFrom<Value> for KeyValue {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Undefined => Ok(KeyValue::Undefined),
+            Value::None => Ok(KeyValue::None),
+            Value::Bool(v) => Ok(KeyValue::Bool(v)),
+            Value::Int(v) => Ok(KeyValue::Int(v)),
+            Value::UInt(v) => Ok(KeyValue::UInt(v)),
+            Value::Float(v) => Ok(KeyValue::Float(v)),
+            Value::String(v) => Ok(KeyValue::String(v)),
+            Value::Bytes(v) => Ok(KeyValue::Bytes(v)),
+            Value::UUID(v) => Ok(KeyValue::UUID(v)),
+            Value::DateTime(v) => Ok(KeyValue::DateTime(v)),
+            Value::Timestamp(v) => Ok(KeyValue::Timestamp(v)),
+            Value::Duration(v) => Ok(KeyValue::Duration(v)),
+            other => Err(other),
         }
     }
-}
\ No newline at end of file
+}
+
+macro_rules! impl_key_from {
+    ($($ty:ty => $variant:ident($inner:ident),)*) => {
+        $(
+            impl From<$ty> for KeyValue {
+                #[inline]
+                fn from(v: $ty) -> Self {
+                    KeyValue::$inner($inner::$variant(v))
+                }
+            }
+        )*
+    };
+}
+
+impl_key_from! {
+    i8 => I8(Int), i16 => I16(Int), i32 => I32(Int), i64 => I64(Int), i128 => I128(Int),
+    u8 => U8(UInt), u16 => U16(UInt), u32 => U32(UInt), u64 => U64(UInt), u128 => U128(UInt),
+}
+
+impl From<&str> for KeyValue {
+    #[inline]
+    fn from(v: &str) -> Self {
+        KeyValue::String(v.to_string())
+    }
+}
+
+impl From<String> for KeyValue {
+    #[inline]
+    fn from(v: String) -> Self {
+        KeyValue::String(v)
+    }
+}
+
+impl From<Uuid> for KeyValue {
+    #[inline]
+    fn from(v: Uuid) -> Self {
+        KeyValue::UUID(v)
+    }
+}
+
+impl From<DateTime<Utc>> for KeyValue {
+    #[inline]
+    fn from(v: DateTime<Utc>) -> Self {
+        KeyValue::DateTime(v)
+    }
+}
@@ -0,0 +1,386 @@
+//! TON の遅延ランダムアクセス
+//!
+//! container の header にはサイズが書かれているので、
+//! 兄弟の要素は body を読まずにサイズ分だけ飛ばせます
+//! TonView は必要な値の位置だけを辿り、中身は取り出すときに初めて読みます
+
+use serde::Deserialize;
+
+use super::{de::{decode_prefix, prefix_error, Deserializer, Header, SliceRead, TonTypes}, error::{Error, ErrorCode, Result}, value::{index::{parse_index, unescape_token}, prefix::size_prefix::SIZE_PREFIX_8BYTE, value::Value}};
+
+/// バイト列上の TON の値への参照
+///
+/// 値の位置と header だけを持ち、body は読みません
+#[derive(Debug, Clone, Copy)]
+pub struct TonView<'a> {
+    input: &'a [u8],
+    pos: usize,
+    header: Header,
+}
+
+impl<'a> TonView<'a> {
+    /// input の末尾にある値の TonView を作る
+    ///
+    /// 末尾の PADDING は読み飛ばします
+    ///
+    /// input: &'a [u8]
+    ///
+    /// return: Result<TonView>
+    pub fn new(input: &'a [u8]) -> Result<Self> {
+        let mut pos = input.len().checked_sub(1).ok_or_else(|| Error::syntax(ErrorCode::NotFoundTarget, 0))?;
+        loop {
+            let view = Self::at(input, pos)?;
+            if view.ton_type() != TonTypes::Padding {
+                return Ok(view);
+            }
+            pos = view.header.body_start(pos).checked_sub(1)
+                .ok_or_else(|| Error::syntax(ErrorCode::SizeOverflow, pos))?;
+        }
+    }
+
+    /// pos にある値の TonView を作る
+    ///
+    /// input: &'a [u8]
+    /// pos: usize // prefix の位置
+    ///
+    /// return: Result<TonView>
+    pub fn at(input: &'a [u8], pos: usize) -> Result<Self> {
+        let byte = *input.get(pos).ok_or_else(|| Error::syntax(ErrorCode::NotFoundTarget, pos))?;
+        let overflow = || Error::syntax(ErrorCode::SizeOverflow, pos);
+        let header = match decode_prefix(byte).ok_or_else(|| prefix_error(byte, pos))? {
+            (ton_type, Some(body_size)) => Header { ton_type, header_size: 1, body_size },
+            (ton_type, None) => {
+                // size フィールドは反転して書かれているので big endian として読む
+                let size_width = 1usize << (byte & SIZE_PREFIX_8BYTE);
+                let start = pos.checked_sub(size_width).ok_or_else(|| Error::syntax(ErrorCode::TruncatedHeader, pos))?;
+                let body_size = input[start..pos].iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+                let body_size = usize::try_from(body_size).map_err(|_| overflow())?;
+                Header { ton_type, header_size: 1 + size_width, body_size }
+            }
+        };
+        if header.header_size.checked_add(header.body_size).is_none_or(|size| size > pos + 1) {
+            return Err(overflow());
+        }
+        Ok(Self { input, pos, header })
+    }
+
+    /// 値の prefix の位置
+    ///
+    /// Error::offset と比べて、壊れた値を特定するのに使えます
+    ///
+    /// return: usize
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// 値の型
+    ///
+    /// return: TonTypes
+    #[inline]
+    pub fn ton_type(&self) -> TonTypes {
+        self.header.ton_type
+    }
+
+    /// header を含む値全体のバイト列
+    ///
+    /// そのまま書き出せば単独の TON として読めます
+    ///
+    /// return: &'a [u8]
+    #[inline]
+    pub fn raw(&self) -> &'a [u8] {
+        &self.input[self.header.body_start(self.pos)..=self.pos]
+    }
+
+    /// body のバイト列
+    ///
+    /// return: &'a [u8]
+    #[inline]
+    fn body(&self) -> &'a [u8] {
+        &self.input[self.header.body_start(self.pos)..self.header.body_end(self.pos)]
+    }
+
+    /// 子要素を後ろから順に返す
+    ///
+    /// return: RevElements
+    #[inline]
+    fn elements_rev(&self) -> RevElements<'a> {
+        RevElements {
+            input: self.input,
+            start: self.header.body_start(self.pos),
+            cur: self.header.body_end(self.pos),
+        }
+    }
+
+    /// Meta を読み飛ばして値を取る
+    ///
+    /// return: Result<TonView>
+    pub fn payload(&self) -> Result<TonView<'a>> {
+        let mut view = *self;
+        while view.ton_type() == TonTypes::Meta {
+            // 値は body の末尾にある、空の body には値が無い
+            let pos = view.header.body_end(view.pos).checked_sub(1)
+                .filter(|end| *end >= view.header.body_start(view.pos))
+                .ok_or_else(|| Error::syntax(ErrorCode::SizeOverflow, view.pos))?;
+            view = Self::at(view.input, pos)?;
+        }
+        Ok(view)
+    }
+
+    /// Meta の metadata を取る
+    ///
+    /// return: Result<Option<TonView>> // Meta でない場合は None
+    pub fn meta(&self) -> Result<Option<TonView<'a>>> {
+        if self.ton_type() != TonTypes::Meta {
+            return Ok(None);
+        }
+        let mut elements = self.elements_rev();
+        elements.next().transpose()?;
+        elements.next().transpose()
+    }
+
+    /// Array Object の要素数
+    ///
+    /// 要素の header だけを辿って数えます
+    ///
+    /// return: Result<Option<usize>> // container でない場合は None
+    pub fn len(&self) -> Result<Option<usize>> {
+        let view = self.payload()?;
+        let count = match view.ton_type() {
+            TonTypes::Array | TonTypes::Object => view.elements_rev().try_fold(0usize, |n, e| e.map(|_| n + 1))?,
+            _ => return Ok(None),
+        };
+        match view.ton_type() {
+            TonTypes::Object => Ok(Some(count / 2)),
+            _ => Ok(Some(count)),
+        }
+    }
+
+    /// Object の key で値を取る
+    ///
+    /// 兄弟の値は body を読まずに飛ばします
+    ///
+    /// key: &str
+    ///
+    /// return: Result<Option<TonView>> // 見つからないか Object でない場合は None
+    pub fn get(&self, key: &str) -> Result<Option<TonView<'a>>> {
+        let view = self.payload()?;
+        if view.ton_type() != TonTypes::Object {
+            return Ok(None);
+        }
+        // 要素は value, key の順に並んでいるので後ろからは key, value の順に出てくる
+        let mut elements = view.elements_rev();
+        while let Some(k) = elements.next().transpose()? {
+            let value = elements.next().transpose()?.ok_or_else(|| Error::syntax(ErrorCode::InvalidType, view.pos))?;
+            if k.ton_type() == TonTypes::String && k.body() == key.as_bytes() {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Array の index で値を取る
+    ///
+    /// index: usize
+    ///
+    /// return: Result<Option<TonView>> // 範囲外か Array でない場合は None
+    pub fn get_index(&self, index: usize) -> Result<Option<TonView<'a>>> {
+        let view = self.payload()?;
+        if view.ton_type() != TonTypes::Array {
+            return Ok(None);
+        }
+        let len = view.len()?.unwrap_or(0);
+        match len.checked_sub(index + 1) {
+            Some(skip) => view.elements_rev().nth(skip).transpose(),
+            None => Ok(None),
+        }
+    }
+
+    /// JSON Pointer (RFC 6901) 形式のパスで値を取る
+    ///
+    /// pointer: &str
+    ///
+    /// return: Result<Option<TonView>>
+    pub fn pointer(&self, pointer: &str) -> Result<Option<TonView<'a>>> {
+        if pointer.is_empty() {
+            return Ok(Some(*self));
+        }
+        if !pointer.starts_with('/') {
+            return Ok(None);
+        }
+        let mut view = *self;
+        for token in pointer.split('/').skip(1).map(unescape_token) {
+            let next = match view.payload()?.ton_type() {
+                TonTypes::Object => view.get(&token)?,
+                TonTypes::Array => match parse_index(&token) {
+                    Some(index) => view.get_index(index)?,
+                    None => None,
+                },
+                _ => None,
+            };
+            match next {
+                Some(next) => view = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(view))
+    }
+
+    /// 文字列を借りて取る
+    ///
+    /// return: Option<&'a str> // String でない場合は None
+    #[inline]
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self.ton_type() {
+            TonTypes::String => core::str::from_utf8(self.body()).ok(),
+            _ => None,
+        }
+    }
+
+    /// バイト列を借りて取る
+    ///
+    /// return: Option<&'a [u8]> // Bytes でない場合は None
+    #[inline]
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self.ton_type() {
+            TonTypes::Bytes => Some(self.body()),
+            _ => None,
+        }
+    }
+
+    /// 値を T にデシリアライズする
+    ///
+    /// return: Result<T>
+    #[inline]
+    pub fn deserialize<T>(&self) -> Result<T>
+    where
+        T: Deserialize<'a>,
+    {
+        let mut deserializer = Deserializer::new(SliceRead::new(self.input), self.pos);
+        T::deserialize(&mut deserializer)
+    }
+
+    /// 値を Value に変換する
+    ///
+    /// return: Result<Value>
+    #[inline]
+    pub fn to_value(&self) -> Result<Value> {
+        self.deserialize()
+    }
+}
+
+/// container の子要素を後ろから順に返す
+struct RevElements<'a> {
+    input: &'a [u8],
+    start: usize,
+    cur: usize,
+}
+
+impl<'a> Iterator for RevElements<'a> {
+    type Item = Result<TonView<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.cur > self.start {
+            let view = match TonView::at(self.input, self.cur - 1) {
+                Ok(view) => view,
+                Err(e) => {
+                    self.cur = self.start;
+                    return Some(Err(e));
+                }
+            };
+            let size = view.header.header_size + view.header.body_size;
+            if size > self.cur - self.start {
+                self.cur = self.start;
+                return Some(Err(Error::syntax(ErrorCode::SizeOverflow, view.pos)));
+            }
+            self.cur -= size;
+            // PADDING は要素ではない
+            if view.ton_type() != TonTypes::Padding {
+                return Some(Ok(view));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod view_tests {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use super::*;
+    use crate::ton::{to_vec, WithMeta};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Child {
+        name: String,
+        size: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Document {
+        id: u64,
+        children: Vec<Child>,
+        blob: BTreeMap<String, Vec<u8>>,
+        title: String,
+    }
+
+    fn sample() -> Vec<u8> {
+        let children = (0..100).map(|i| Child { name: format!("child{}", i), size: i }).collect();
+        let blob = (0..50).map(|i| (format!("k{}", i), vec![i as u8; 64])).collect();
+        to_vec(&Document { id: 9, children, blob, title: "doc".to_string() }).unwrap()
+    }
+
+    #[test]
+    fn test_get() {
+        let bytes = sample();
+        let view = TonView::new(&bytes).unwrap();
+        assert_eq!(view.ton_type(), TonTypes::Object);
+        assert_eq!(view.len().unwrap(), Some(4));
+        assert_eq!(view.get("title").unwrap().unwrap().as_str(), Some("doc"));
+        assert_eq!(view.get("id").unwrap().unwrap().deserialize::<u64>().unwrap(), 9);
+        assert!(view.get("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_index_and_pointer() {
+        let bytes = sample();
+        let view = TonView::new(&bytes).unwrap();
+        let children = view.get("children").unwrap().unwrap();
+        assert_eq!(children.len().unwrap(), Some(100));
+        let child = children.get_index(42).unwrap().unwrap();
+        assert_eq!(child.deserialize::<Child>().unwrap(), Child { name: "child42".to_string(), size: 42 });
+        assert!(children.get_index(100).unwrap().is_none());
+
+        assert_eq!(view.pointer("/children/7/name").unwrap().unwrap().as_str(), Some("child7"));
+        assert!(view.pointer("/children/x").unwrap().is_none());
+
+        // 部分の値はそのまま単独の TON として読める
+        let raw = view.pointer("/children/3").unwrap().unwrap().raw();
+        assert_eq!(raw, to_vec(&Child { name: "child3".to_string(), size: 3 }).unwrap());
+    }
+
+    #[test]
+    fn test_meta() {
+        let bytes = to_vec(&WithMeta::new(Value::String("owner".to_string()), Child { name: "a".to_string(), size: 1 })).unwrap();
+        let view = TonView::new(&bytes).unwrap();
+        assert_eq!(view.ton_type(), TonTypes::Meta);
+        assert_eq!(view.meta().unwrap().unwrap().as_str(), Some("owner"));
+        assert_eq!(view.get("size").unwrap().unwrap().deserialize::<u32>().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(TonView::new(&[]).is_err());
+        // size フィールドが入力より大きい
+        assert!(TonView::new(&[b'a', 10, 0x14]).is_err());
+        // body が空の Meta
+        let view = TonView::new(&[0x00, 0x38]).unwrap();
+        assert!(view.payload().unwrap_err().is_syntax());
+        assert!(view.len().is_err());
+        // 前にある値を Meta の値として読まない
+        let view = TonView::new(&[0x00, 0x00, 0x38]).unwrap();
+        assert!(view.len().is_err());
+    }
+}